@@ -0,0 +1,553 @@
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{Error, Result};
+
+// Used when neither --config nor RPG_CONFIG is given (it's fine if it doesn't exist)
+const DEFAULT_CONFIG_PATH: &str = "rpg_server.toml";
+
+/// Command-line flags, taking precedence over the environment and the configuration file.
+#[derive(Debug, Parser, Default)]
+#[command(version, about = "REST API for the RPG economy")]
+pub struct Cli {
+    /// Path of the TOML configuration file
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Print the resolved configuration and exit
+    #[arg(long)]
+    pub print_config: bool,
+    #[arg(long)]
+    pub bind_address: Option<String>,
+    #[arg(long)]
+    pub drain_timeout_secs: Option<u64>,
+    #[arg(long)]
+    pub database_url: Option<String>,
+    #[arg(long)]
+    pub database_auth_token: Option<String>,
+    #[arg(long)]
+    pub replica_path: Option<String>,
+    /// Use a local database at the replica path instead of a Turso primary
+    #[arg(long)]
+    pub local_database: bool,
+    #[arg(long)]
+    pub replica_sync_interval_secs: Option<u64>,
+    #[arg(long)]
+    pub expiry_interval_secs: Option<u64>,
+    #[arg(long)]
+    pub auction_duration_secs: Option<u64>,
+    #[arg(long)]
+    pub auction_price: Option<u64>,
+    #[arg(long)]
+    pub auction_deposit_percent: Option<u64>,
+    #[arg(long)]
+    pub auction_tax_percent: Option<u64>,
+    #[arg(long)]
+    pub trade_duration_secs: Option<u64>,
+    #[arg(long)]
+    pub vendor_sell_back_percent: Option<u64>,
+    #[arg(long)]
+    pub warrior_inventory_slots: Option<u64>,
+    #[arg(long)]
+    pub mage_inventory_slots: Option<u64>,
+    #[arg(long)]
+    pub ranger_inventory_slots: Option<u64>,
+    #[arg(long)]
+    pub rate_limit_per_minute: Option<u64>,
+    #[arg(long)]
+    pub rate_limit_burst: Option<u64>,
+    #[arg(long)]
+    pub marketplace_rate_limit_per_minute: Option<u64>,
+    #[arg(long)]
+    pub marketplace_rate_limit_burst: Option<u64>,
+    #[arg(long)]
+    pub base_experience: Option<u64>,
+    #[arg(long)]
+    pub experience_growth_percent: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auctions: AuctionsConfig,
+    pub economy: EconomyConfig,
+    pub inventory: InventoryConfig,
+    pub rate_limit: RateLimitConfig,
+    pub progression: ProgressionConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    /// How long in-flight requests get to complete once a shutdown is requested
+    pub drain_timeout_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// URL of the Turso primary, required unless `local` is set
+    pub url: String,
+    pub auth_token: String,
+    /// Use a local database at `replica_path` instead of an embedded replica of the primary.
+    /// An in-memory database (`:memory:`) is always local.
+    pub local: bool,
+    /// Path of the embedded replica (or of the local database, `:memory:` is accepted)
+    pub replica_path: String,
+    pub sync_interval_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuctionsConfig {
+    /// Delay between two runs of the auction status updater
+    pub expiry_interval_secs: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct EconomyConfig {
+    /// How long a new auction stays active
+    pub auction_duration_secs: u64,
+    /// Price in gold of a new auction
+    pub auction_price: u64,
+    /// Share of the price the seller pays to list an auction, refunded on sale and lost otherwise
+    pub auction_deposit_percent: u64,
+    /// Share of the price withheld from the seller's proceeds
+    pub auction_tax_percent: u64,
+    /// How long a trade proposal waits for both characters to accept it
+    pub trade_duration_secs: u64,
+    /// Share of an item's base value paid by vendors buying it back
+    pub vendor_sell_back_percent: u64,
+}
+
+/// Inventory slots of each class, a stack of items taking a single slot.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct InventoryConfig {
+    pub warrior_slots: u64,
+    pub mage_slots: u64,
+    pub ranger_slots: u64,
+}
+
+/// Token buckets per client: `burst` requests at once, refilled at `*_per_minute`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub requests_per_minute: u64,
+    pub burst: u64,
+    /// Tighter limits for the marketplace writes (listing, cancelling and buying auctions)
+    pub marketplace_requests_per_minute: u64,
+    pub marketplace_burst: u64,
+}
+
+/// Experience curve: going from level 1 to 2 takes `base_experience`, and every level after
+/// that takes `experience_growth_percent` more than the previous one.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProgressionConfig {
+    pub base_experience: u64,
+    pub experience_growth_percent: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind_address: "0.0.0.0:3001".to_string(),
+            drain_timeout_secs: 30,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    pub fn is_local(&self) -> bool {
+        self.local || self.replica_path == ":memory:"
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            url: String::new(),
+            auth_token: String::new(),
+            local: false,
+            replica_path: "local.db".to_string(),
+            sync_interval_secs: 60,
+        }
+    }
+}
+
+impl Default for AuctionsConfig {
+    fn default() -> Self {
+        AuctionsConfig {
+            expiry_interval_secs: 30,
+        }
+    }
+}
+
+impl Default for EconomyConfig {
+    fn default() -> Self {
+        EconomyConfig {
+            auction_duration_secs: 60,
+            auction_price: 100,
+            auction_deposit_percent: 5,
+            auction_tax_percent: 5,
+            trade_duration_secs: 300,
+            vendor_sell_back_percent: 25,
+        }
+    }
+}
+
+impl Default for InventoryConfig {
+    fn default() -> Self {
+        InventoryConfig {
+            warrior_slots: 20,
+            mage_slots: 16,
+            ranger_slots: 24,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            requests_per_minute: 300,
+            burst: 60,
+            marketplace_requests_per_minute: 20,
+            marketplace_burst: 5,
+        }
+    }
+}
+
+impl Default for ProgressionConfig {
+    fn default() -> Self {
+        ProgressionConfig {
+            base_experience: 100,
+            experience_growth_percent: 50,
+        }
+    }
+}
+
+impl ProgressionConfig {
+    /// Experience needed to go from `level` to the next one.
+    pub fn experience_to_next_level(&self, level: u64) -> u64 {
+        let growth = 1.0 + self.experience_growth_percent as f64 / 100.0;
+        let needed = self.base_experience as f64 * growth.powi(level.saturating_sub(1) as i32);
+        needed.round().min(u64::MAX as f64) as u64
+    }
+}
+
+impl Config {
+    /// Resolves the configuration from the file, the environment and the flags (in that precedence).
+    /// It still has to be validated.
+    pub fn load(cli: &Cli) -> Result<Config> {
+        let path = cli
+            .config
+            .clone()
+            .or_else(|| std::env::var("RPG_CONFIG").ok().map(PathBuf::from));
+
+        let mut config = match path {
+            Some(path) => Config::from_file(&path)?,
+            None => {
+                let path = PathBuf::from(DEFAULT_CONFIG_PATH);
+                if path.exists() {
+                    Config::from_file(&path)?
+                } else {
+                    Config::default()
+                }
+            }
+        };
+
+        config.apply_env(|key| std::env::var(key).ok())?;
+        config.apply_cli(cli);
+        Ok(config)
+    }
+
+    fn from_file(path: &PathBuf) -> Result<Config> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| Error::InvalidConfig(format!("cannot read {}: {}", path.display(), e)))?;
+        toml::from_str(&content)
+            .map_err(|e| Error::InvalidConfig(format!("cannot parse {}: {}", path.display(), e)))
+    }
+
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let parse = |key: &str, value: String| {
+            value
+                .parse::<u64>()
+                .map_err(|_| Error::InvalidConfig(format!("{} must be a positive integer", key)))
+        };
+
+        if let Some(value) = var("RPG_BIND_ADDRESS") {
+            self.server.bind_address = value;
+        }
+        if let Some(value) = var("RPG_DRAIN_TIMEOUT_SECS") {
+            self.server.drain_timeout_secs = parse("RPG_DRAIN_TIMEOUT_SECS", value)?;
+        }
+        if let Some(value) = var("TURSO_DATABASE_URL") {
+            self.database.url = value;
+        }
+        if let Some(value) = var("TURSO_AUTH_TOKEN") {
+            self.database.auth_token = value;
+        }
+        if let Some(value) = var("RPG_DATABASE_LOCAL") {
+            self.database.local = value.parse().map_err(|_| {
+                Error::InvalidConfig("RPG_DATABASE_LOCAL must be true or false".to_string())
+            })?;
+        }
+        if let Some(value) = var("RPG_REPLICA_PATH") {
+            self.database.replica_path = value;
+        }
+        if let Some(value) = var("RPG_REPLICA_SYNC_INTERVAL_SECS") {
+            self.database.sync_interval_secs = parse("RPG_REPLICA_SYNC_INTERVAL_SECS", value)?;
+        }
+        if let Some(value) = var("RPG_EXPIRY_INTERVAL_SECS") {
+            self.auctions.expiry_interval_secs = parse("RPG_EXPIRY_INTERVAL_SECS", value)?;
+        }
+        if let Some(value) = var("RPG_AUCTION_DURATION_SECS") {
+            self.economy.auction_duration_secs = parse("RPG_AUCTION_DURATION_SECS", value)?;
+        }
+        if let Some(value) = var("RPG_AUCTION_PRICE") {
+            self.economy.auction_price = parse("RPG_AUCTION_PRICE", value)?;
+        }
+        if let Some(value) = var("RPG_AUCTION_DEPOSIT_PERCENT") {
+            self.economy.auction_deposit_percent = parse("RPG_AUCTION_DEPOSIT_PERCENT", value)?;
+        }
+        if let Some(value) = var("RPG_AUCTION_TAX_PERCENT") {
+            self.economy.auction_tax_percent = parse("RPG_AUCTION_TAX_PERCENT", value)?;
+        }
+        if let Some(value) = var("RPG_TRADE_DURATION_SECS") {
+            self.economy.trade_duration_secs = parse("RPG_TRADE_DURATION_SECS", value)?;
+        }
+        if let Some(value) = var("RPG_VENDOR_SELL_BACK_PERCENT") {
+            self.economy.vendor_sell_back_percent = parse("RPG_VENDOR_SELL_BACK_PERCENT", value)?;
+        }
+        if let Some(value) = var("RPG_WARRIOR_INVENTORY_SLOTS") {
+            self.inventory.warrior_slots = parse("RPG_WARRIOR_INVENTORY_SLOTS", value)?;
+        }
+        if let Some(value) = var("RPG_MAGE_INVENTORY_SLOTS") {
+            self.inventory.mage_slots = parse("RPG_MAGE_INVENTORY_SLOTS", value)?;
+        }
+        if let Some(value) = var("RPG_RANGER_INVENTORY_SLOTS") {
+            self.inventory.ranger_slots = parse("RPG_RANGER_INVENTORY_SLOTS", value)?;
+        }
+        if let Some(value) = var("RPG_RATE_LIMIT_PER_MINUTE") {
+            self.rate_limit.requests_per_minute = parse("RPG_RATE_LIMIT_PER_MINUTE", value)?;
+        }
+        if let Some(value) = var("RPG_RATE_LIMIT_BURST") {
+            self.rate_limit.burst = parse("RPG_RATE_LIMIT_BURST", value)?;
+        }
+        if let Some(value) = var("RPG_MARKETPLACE_RATE_LIMIT_PER_MINUTE") {
+            self.rate_limit.marketplace_requests_per_minute =
+                parse("RPG_MARKETPLACE_RATE_LIMIT_PER_MINUTE", value)?;
+        }
+        if let Some(value) = var("RPG_MARKETPLACE_RATE_LIMIT_BURST") {
+            self.rate_limit.marketplace_burst = parse("RPG_MARKETPLACE_RATE_LIMIT_BURST", value)?;
+        }
+        if let Some(value) = var("RPG_BASE_EXPERIENCE") {
+            self.progression.base_experience = parse("RPG_BASE_EXPERIENCE", value)?;
+        }
+        if let Some(value) = var("RPG_EXPERIENCE_GROWTH_PERCENT") {
+            self.progression.experience_growth_percent =
+                parse("RPG_EXPERIENCE_GROWTH_PERCENT", value)?;
+        }
+        Ok(())
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(value) = &cli.bind_address {
+            self.server.bind_address = value.clone();
+        }
+        if let Some(value) = cli.drain_timeout_secs {
+            self.server.drain_timeout_secs = value;
+        }
+        if let Some(value) = &cli.database_url {
+            self.database.url = value.clone();
+        }
+        if let Some(value) = &cli.database_auth_token {
+            self.database.auth_token = value.clone();
+        }
+        if cli.local_database {
+            self.database.local = true;
+        }
+        if let Some(value) = &cli.replica_path {
+            self.database.replica_path = value.clone();
+        }
+        if let Some(value) = cli.replica_sync_interval_secs {
+            self.database.sync_interval_secs = value;
+        }
+        if let Some(value) = cli.expiry_interval_secs {
+            self.auctions.expiry_interval_secs = value;
+        }
+        if let Some(value) = cli.auction_duration_secs {
+            self.economy.auction_duration_secs = value;
+        }
+        if let Some(value) = cli.auction_price {
+            self.economy.auction_price = value;
+        }
+        if let Some(value) = cli.auction_deposit_percent {
+            self.economy.auction_deposit_percent = value;
+        }
+        if let Some(value) = cli.auction_tax_percent {
+            self.economy.auction_tax_percent = value;
+        }
+        if let Some(value) = cli.trade_duration_secs {
+            self.economy.trade_duration_secs = value;
+        }
+        if let Some(value) = cli.vendor_sell_back_percent {
+            self.economy.vendor_sell_back_percent = value;
+        }
+        if let Some(value) = cli.warrior_inventory_slots {
+            self.inventory.warrior_slots = value;
+        }
+        if let Some(value) = cli.mage_inventory_slots {
+            self.inventory.mage_slots = value;
+        }
+        if let Some(value) = cli.ranger_inventory_slots {
+            self.inventory.ranger_slots = value;
+        }
+        if let Some(value) = cli.rate_limit_per_minute {
+            self.rate_limit.requests_per_minute = value;
+        }
+        if let Some(value) = cli.rate_limit_burst {
+            self.rate_limit.burst = value;
+        }
+        if let Some(value) = cli.marketplace_rate_limit_per_minute {
+            self.rate_limit.marketplace_requests_per_minute = value;
+        }
+        if let Some(value) = cli.marketplace_rate_limit_burst {
+            self.rate_limit.marketplace_burst = value;
+        }
+        if let Some(value) = cli.base_experience {
+            self.progression.base_experience = value;
+        }
+        if let Some(value) = cli.experience_growth_percent {
+            self.progression.experience_growth_percent = value;
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.server.bind_address.parse::<SocketAddr>().is_err() {
+            return Err(Error::InvalidConfig(format!(
+                "server.bind_address is not a valid socket address: {}",
+                self.server.bind_address
+            )));
+        }
+        if self.database.url.is_empty() && !self.database.is_local() {
+            return Err(Error::InvalidConfig(
+                "database.url (TURSO_DATABASE_URL) is not set, set database.local to use a local database instead".to_string(),
+            ));
+        }
+        if !self.database.url.is_empty() && self.database.local {
+            return Err(Error::InvalidConfig(
+                "database.url and database.local cannot both be set".to_string(),
+            ));
+        }
+        if !self.database.url.is_empty() && self.database.auth_token.is_empty() {
+            return Err(Error::InvalidConfig(
+                "database.auth_token (TURSO_AUTH_TOKEN) is not set".to_string(),
+            ));
+        }
+        if self.database.replica_path.is_empty() {
+            return Err(Error::InvalidConfig(
+                "database.replica_path is empty".to_string(),
+            ));
+        }
+        if self.database.sync_interval_secs == 0 {
+            return Err(Error::InvalidConfig(
+                "database.sync_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.auctions.expiry_interval_secs == 0 {
+            return Err(Error::InvalidConfig(
+                "auctions.expiry_interval_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.economy.auction_duration_secs == 0 {
+            return Err(Error::InvalidConfig(
+                "economy.auction_duration_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.economy.auction_price > 1_000_000_000 {
+            return Err(Error::InvalidConfig(
+                "economy.auction_price must be at most 1000000000".to_string(),
+            ));
+        }
+        if self.economy.auction_deposit_percent > 100 {
+            return Err(Error::InvalidConfig(
+                "economy.auction_deposit_percent must be at most 100".to_string(),
+            ));
+        }
+        if self.economy.auction_tax_percent > 100 {
+            return Err(Error::InvalidConfig(
+                "economy.auction_tax_percent must be at most 100".to_string(),
+            ));
+        }
+        if self.economy.trade_duration_secs == 0 {
+            return Err(Error::InvalidConfig(
+                "economy.trade_duration_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.economy.vendor_sell_back_percent > 100 {
+            return Err(Error::InvalidConfig(
+                "economy.vendor_sell_back_percent must be at most 100".to_string(),
+            ));
+        }
+        let slots = [
+            ("warrior_slots", self.inventory.warrior_slots),
+            ("mage_slots", self.inventory.mage_slots),
+            ("ranger_slots", self.inventory.ranger_slots),
+        ];
+        if let Some((name, _)) = slots.iter().find(|(_, value)| *value == 0) {
+            return Err(Error::InvalidConfig(format!(
+                "inventory.{} must be greater than 0",
+                name
+            )));
+        }
+        let rate_limits = [
+            ("requests_per_minute", self.rate_limit.requests_per_minute),
+            ("burst", self.rate_limit.burst),
+            (
+                "marketplace_requests_per_minute",
+                self.rate_limit.marketplace_requests_per_minute,
+            ),
+            ("marketplace_burst", self.rate_limit.marketplace_burst),
+        ];
+        if let Some((name, _)) = rate_limits.iter().find(|(_, value)| *value == 0) {
+            return Err(Error::InvalidConfig(format!(
+                "rate_limit.{} must be greater than 0",
+                name
+            )));
+        }
+        if self.progression.base_experience == 0 {
+            return Err(Error::InvalidConfig(
+                "progression.base_experience must be greater than 0".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// TOML rendering of the configuration, with the auth token redacted.
+    pub fn to_redacted_toml(&self) -> String {
+        let mut config = self.clone();
+        if !config.database.auth_token.is_empty() {
+            config.database.auth_token = "<redacted>".to_string();
+        }
+        toml::to_string_pretty(&config).unwrap_or_default()
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.server.drain_timeout_secs)
+    }
+
+    pub fn sync_interval(&self) -> Duration {
+        Duration::from_secs(self.database.sync_interval_secs)
+    }
+
+    pub fn expiry_interval(&self) -> Duration {
+        Duration::from_secs(self.auctions.expiry_interval_secs)
+    }
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Deserializer, de};
+use serde_json::Value;
+
+// JSON documents are stored as TEXT columns
+
+/// Reads a JSON document from a row (text) or from JSON (as is).
+/// Use with `#[serde(deserialize_with = "crate::db::json::deserialize")]`.
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: de::DeserializeOwned,
+{
+    match Value::deserialize(deserializer)? {
+        Value::String(text) => serde_json::from_str(&text).map_err(de::Error::custom),
+        value => serde_json::from_value(value).map_err(de::Error::custom),
+    }
+}
@@ -0,0 +1,361 @@
+use std::{sync::Arc, time::Instant};
+
+use libsql::{Builder, OpenFlags, ffi, params::IntoParams};
+use uuid::Uuid;
+
+use crate::{errors::Result, metrics::Metrics, replica::Replica};
+
+pub mod json;
+pub mod timestamp;
+
+// Every schema change gets appended here, its version being its position in the list (starting at 1).
+// A migration can hold several statements, they are applied in a single transaction.
+const MIGRATIONS: &[&str] = &[
+    // Creating characters DB if it doesn't already exist
+    "CREATE TABLE IF NOT EXISTS characters (
+        name TEXT PRIMARY KEY,
+        class TEXT NOT NULL CHECK (class IN ('warrior', 'mage', 'ranger')),
+        gold INTEGER NOT NULL CHECK (gold >= 0)
+        )",
+    // Creating items DB if it doesn't already exist
+    "CREATE TABLE IF NOT EXISTS items (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        name TEXT NOT NULL UNIQUE
+        )",
+    // Creating items_instances DB if it doesn't already exist
+    "CREATE TABLE IF NOT EXISTS items_instances (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        item_name TEXT NOT NULL,
+        item_id TEXT NOT NULL CHECK (length(item_id) = 36),
+        owner_name TEXT NOT NULL,
+        FOREIGN KEY (item_name) REFERENCES items(name) ON DELETE CASCADE ON UPDATE CASCADE,
+        FOREIGN KEY (item_id) REFERENCES items(id) ON DELETE CASCADE,
+        FOREIGN KEY (owner_name) REFERENCES characters(name) ON DELETE CASCADE
+        )",
+    // Creating auctions DB if it doesn't already exist
+    "CREATE TABLE IF NOT EXISTS auctions (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        auctioned_item_id TEXT NOT NULL CHECK (length(auctioned_item_id) = 36),
+        seller_name TEXT NOT NULL,
+        creation_date TEXT NOT NULL,
+        end_date TEXT NOT NULL,
+        price INTEGER NOT NULL CHECK (price >= 0),
+        status TEXT NOT NULL CHECK (status IN ('active', 'sold', 'expired')),
+        FOREIGN KEY (auctioned_item_id) REFERENCES items(id) ON DELETE CASCADE,
+        FOREIGN KEY (seller_name) REFERENCES characters(name) ON DELETE CASCADE
+        )",
+    // Storing auction dates as Unix milliseconds (they used to be text in various formats)
+    "CREATE TABLE auctions_new (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        auctioned_item_id TEXT NOT NULL CHECK (length(auctioned_item_id) = 36),
+        seller_name TEXT NOT NULL,
+        creation_date INTEGER NOT NULL,
+        end_date INTEGER NOT NULL,
+        price INTEGER NOT NULL CHECK (price >= 0),
+        status TEXT NOT NULL CHECK (status IN ('active', 'sold', 'expired')),
+        FOREIGN KEY (auctioned_item_id) REFERENCES items(id) ON DELETE CASCADE,
+        FOREIGN KEY (seller_name) REFERENCES characters(name) ON DELETE CASCADE
+        );
+    INSERT INTO auctions_new (id, auctioned_item_id, seller_name, creation_date, end_date, price, status)
+        SELECT id, auctioned_item_id, seller_name,
+        CAST(ROUND((julianday(creation_date) - 2440587.5) * 86400000) AS INTEGER),
+        CAST(ROUND((julianday(end_date) - 2440587.5) * 86400000) AS INTEGER),
+        price, status
+        FROM auctions;
+    DROP TABLE auctions;
+    ALTER TABLE auctions_new RENAME TO auctions;
+    CREATE INDEX auctions_status_end_date ON auctions (status, end_date);",
+    // Stackable items (a max stack of 1 means the item is unique) and inventory quantities
+    "ALTER TABLE items ADD COLUMN max_stack INTEGER NOT NULL DEFAULT 1 CHECK (max_stack >= 1);
+    ALTER TABLE items_instances ADD COLUMN quantity INTEGER NOT NULL DEFAULT 1 CHECK (quantity >= 1);",
+    // Describing items (existing ones become common materials anyone can use)
+    "ALTER TABLE items ADD COLUMN kind TEXT NOT NULL DEFAULT 'material'
+        CHECK (kind IN ('weapon', 'armor', 'consumable', 'material'));
+    ALTER TABLE items ADD COLUMN rarity TEXT NOT NULL DEFAULT 'common'
+        CHECK (rarity IN ('common', 'uncommon', 'rare', 'epic', 'legendary'));
+    ALTER TABLE items ADD COLUMN level_requirement INTEGER NOT NULL DEFAULT 1 CHECK (level_requirement >= 1);
+    ALTER TABLE items ADD COLUMN class_restriction TEXT
+        CHECK (class_restriction IS NULL OR class_restriction IN ('warrior', 'mage', 'ranger'));
+    ALTER TABLE items ADD COLUMN base_value INTEGER NOT NULL DEFAULT 0 CHECK (base_value >= 0);
+    ALTER TABLE items ADD COLUMN description TEXT NOT NULL DEFAULT '';
+    ALTER TABLE items ADD COLUMN attributes TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(attributes));
+    CREATE INDEX items_kind_rarity ON items (kind, rarity);",
+    // Instances carry their own properties (rolled from the item's ranges when looted),
+    // auctions keep the listed instance and a copy of its properties
+    "ALTER TABLE items ADD COLUMN max_durability INTEGER CHECK (max_durability IS NULL OR max_durability >= 1);
+    ALTER TABLE items ADD COLUMN stat_ranges TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(stat_ranges));
+    ALTER TABLE items ADD COLUMN binds_on_loot INTEGER NOT NULL DEFAULT 0 CHECK (binds_on_loot IN (0, 1));
+    ALTER TABLE items_instances ADD COLUMN durability INTEGER CHECK (durability IS NULL OR durability >= 0);
+    ALTER TABLE items_instances ADD COLUMN enchantment INTEGER NOT NULL DEFAULT 0 CHECK (enchantment >= 0);
+    ALTER TABLE items_instances ADD COLUMN stats TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(stats));
+    ALTER TABLE items_instances ADD COLUMN soulbound INTEGER NOT NULL DEFAULT 0 CHECK (soulbound IN (0, 1));
+    ALTER TABLE auctions ADD COLUMN auctioned_instance_id TEXT CHECK (auctioned_instance_id IS NULL OR length(auctioned_instance_id) = 36);
+    ALTER TABLE auctions ADD COLUMN durability INTEGER;
+    ALTER TABLE auctions ADD COLUMN enchantment INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE auctions ADD COLUMN stats TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(stats));",
+    // Character progression (experience counts towards the next level)
+    "ALTER TABLE characters ADD COLUMN level INTEGER NOT NULL DEFAULT 1 CHECK (level >= 1);
+    ALTER TABLE characters ADD COLUMN experience INTEGER NOT NULL DEFAULT 0 CHECK (experience >= 0);",
+    // Equipped instances (they don't take an inventory slot), one per slot and character
+    "ALTER TABLE items_instances ADD COLUMN equipped_slot TEXT
+        CHECK (equipped_slot IS NULL OR equipped_slot IN ('head', 'chest', 'hands', 'legs', 'feet', 'main_hand', 'off_hand'));
+    CREATE UNIQUE INDEX items_instances_owner_equipped_slot ON items_instances (owner_name, equipped_slot)
+        WHERE equipped_slot IS NOT NULL;",
+    // Trades between two characters (offered item instances are JSON arrays of ids)
+    "CREATE TABLE trades (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        proposer_name TEXT NOT NULL,
+        recipient_name TEXT NOT NULL,
+        proposer_items TEXT NOT NULL CHECK (json_valid(proposer_items)),
+        recipient_items TEXT NOT NULL CHECK (json_valid(recipient_items)),
+        proposer_gold INTEGER NOT NULL CHECK (proposer_gold >= 0),
+        recipient_gold INTEGER NOT NULL CHECK (recipient_gold >= 0),
+        proposer_accepted INTEGER NOT NULL CHECK (proposer_accepted IN (0, 1)),
+        recipient_accepted INTEGER NOT NULL CHECK (recipient_accepted IN (0, 1)),
+        status TEXT NOT NULL CHECK (status IN ('pending', 'completed', 'cancelled', 'expired')),
+        creation_date INTEGER NOT NULL,
+        end_date INTEGER NOT NULL,
+        FOREIGN KEY (proposer_name) REFERENCES characters(name) ON DELETE CASCADE ON UPDATE CASCADE,
+        FOREIGN KEY (recipient_name) REFERENCES characters(name) ON DELETE CASCADE ON UPDATE CASCADE
+        );
+    CREATE INDEX trades_status_end_date ON trades (status, end_date);",
+    // Loot tables (entries are a JSON array)
+    "CREATE TABLE loot_tables (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        name TEXT NOT NULL,
+        rolls INTEGER NOT NULL CHECK (rolls >= 0),
+        nothing_weight INTEGER NOT NULL DEFAULT 0 CHECK (nothing_weight >= 0),
+        min_gold INTEGER NOT NULL DEFAULT 0 CHECK (min_gold >= 0),
+        max_gold INTEGER NOT NULL DEFAULT 0 CHECK (max_gold >= min_gold),
+        entries TEXT NOT NULL DEFAULT '[]' CHECK (json_valid(entries))
+        );",
+    // Crafting recipes (inputs are a JSON array of item ids and quantities)
+    "CREATE TABLE recipes (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        name TEXT NOT NULL,
+        inputs TEXT NOT NULL CHECK (json_valid(inputs)),
+        gold_cost INTEGER NOT NULL DEFAULT 0 CHECK (gold_cost >= 0),
+        output_item_id TEXT NOT NULL,
+        output_quantity INTEGER NOT NULL DEFAULT 1 CHECK (output_quantity >= 1),
+        FOREIGN KEY (output_item_id) REFERENCES items(id) ON DELETE CASCADE
+        );",
+    // NPC vendors and the items they sell (a NULL stock is unlimited)
+    "CREATE TABLE vendors (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        name TEXT NOT NULL
+        );
+    CREATE TABLE vendor_offers (
+        vendor_id TEXT NOT NULL,
+        item_id TEXT NOT NULL,
+        price INTEGER NOT NULL CHECK (price >= 0),
+        stock INTEGER CHECK (stock IS NULL OR stock >= 0),
+        max_stock INTEGER CHECK (max_stock IS NULL OR max_stock >= 1),
+        restock_interval_secs INTEGER CHECK (restock_interval_secs IS NULL OR restock_interval_secs >= 1),
+        restock_date INTEGER,
+        PRIMARY KEY (vendor_id, item_id),
+        FOREIGN KEY (vendor_id) REFERENCES vendors(id) ON DELETE CASCADE,
+        FOREIGN KEY (item_id) REFERENCES items(id) ON DELETE CASCADE
+        );
+    CREATE INDEX vendor_offers_restock_date ON vendor_offers (restock_date);",
+    // Auction deposits, and a ledger of every marketplace fee
+    "ALTER TABLE auctions ADD COLUMN deposit INTEGER NOT NULL DEFAULT 0 CHECK (deposit >= 0);
+    CREATE TABLE fees (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        kind TEXT NOT NULL CHECK (kind IN ('listing_deposit', 'deposit_refund', 'sales_tax')),
+        character_name TEXT NOT NULL,
+        auction_id TEXT NOT NULL,
+        amount INTEGER NOT NULL CHECK (amount > 0),
+        date INTEGER NOT NULL
+        );
+    CREATE INDEX fees_kind_date ON fees (kind, date);",
+    // Gold entering (positive amounts) and leaving (negative amounts) the economy, and when
+    // auctions sold
+    "CREATE TABLE gold_ledger (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        source TEXT NOT NULL CHECK (source IN ('character_creation', 'admin_adjustment', 'character_deletion', 'loot', 'vendor_purchase', 'vendor_sale', 'crafting')),
+        character_name TEXT NOT NULL,
+        amount INTEGER NOT NULL CHECK (amount != 0),
+        date INTEGER NOT NULL
+        );
+    CREATE INDEX gold_ledger_date ON gold_ledger (date);
+    ALTER TABLE auctions ADD COLUMN sale_date INTEGER;",
+    // Buy orders, holding the gold of their unfilled quantity until filled or cancelled
+    "CREATE TABLE buy_orders (
+        id TEXT PRIMARY KEY CHECK (length(id) = 36),
+        character_name TEXT NOT NULL,
+        item_id TEXT NOT NULL,
+        max_price INTEGER NOT NULL CHECK (max_price >= 1),
+        quantity INTEGER NOT NULL CHECK (quantity >= 1),
+        filled INTEGER NOT NULL DEFAULT 0 CHECK (filled >= 0 AND filled <= quantity),
+        reserved INTEGER NOT NULL CHECK (reserved >= 0),
+        status TEXT NOT NULL CHECK (status IN ('open', 'filled', 'cancelled')),
+        creation_date INTEGER NOT NULL,
+        FOREIGN KEY (character_name) REFERENCES characters(name) ON DELETE CASCADE,
+        FOREIGN KEY (item_id) REFERENCES items(id) ON DELETE CASCADE
+        );
+    CREATE INDEX buy_orders_item_status_price ON buy_orders (item_id, status, max_price);",
+    // Gold held for pending obligations, apart from the gold a character can spend. The open buy
+    // orders' gold moves there, and pending trades have to be accepted again, which now reserves
+    // the gold they offer
+    "ALTER TABLE characters ADD COLUMN reserved_gold INTEGER NOT NULL DEFAULT 0 CHECK (reserved_gold >= 0);
+    UPDATE characters SET reserved_gold = (
+        SELECT COALESCE(SUM(reserved), 0) FROM buy_orders
+        WHERE buy_orders.character_name = characters.name AND status = 'open'
+        );
+    UPDATE trades SET proposer_accepted = 0, recipient_accepted = 0 WHERE status = 'pending';",
+];
+
+/// Opens a local database file. `:memory:` opens a named in-memory database instead, so that
+/// every connection to it sees the same data.
+pub async fn open_local(path: &str) -> libsql::Result<libsql::Database> {
+    if path != ":memory:" {
+        return Builder::new_local(path).build().await;
+    }
+    Builder::new_local(format!("file:/{}?vfs=memdb", Uuid::new_v4()))
+        .flags(OpenFlags::default() | OpenFlags::from_bits_retain(ffi::SQLITE_OPEN_URI))
+        .build()
+        .await
+}
+
+/// Thin wrapper around `libsql::Connection` recording the latency of every statement.
+/// Each transaction runs on a connection of its own, so the statements of other requests never
+/// end up inside it.
+#[derive(Clone)]
+pub struct Connection {
+    inner: libsql::Connection,
+    replica: Arc<Replica>,
+    metrics: Arc<Metrics>,
+}
+
+impl Connection {
+    pub async fn new(replica: Arc<Replica>, metrics: Arc<Metrics>) -> Result<Self> {
+        Ok(Connection {
+            inner: replica.connect().await?,
+            replica,
+            metrics,
+        })
+    }
+
+    pub async fn query(&self, sql: &str, params: impl IntoParams) -> libsql::Result<libsql::Rows> {
+        let start = Instant::now();
+        let result = self.inner.query(sql, params).await;
+        self.metrics.observe_db_query(sql, start.elapsed());
+        result
+    }
+
+    pub async fn execute(&self, sql: &str, params: impl IntoParams) -> libsql::Result<u64> {
+        let start = Instant::now();
+        let result = self.inner.execute(sql, params).await;
+        self.metrics.observe_db_query(sql, start.elapsed());
+        result
+    }
+
+    pub async fn execute_batch(&self, sql: &str) -> libsql::Result<()> {
+        let start = Instant::now();
+        let result = self.inner.execute_batch(sql).await;
+        self.metrics.observe_db_query(sql, start.elapsed());
+        result.map(|_| ())
+    }
+
+    /// Starts a transaction on a new connection, rolled back if dropped without being committed.
+    pub async fn transaction(&self) -> Result<Transaction<'_>> {
+        let inner = self.replica.connect().await?.transaction().await?;
+        Ok(Transaction {
+            inner,
+            metrics: &self.metrics,
+        })
+    }
+}
+
+pub struct Transaction<'a> {
+    inner: libsql::Transaction,
+    metrics: &'a Metrics,
+}
+
+impl Transaction<'_> {
+    pub async fn query(&self, sql: &str, params: impl IntoParams) -> libsql::Result<libsql::Rows> {
+        let start = Instant::now();
+        let result = self.inner.query(sql, params).await;
+        self.metrics.observe_db_query(sql, start.elapsed());
+        result
+    }
+
+    pub async fn execute(&self, sql: &str, params: impl IntoParams) -> libsql::Result<u64> {
+        let start = Instant::now();
+        let result = self.inner.execute(sql, params).await;
+        self.metrics.observe_db_query(sql, start.elapsed());
+        result
+    }
+
+    pub async fn execute_batch(&self, sql: &str) -> libsql::Result<()> {
+        let start = Instant::now();
+        let result = self.inner.execute_batch(sql).await;
+        self.metrics.observe_db_query(sql, start.elapsed());
+        result.map(|_| ())
+    }
+
+    pub async fn commit(self) -> libsql::Result<()> {
+        self.inner.commit().await
+    }
+}
+
+// =========================Migrations=========================
+async fn get_schema_version_libsql_query(conn: &Connection) -> Result<usize> {
+    let mut query = conn
+        .query(
+            "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+            (),
+        )
+        .await?;
+    let version = match query.next().await? {
+        Some(row) => row.get::<u64>(0)? as usize,
+        None => 0,
+    };
+    Ok(version)
+}
+
+/// Applies every migration that hasn't been recorded in `schema_migrations` yet.
+pub async fn run_migrations(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+        version INTEGER PRIMARY KEY,
+        applied_at TEXT NOT NULL
+        )",
+        (),
+    )
+    .await?;
+
+    let version = get_schema_version_libsql_query(conn).await?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = conn.transaction().await?;
+        transaction.execute_batch(migration).await?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, applied_at) VALUES (?1, datetime('now'))",
+                [index as u64 + 1],
+            )
+            .await?;
+        transaction.commit().await?;
+    }
+    Ok(())
+}
+
+/// Number of migrations that still have to be applied to the database.
+pub async fn pending_migrations(conn: &Connection) -> Result<usize> {
+    let mut query = conn
+        .query(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'schema_migrations'",
+            (),
+        )
+        .await?;
+    let table_exists = match query.next().await? {
+        Some(row) => row.get::<u64>(0)? > 0,
+        None => false,
+    };
+    if !table_exists {
+        return Ok(MIGRATIONS.len());
+    }
+
+    let version = get_schema_version_libsql_query(conn).await?;
+    Ok(MIGRATIONS.len().saturating_sub(version))
+}
@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, de};
+
+// Timestamps are stored as Unix milliseconds (INTEGER) so they compare numerically and can be indexed
+
+pub fn to_millis(date: &DateTime<Utc>) -> i64 {
+    date.timestamp_millis()
+}
+
+/// `date` with the precision it has once stored (milliseconds).
+pub fn truncate(date: DateTime<Utc>) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(date.timestamp_millis()).unwrap_or(date)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Millis(i64),
+    Rfc3339(DateTime<Utc>),
+}
+
+impl Repr {
+    fn into_date<E: de::Error>(self) -> Result<DateTime<Utc>, E> {
+        match self {
+            Repr::Millis(millis) => DateTime::from_timestamp_millis(millis)
+                .ok_or_else(|| de::Error::custom(format!("timestamp out of range: {}", millis))),
+            Repr::Rfc3339(date) => Ok(date),
+        }
+    }
+}
+
+/// Reads a timestamp from a row (Unix milliseconds) or from JSON (RFC 3339).
+/// Use with `#[serde(deserialize_with = "crate::db::timestamp::deserialize")]`.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Repr::deserialize(deserializer)?.into_date()
+}
+
+/// Same as `deserialize`, for nullable timestamps.
+pub fn deserialize_option<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<Repr>::deserialize(deserializer)?
+        .map(Repr::into_date)
+        .transpose()
+}
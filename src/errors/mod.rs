@@ -1,7 +1,7 @@
 use std::fmt;
 
 use axum::{
-    http::StatusCode,
+    http::{StatusCode, header::RETRY_AFTER},
     response::{IntoResponse, Response},
 };
 use serde::de;
@@ -11,6 +11,7 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     EmptyName,
+    InvalidItem(&'static str),
     Libsql(libsql::Error),
     De(de::value::Error),
     CharacterNotFound,
@@ -19,7 +20,34 @@ pub enum Error {
     AuctionNotFound,
     AuctionNotActive,
     InsufficientGold,
+    GoldOverflow,
     IncorrectBuyer,
+    InventoryFull,
+    ItemSoulbound,
+    ItemEquipped,
+    CannotEquip(&'static str),
+    EquipmentSlotEmpty,
+    InvalidTrade(&'static str),
+    TradeNotFound,
+    TradeNotPending,
+    NotTradeParty,
+    InvalidLootTable(&'static str),
+    LootTableNotFound,
+    InvalidRecipe(&'static str),
+    RecipeNotFound,
+    /// Name and missing quantity of each ingredient
+    MissingIngredients(Vec<(String, u64)>),
+    InvalidVendor(&'static str),
+    VendorNotFound,
+    NotSoldByVendor,
+    OutOfStock,
+    InvalidBuyOrder(&'static str),
+    BuyOrderNotFound,
+    BuyOrderNotOpen,
+    NotBuyOrderOwner,
+    InvalidConfig(String),
+    /// Seconds to wait before retrying
+    RateLimited(u64),
 }
 
 // To allow conversion (for await? for libsql)
@@ -41,7 +69,8 @@ impl IntoResponse for Error {
         println!("{}", self);
         let (status, body) = match self {
             Error::EmptyName => (StatusCode::BAD_REQUEST, "The name provided is empty."),
-            Error::Libsql(_) | Error::De(_) => (
+            Error::InvalidItem(reason) => (StatusCode::BAD_REQUEST, reason),
+            Error::Libsql(_) | Error::De(_) | Error::InvalidConfig(_) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 StatusCode::INTERNAL_SERVER_ERROR
                     .canonical_reason()
@@ -58,10 +87,62 @@ impl IntoResponse for Error {
                 StatusCode::FORBIDDEN,
                 "The buyer does not have enough gold.",
             ),
+            Error::GoldOverflow => (StatusCode::BAD_REQUEST, "This amount of gold is too large."),
             Error::IncorrectBuyer => (
                 StatusCode::FORBIDDEN,
                 "The buyer cannot be the auction's owner.",
             ),
+            Error::InventoryFull => (StatusCode::FORBIDDEN, "The inventory is full."),
+            Error::ItemSoulbound => (StatusCode::FORBIDDEN, "This item is soulbound."),
+            Error::ItemEquipped => (StatusCode::FORBIDDEN, "This item is equipped."),
+            Error::CannotEquip(reason) => (StatusCode::FORBIDDEN, reason),
+            Error::EquipmentSlotEmpty => {
+                (StatusCode::NOT_FOUND, "Nothing is equipped in this slot.")
+            }
+            Error::InvalidTrade(reason) => (StatusCode::BAD_REQUEST, reason),
+            Error::TradeNotFound => (StatusCode::NOT_FOUND, "This trade does not exist."),
+            Error::TradeNotPending => (StatusCode::NOT_FOUND, "This trade is not pending."),
+            Error::NotTradeParty => (
+                StatusCode::FORBIDDEN,
+                "This character is not part of the trade.",
+            ),
+            Error::InvalidLootTable(reason) => (StatusCode::BAD_REQUEST, reason),
+            Error::LootTableNotFound => (StatusCode::NOT_FOUND, "This loot table does not exist."),
+            Error::InvalidRecipe(reason) => (StatusCode::BAD_REQUEST, reason),
+            Error::RecipeNotFound => (StatusCode::NOT_FOUND, "This recipe does not exist."),
+            Error::InvalidVendor(reason) => (StatusCode::BAD_REQUEST, reason),
+            Error::VendorNotFound => (StatusCode::NOT_FOUND, "This vendor does not exist."),
+            Error::NotSoldByVendor => (
+                StatusCode::NOT_FOUND,
+                "This vendor does not sell this item.",
+            ),
+            Error::OutOfStock => (StatusCode::FORBIDDEN, "The vendor is out of stock."),
+            Error::InvalidBuyOrder(reason) => (StatusCode::BAD_REQUEST, reason),
+            Error::BuyOrderNotFound => (StatusCode::NOT_FOUND, "This buy order does not exist."),
+            Error::BuyOrderNotOpen => (StatusCode::NOT_FOUND, "This buy order is not open."),
+            Error::NotBuyOrderOwner => (
+                StatusCode::FORBIDDEN,
+                "This character did not place the buy order.",
+            ),
+            Error::MissingIngredients(missing) => {
+                let missing: Vec<String> = missing
+                    .iter()
+                    .map(|(name, quantity)| format!("{} x {}", quantity, name))
+                    .collect();
+                return (
+                    StatusCode::FORBIDDEN,
+                    format!("Missing ingredients : {}.", missing.join(", ")),
+                )
+                    .into_response();
+            }
+            Error::RateLimited(retry_after) => {
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(RETRY_AFTER, retry_after.to_string())],
+                    "Too many requests, slow down.",
+                )
+                    .into_response();
+            }
         };
         (status, body).into_response()
     }
@@ -73,6 +154,9 @@ impl fmt::Display for Error {
             Error::EmptyName => {
                 write!(f, "Empty name")
             }
+            Error::InvalidItem(reason) => {
+                write!(f, "Invalid item : {}", reason)
+            }
             Error::Libsql(e) => {
                 write!(f, "Libsql : {}", e)
             }
@@ -97,9 +181,84 @@ impl fmt::Display for Error {
             Error::InsufficientGold => {
                 write!(f, "Not enough gold")
             }
+            Error::GoldOverflow => {
+                write!(f, "Gold overflow")
+            }
             Error::IncorrectBuyer => {
                 write!(f, "Incorrect buyer")
             }
+            Error::InventoryFull => {
+                write!(f, "Inventory full")
+            }
+            Error::ItemSoulbound => {
+                write!(f, "Item soulbound")
+            }
+            Error::ItemEquipped => {
+                write!(f, "Item equipped")
+            }
+            Error::CannotEquip(reason) => {
+                write!(f, "Cannot equip : {}", reason)
+            }
+            Error::EquipmentSlotEmpty => {
+                write!(f, "Equipment slot empty")
+            }
+            Error::InvalidTrade(reason) => {
+                write!(f, "Invalid trade : {}", reason)
+            }
+            Error::TradeNotFound => {
+                write!(f, "Trade not found")
+            }
+            Error::TradeNotPending => {
+                write!(f, "Trade not pending")
+            }
+            Error::NotTradeParty => {
+                write!(f, "Not a trade party")
+            }
+            Error::InvalidLootTable(reason) => {
+                write!(f, "Invalid loot table : {}", reason)
+            }
+            Error::LootTableNotFound => {
+                write!(f, "Loot table not found")
+            }
+            Error::InvalidRecipe(reason) => {
+                write!(f, "Invalid recipe : {}", reason)
+            }
+            Error::RecipeNotFound => {
+                write!(f, "Recipe not found")
+            }
+            Error::InvalidVendor(reason) => {
+                write!(f, "Invalid vendor operation : {}", reason)
+            }
+            Error::VendorNotFound => {
+                write!(f, "Vendor not found")
+            }
+            Error::NotSoldByVendor => {
+                write!(f, "Not sold by vendor")
+            }
+            Error::OutOfStock => {
+                write!(f, "Out of stock")
+            }
+            Error::InvalidBuyOrder(reason) => {
+                write!(f, "Invalid buy order : {}", reason)
+            }
+            Error::BuyOrderNotFound => {
+                write!(f, "Buy order not found")
+            }
+            Error::BuyOrderNotOpen => {
+                write!(f, "Buy order not open")
+            }
+            Error::NotBuyOrderOwner => {
+                write!(f, "Not the buy order owner")
+            }
+            Error::MissingIngredients(missing) => {
+                write!(f, "Missing ingredients : {:?}", missing)
+            }
+            Error::InvalidConfig(e) => {
+                write!(f, "Invalid configuration : {}", e)
+            }
+            Error::RateLimited(retry_after) => {
+                write!(f, "Rate limited for {}s", retry_after)
+            }
         }
     }
 }
@@ -1,32 +1,57 @@
+use std::{collections::BTreeMap, fmt};
+
 use crate::{
     AppState,
+    config::Config,
+    db::{Transaction, json, timestamp},
     errors::{Error, Result},
-    handlers::characters::{Character, get_character_libsql_query},
+    handlers::{
+        characters::{
+            Character, add_to_inventory_libsql_query, credit_gold_libsql_query,
+            get_character_libsql_query, spend_gold_libsql_query,
+        },
+        fees::{FeeKind, record_fee_libsql_query},
+        items::{Item, ItemInstance, get_item_libsql_query},
+    },
     into_rows,
 };
 use axum::{
-    extract::{self, Extension, Json, Path, Query, Request, State},
+    extract::{Extension, Json, Path, Query, Request, State},
     http::StatusCode,
     middleware::Next,
     response::{IntoResponse, Response},
 };
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
 use libsql::de::from_row;
+use rand::{SeedableRng, rngs::StdRng};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Auction {
     pub id: Uuid,
     pub auctioned_item_id: Uuid,
+    /// Null for auctions listed before instances had their own properties
+    pub auctioned_instance_id: Option<Uuid>,
     pub seller_name: String,
+    #[serde(deserialize_with = "timestamp::deserialize")]
     pub creation_date: DateTime<Utc>,
+    #[serde(deserialize_with = "timestamp::deserialize")]
     pub end_date: DateTime<Utc>,
     pub price: u64,
     pub status: AuctionStatus,
+    // The listed instance's properties when the auction was created
+    pub durability: Option<u64>,
+    pub enchantment: u64,
+    #[serde(deserialize_with = "json::deserialize")]
+    pub stats: BTreeMap<String, i64>,
+    /// Paid by the seller when listing, refunded on sale
+    #[serde(default)]
+    pub deposit: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum AuctionStatus {
     Active,
@@ -34,17 +59,18 @@ pub enum AuctionStatus {
     Expired,
 }
 
-impl AuctionStatus {
-    pub fn to_string(&self) -> String {
+impl fmt::Display for AuctionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            AuctionStatus::Active => "active".to_string(),
-            AuctionStatus::Sold => "sold".to_string(),
-            AuctionStatus::Expired => "expired".to_string(),
+            AuctionStatus::Active => write!(f, "active"),
+            AuctionStatus::Sold => write!(f, "sold"),
+            AuctionStatus::Expired => write!(f, "expired"),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct AuctionStatusQuery {
     status: Option<AuctionStatus>,
 }
@@ -85,7 +111,151 @@ pub async fn get_auction_libsql_query(
         .transpose()
 }
 
+/// Marks every active auction whose end date is before `now` as expired.
+pub async fn expire_auctions_libsql_query(state: &AppState, now: DateTime<Utc>) -> Result<u64> {
+    let expired = state
+        .conn
+        .execute(
+            "UPDATE auctions SET status = 'expired' WHERE end_date < ?1 AND status = 'active'",
+            [timestamp::to_millis(&now)],
+        )
+        .await?;
+    Ok(expired)
+}
+
+/// Sells `auction` to `buyer` at `price`, which the buyer already paid: the seller gets the price
+/// minus the sales tax plus the listing deposit, and hands over one of the auctioned item.
+pub async fn settle_auction_libsql_query(
+    transaction: &Transaction<'_>,
+    config: &Config,
+    auction: &Auction,
+    item: &Item,
+    buyer: &Character,
+    price: u64,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    // Only one buyer can win the auction, and only before it ends
+    let sold = transaction
+        .execute(
+            "UPDATE auctions SET status = 'sold', sale_date = ?2, price = ?3
+            WHERE id = ?1 AND status = 'active' AND end_date >= ?2",
+            (auction.id.to_string(), timestamp::to_millis(&now), price),
+        )
+        .await?;
+    if sold == 0 {
+        return Err(Error::AuctionNotActive);
+    }
+
+    // The seller gets the price minus the sales tax, and the listing deposit back
+    let Some(tax) = price
+        .checked_mul(config.economy.auction_tax_percent)
+        .map(|tax| tax / 100)
+    else {
+        return Err(Error::GoldOverflow);
+    };
+    let Some(proceeds) = (price - tax).checked_add(auction.deposit) else {
+        return Err(Error::GoldOverflow);
+    };
+    credit_gold_libsql_query(transaction, &auction.seller_name, proceeds).await?;
+    record_fee_libsql_query(
+        transaction,
+        FeeKind::SalesTax,
+        &auction.seller_name,
+        &auction.id,
+        tax,
+        now,
+    )
+    .await?;
+    record_fee_libsql_query(
+        transaction,
+        FeeKind::DepositRefund,
+        &auction.seller_name,
+        &auction.id,
+        auction.deposit,
+        now,
+    )
+    .await?;
+
+    // The seller hands over one of the auctioned item, unique ones being the listed instance
+    let listed_instance_id = auction
+        .auctioned_instance_id
+        .filter(|_| item.max_stack == 1)
+        .map(|id| id.to_string());
+    let mut query = transaction
+        .query(
+            "SELECT * FROM items_instances WHERE owner_name = ?1 AND item_id = ?2 AND (?3 IS NULL OR id = ?3)
+            ORDER BY equipped_slot IS NOT NULL, quantity LIMIT 1",
+            (
+                auction.seller_name.as_str(),
+                auction.auctioned_item_id.to_string(),
+                listed_instance_id,
+            ),
+        )
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::ItemInstanceNotFound);
+    };
+    let instance: ItemInstance = from_row(&row)?;
+    if instance.soulbound {
+        return Err(Error::ItemSoulbound);
+    }
+    if instance.equipped_slot.is_some() {
+        return Err(Error::ItemEquipped);
+    }
+
+    let slots = buyer.inventory_slots(&config.inventory);
+    if item.max_stack == 1 {
+        // Unique items change hands as they are, if the buyer has a free slot
+        let transferred = transaction
+            .execute(
+                "UPDATE items_instances SET owner_name = ?1 WHERE id = ?2
+                AND (SELECT COUNT(*) FROM items_instances WHERE owner_name = ?1 AND equipped_slot IS NULL) < ?3",
+                (buyer.name.as_str(), instance.id.to_string(), slots),
+            )
+            .await?;
+        if transferred == 0 {
+            return Err(Error::InventoryFull);
+        }
+    } else {
+        // One of the seller's stack goes to the buyer's stacks
+        if instance.quantity > 1 {
+            transaction
+                .execute(
+                    "UPDATE items_instances SET quantity = quantity - 1 WHERE id = ?1",
+                    [instance.id.to_string()],
+                )
+                .await?;
+        } else {
+            transaction
+                .execute(
+                    "DELETE FROM items_instances WHERE id = ?1",
+                    [instance.id.to_string()],
+                )
+                .await?;
+        }
+        let mut rng = StdRng::from_entropy();
+        if add_to_inventory_libsql_query(transaction, buyer, item, slots, &mut rng)
+            .await?
+            .is_none()
+        {
+            return Err(Error::InventoryFull);
+        }
+    }
+
+    Ok(())
+}
+
 // =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/auctions",
+    tag = "auctions",
+    params(AuctionStatusQuery),
+    responses(
+        (status = 200, description = "Every auction, optionally filtered by status", body = Vec<Auction>),
+        (status = 400, description = "The status is not one of active, sold or expired.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn get_auctions(
     state: State<AppState>,
     query_status: Query<AuctionStatusQuery>,
@@ -100,21 +270,44 @@ pub async fn get_auctions(
     // (header, serde_json::to_string(&characters).unwrap())
 }
 
+#[utoipa::path(
+    get,
+    path = "/auctions/{id}",
+    tag = "auctions",
+    params(("id" = Uuid, Path, description = "Id of the auction")),
+    responses(
+        (status = 200, description = "The auction", body = Auction),
+        (status = 404, description = "This auction does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn get_auction(Extension(auction): Extension<Auction>) -> Json<Auction> {
     Json(auction)
 }
 
+#[utoipa::path(
+    post,
+    path = "/auctions/{id}/purchase",
+    tag = "auctions",
+    request_body = Character,
+    params(("id" = Uuid, Path, description = "Id of the auction")),
+    responses(
+        (status = 201, description = "The sold auction, the seller getting the price minus the sales tax plus the deposit", body = Auction),
+        (status = 403, description = "The buyer does not have enough gold, is the auction's owner, or has a full inventory, or the item is soulbound or equipped.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The auction or the buyer does not exist, or the auction is not active.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn post_auction(
     state: State<AppState>,
     Extension(mut auction): Extension<Auction>,
     Json(buyer): Json<Character>,
-) -> Result<(StatusCode, Option<Json<Auction>>)> {
+) -> Result<(StatusCode, Json<Auction>)> {
     let Some(buyer) = get_character_libsql_query(&state, &buyer.name).await? else {
         return Err(Error::CharacterNotFound);
     };
 
-    let now = Utc::now();
-    if now > auction.end_date || auction.status.to_string() == "sold" {
+    let now = state.clock.now();
+    if now > auction.end_date || !matches!(auction.status, AuctionStatus::Active) {
         return Err(Error::AuctionNotActive);
     }
 
@@ -126,28 +319,28 @@ pub async fn post_auction(
         return Err(Error::IncorrectBuyer);
     }
 
-    let Some(owner) = get_character_libsql_query(&state, &auction.seller_name).await? else {
-        return Err(Error::CharacterNotFound);
+    let Some(item) = get_item_libsql_query(&state, &auction.auctioned_item_id).await? else {
+        return Err(Error::ItemNotFound);
     };
 
-    state
-        .conn
-        .execute(
-            "UPDATE characters SET gold = ?1 WHERE name = ?2",
-            (buyer.gold - auction.price, buyer.name.as_str()),
-        )
-        .await?;
-    state
-        .conn
-        .execute(
-            "UPDATE characters SET gold = ?1 WHERE name = ?2",
-            (owner.gold + auction.price, auction.seller_name.as_str()),
-        )
-        .await?;
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+    spend_gold_libsql_query(&transaction, &buyer.name, auction.price).await?;
+    settle_auction_libsql_query(
+        &transaction,
+        &state.config,
+        &auction,
+        &item,
+        &buyer,
+        auction.price,
+        now,
+    )
+    .await?;
+    transaction.commit().await?;
 
     auction.status = AuctionStatus::Sold;
 
-    Ok((StatusCode::CREATED, Some(Json(auction))))
+    Ok((StatusCode::CREATED, Json(auction)))
 }
 
 // =========================Middleware=========================
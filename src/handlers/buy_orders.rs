@@ -0,0 +1,505 @@
+use std::fmt;
+
+use crate::{
+    AppState,
+    config::Config,
+    db::{Transaction, timestamp},
+    errors::{Error, Result},
+    handlers::{
+        auctions::{Auction, settle_auction_libsql_query},
+        characters::{
+            get_character_in_transaction_libsql_query, get_character_libsql_query,
+            release_gold_libsql_query, reserve_gold_libsql_query, settle_gold_libsql_query,
+        },
+        items::{Item, get_item_libsql_query},
+    },
+    into_rows,
+};
+use axum::{
+    extract::{Extension, Json, Path, Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use libsql::de::from_row;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// A standing offer to buy an item at up to `max_price` each, filled by matching auctions.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct BuyOrder {
+    pub id: Uuid,
+    pub character_name: String,
+    pub item_id: Uuid,
+    pub max_price: u64,
+    pub quantity: u64,
+    pub filled: u64,
+    /// Part of the character's reserved gold held for the unfilled quantity
+    pub reserved: u64,
+    pub status: BuyOrderStatus,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub creation_date: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BuyOrderStatus {
+    Open,
+    Filled,
+    Cancelled,
+}
+
+impl fmt::Display for BuyOrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuyOrderStatus::Open => write!(f, "open"),
+            BuyOrderStatus::Filled => write!(f, "filled"),
+            BuyOrderStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct NewBuyOrder {
+    character_name: String,
+    item_id: Uuid,
+    max_price: u64,
+    #[serde(default = "default_quantity")]
+    quantity: u64,
+}
+
+fn default_quantity() -> u64 {
+    1
+}
+
+/// The character cancelling the buy order.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct BuyOrderCancel {
+    character_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BuyOrderQuery {
+    status: Option<BuyOrderStatus>,
+    /// Only the orders placed by this character
+    character: Option<String>,
+    item_id: Option<Uuid>,
+}
+
+/// Every open buy order (bids) and active auction (asks) of an item, grouped by price.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct OrderBook {
+    pub item_id: Uuid,
+    pub item_name: String,
+    /// Highest price first
+    pub bids: Vec<PriceLevel>,
+    /// Lowest price first
+    pub asks: Vec<PriceLevel>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct PriceLevel {
+    pub price: u64,
+    pub quantity: u64,
+}
+
+const MAX_QUANTITY: u64 = 1000;
+const MAX_PRICE: u64 = 1_000_000_000;
+
+// =========================Query functions=========================
+async fn get_buy_orders_libsql_query(
+    state: &State<AppState>,
+    filter: &BuyOrderQuery,
+) -> Result<Vec<BuyOrder>> {
+    let query = state
+        .conn
+        .query(
+            "SELECT * FROM buy_orders WHERE (?1 IS NULL OR status = ?1)
+            AND (?2 IS NULL OR character_name = ?2) AND (?3 IS NULL OR item_id = ?3)
+            ORDER BY creation_date",
+            (
+                filter.status.map(|status| status.to_string()),
+                filter.character.clone(),
+                filter.item_id.map(|id| id.to_string()),
+            ),
+        )
+        .await?;
+    let orders: Vec<BuyOrder> = into_rows(query).await?;
+    Ok(orders)
+}
+
+pub async fn get_buy_order_libsql_query(
+    state: &State<AppState>,
+    id: &Uuid,
+) -> Result<Option<BuyOrder>> {
+    let mut query = state
+        .conn
+        .query("SELECT * FROM buy_orders WHERE id = ?1", [id.to_string()])
+        .await?;
+    let order = query.next().await?; //None if there are no more rows
+    order
+        .map(|row| from_row(&row).map_err(Error::from))
+        .transpose()
+}
+
+async fn get_buy_order_in_transaction_libsql_query(
+    transaction: &Transaction<'_>,
+    id: &Uuid,
+) -> Result<BuyOrder> {
+    let mut query = transaction
+        .query("SELECT * FROM buy_orders WHERE id = ?1", [id.to_string()])
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::BuyOrderNotFound);
+    };
+    Ok(from_row(&row)?)
+}
+
+async fn get_order_book_libsql_query(
+    state: &State<AppState>,
+    item_id: &Uuid,
+    now: DateTime<Utc>,
+) -> Result<(Vec<PriceLevel>, Vec<PriceLevel>)> {
+    let bids = state
+        .conn
+        .query(
+            "SELECT max_price AS price, SUM(quantity - filled) AS quantity FROM buy_orders
+            WHERE item_id = ?1 AND status = 'open' GROUP BY max_price ORDER BY max_price DESC",
+            [item_id.to_string()],
+        )
+        .await?;
+    let bids: Vec<PriceLevel> = into_rows(bids).await?;
+    let asks = state
+        .conn
+        .query(
+            "SELECT price, COUNT(*) AS quantity FROM auctions
+            WHERE auctioned_item_id = ?1 AND status = 'active' AND end_date >= ?2
+            GROUP BY price ORDER BY price",
+            (item_id.to_string(), timestamp::to_millis(&now)),
+        )
+        .await?;
+    let asks: Vec<PriceLevel> = into_rows(asks).await?;
+    Ok((bids, asks))
+}
+
+/// Fills one unit of `order` with `auction` at `price`, the difference with the order's maximum
+/// price going back to the buyer. Returns false, leaving everything in place, when this pair
+/// cannot be matched (the buyer's inventory is full, or the seller no longer has the item).
+async fn fill_buy_order_libsql_query(
+    transaction: &Transaction<'_>,
+    config: &Config,
+    order: &BuyOrder,
+    auction: &Auction,
+    item: &Item,
+    price: u64,
+    now: DateTime<Utc>,
+) -> Result<bool> {
+    transaction.execute("SAVEPOINT fill", ()).await?;
+    let result = async {
+        let filled = transaction
+            .execute(
+                "UPDATE buy_orders SET filled = filled + 1, reserved = reserved - max_price,
+                status = CASE WHEN filled + 1 = quantity THEN 'filled' ELSE 'open' END
+                WHERE id = ?1 AND status = 'open'",
+                [order.id.to_string()],
+            )
+            .await?;
+        if filled == 0 {
+            return Err(Error::BuyOrderNotOpen);
+        }
+        settle_gold_libsql_query(transaction, &order.character_name, price).await?;
+        release_gold_libsql_query(transaction, &order.character_name, order.max_price - price)
+            .await?;
+        let buyer =
+            get_character_in_transaction_libsql_query(transaction, &order.character_name).await?;
+        settle_auction_libsql_query(transaction, config, auction, item, &buyer, price, now).await
+    }
+    .await;
+
+    match result {
+        Ok(()) => {
+            transaction.execute("RELEASE fill", ()).await?;
+            Ok(true)
+        }
+        Err(
+            Error::InventoryFull
+            | Error::ItemInstanceNotFound
+            | Error::ItemSoulbound
+            | Error::ItemEquipped
+            | Error::AuctionNotActive,
+        ) => {
+            transaction.execute("ROLLBACK TO fill", ()).await?;
+            transaction.execute("RELEASE fill", ()).await?;
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Sells a newly listed auction to the highest open buy order willing to pay its price, at that
+/// order's price. Returns the filled order, if any.
+pub async fn match_buy_orders_libsql_query(
+    transaction: &Transaction<'_>,
+    config: &Config,
+    auction: &Auction,
+    item: &Item,
+    now: DateTime<Utc>,
+) -> Result<Option<BuyOrder>> {
+    let query = transaction
+        .query(
+            "SELECT * FROM buy_orders WHERE item_id = ?1 AND status = 'open' AND max_price >= ?2
+            AND character_name != ?3 ORDER BY max_price DESC, creation_date, id",
+            (
+                auction.auctioned_item_id.to_string(),
+                auction.price,
+                auction.seller_name.as_str(),
+            ),
+        )
+        .await?;
+    let orders: Vec<BuyOrder> = into_rows(query).await?;
+
+    for order in orders {
+        if fill_buy_order_libsql_query(
+            transaction,
+            config,
+            &order,
+            auction,
+            item,
+            order.max_price,
+            now,
+        )
+        .await?
+        {
+            return Ok(Some(order));
+        }
+    }
+    Ok(None)
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/buy-orders",
+    tag = "orders",
+    params(BuyOrderQuery),
+    responses(
+        (status = 200, description = "Every buy order, oldest first, optionally filtered by status, character and item", body = Vec<BuyOrder>),
+        (status = 400, description = "The status is not one of open, filled or cancelled.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_buy_orders(
+    state: State<AppState>,
+    Query(filter): Query<BuyOrderQuery>,
+) -> Result<Json<Vec<BuyOrder>>> {
+    let orders = get_buy_orders_libsql_query(&state, &filter).await?;
+    Ok(Json(orders))
+}
+
+#[utoipa::path(
+    post,
+    path = "/buy-orders",
+    tag = "orders",
+    request_body = NewBuyOrder,
+    responses(
+        (status = 201, description = "The placed buy order, already filled by the cheapest matching auctions", body = BuyOrder),
+        (status = 400, description = "The price or the quantity is not valid.", body = String, content_type = "text/plain"),
+        (status = 403, description = "The character does not have the gold for the whole order.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The character or the item does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_buy_order(
+    state: State<AppState>,
+    Json(new_order): Json<NewBuyOrder>,
+) -> Result<(StatusCode, Json<BuyOrder>)> {
+    if new_order.max_price == 0 || new_order.max_price > MAX_PRICE {
+        return Err(Error::InvalidBuyOrder(
+            "The maximum price must be between 1 and 1000000000.",
+        ));
+    }
+    if new_order.quantity == 0 || new_order.quantity > MAX_QUANTITY {
+        return Err(Error::InvalidBuyOrder(
+            "The quantity must be between 1 and 1000.",
+        ));
+    }
+    let reserved = new_order.max_price * new_order.quantity;
+    let Some(character) = get_character_libsql_query(&state, &new_order.character_name).await?
+    else {
+        return Err(Error::CharacterNotFound);
+    };
+    let Some(item) = get_item_libsql_query(&state, &new_order.item_id).await? else {
+        return Err(Error::ItemNotFound);
+    };
+
+    let now = state.clock.now();
+    let order = BuyOrder {
+        id: Uuid::new_v4(),
+        character_name: character.name,
+        item_id: item.id,
+        max_price: new_order.max_price,
+        quantity: new_order.quantity,
+        filled: 0,
+        reserved,
+        status: BuyOrderStatus::Open,
+        creation_date: timestamp::truncate(now),
+    };
+
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+    reserve_gold_libsql_query(&transaction, &order.character_name, reserved).await?;
+    transaction
+        .execute(
+            "INSERT INTO buy_orders (id, character_name, item_id, max_price, quantity, filled, reserved, status, creation_date)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            libsql::params![
+                order.id.to_string(),
+                order.character_name.as_str(),
+                order.item_id.to_string(),
+                order.max_price,
+                order.quantity,
+                order.filled,
+                order.reserved,
+                order.status.to_string(),
+                timestamp::to_millis(&order.creation_date),
+            ],
+        )
+        .await?;
+
+    // The cheapest auctions already listed fill the order at their own price
+    let query = transaction
+        .query(
+            "SELECT * FROM auctions WHERE auctioned_item_id = ?1 AND status = 'active' AND end_date >= ?2
+            AND price <= ?3 AND seller_name != ?4 ORDER BY price, creation_date, id",
+            (
+                order.item_id.to_string(),
+                timestamp::to_millis(&now),
+                order.max_price,
+                order.character_name.as_str(),
+            ),
+        )
+        .await?;
+    let auctions: Vec<Auction> = into_rows(query).await?;
+    let mut remaining = order.quantity;
+    for auction in auctions {
+        if remaining == 0 {
+            break;
+        }
+        if fill_buy_order_libsql_query(
+            &transaction,
+            &state.config,
+            &order,
+            &auction,
+            &item,
+            auction.price,
+            now,
+        )
+        .await?
+        {
+            remaining -= 1;
+        }
+    }
+    let order = get_buy_order_in_transaction_libsql_query(&transaction, &order.id).await?;
+    transaction.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(order)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/buy-orders/{id}",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Id of the buy order")),
+    responses(
+        (status = 200, description = "The buy order", body = BuyOrder),
+        (status = 404, description = "This buy order does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_buy_order(Extension(order): Extension<BuyOrder>) -> Json<BuyOrder> {
+    Json(order)
+}
+
+#[utoipa::path(
+    post,
+    path = "/buy-orders/{id}/cancel",
+    tag = "orders",
+    request_body = BuyOrderCancel,
+    params(("id" = Uuid, Path, description = "Id of the buy order")),
+    responses(
+        (status = 200, description = "The cancelled buy order, its reserved gold given back", body = BuyOrder),
+        (status = 403, description = "The character did not place the buy order.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The buy order does not exist or is not open.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_buy_order_cancel(
+    state: State<AppState>,
+    Extension(order): Extension<BuyOrder>,
+    Json(cancel): Json<BuyOrderCancel>,
+) -> Result<Json<BuyOrder>> {
+    if cancel.character_name != order.character_name {
+        return Err(Error::NotBuyOrderOwner);
+    }
+
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+    let mut order = get_buy_order_in_transaction_libsql_query(&transaction, &order.id).await?;
+    let cancelled = transaction
+        .execute(
+            "UPDATE buy_orders SET status = 'cancelled', reserved = 0 WHERE id = ?1 AND status = 'open'",
+            [order.id.to_string()],
+        )
+        .await?;
+    if cancelled == 0 {
+        return Err(Error::BuyOrderNotOpen);
+    }
+    release_gold_libsql_query(&transaction, &order.character_name, order.reserved).await?;
+    transaction.commit().await?;
+
+    order.status = BuyOrderStatus::Cancelled;
+    order.reserved = 0;
+    Ok(Json(order))
+}
+
+#[utoipa::path(
+    get,
+    path = "/items/{id}/orderbook",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Id of the item")),
+    responses(
+        (status = 200, description = "The open buy orders and active auctions of the item, grouped by price", body = OrderBook),
+        (status = 404, description = "This item does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_item_orderbook(
+    state: State<AppState>,
+    Extension(item): Extension<Item>,
+) -> Result<Json<OrderBook>> {
+    let (bids, asks) = get_order_book_libsql_query(&state, &item.id, state.clock.now()).await?;
+    Ok(Json(OrderBook {
+        item_id: item.id,
+        item_name: item.name,
+        bids,
+        asks,
+    }))
+}
+
+// =========================Middleware=========================
+pub async fn middleware_buy_order_exists(
+    state: State<AppState>,
+    Path(id): Path<Uuid>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let response = get_buy_order_libsql_query(&state, &id).await;
+    match response {
+        Ok(None) => Error::BuyOrderNotFound.into_response(),
+        Err(e) => e.into_response(),
+        Ok(Some(order)) => {
+            request.extensions_mut().insert(order);
+            next.run(request).await
+        }
+    }
+}
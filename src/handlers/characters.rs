@@ -1,49 +1,182 @@
+use std::{collections::BTreeMap, fmt};
+
 use crate::{
     AppState,
+    config::{InventoryConfig, ProgressionConfig},
+    db::{Transaction, timestamp},
     errors::{Error, Result},
     handlers::{
         auctions::{Auction, AuctionStatus, get_auction_libsql_query},
-        items::{Item, ItemInstance, get_item_libsql_query},
+        buy_orders::match_buy_orders_libsql_query,
+        equipment::{get_equipment_libsql_query, total_stats},
+        fees::{FeeKind, record_fee_libsql_query},
+        items::{Item, ItemInstance, MAX_LEVEL, get_item_libsql_query},
     },
     into_rows,
+    reports::{GoldSource, record_gold_flow_libsql_query},
 };
 use axum::{
-    extract::{Extension, Json, Path, Request, State},
+    extract::{Extension, Json, Path, Query, Request, State},
     http::StatusCode,
     middleware::Next,
     response::{IntoResponse, Response},
 };
-use chrono::{TimeDelta, Utc};
+use chrono::TimeDelta;
 use libsql::de::from_row;
+use rand::{SeedableRng, rngs::StdRng};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Character {
     pub name: String,
     class: Class,
+    /// Gold the character can spend
     pub gold: u64,
+    /// Gold held for pending buy orders and trades, new characters start with none
+    #[serde(default)]
+    pub reserved_gold: u64,
+    /// New characters start at level 1, whatever is sent
+    #[serde(default = "default_level")]
+    pub level: u64,
+    /// Experience gained since the last level up
+    #[serde(default)]
+    pub experience: u64,
+}
+
+fn default_level() -> u64 {
+    1
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct CharacterGoldUpdate {
     gold: u64,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CharacterQuery {
+    include: Option<CharacterInclude>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ToSchema)]
 #[serde(rename_all = "lowercase")]
-enum Class {
+pub enum CharacterInclude {
+    Equipment,
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct CharacterDetails {
+    #[serde(flatten)]
+    character: Character,
+    /// Equipped item instances, with `include=equipment`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    equipment: Option<Vec<ItemInstance>>,
+    /// Base stats plus the equipment's stats, with `include=equipment`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_stats: Option<BTreeMap<String, i64>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct ExperienceGain {
+    amount: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+pub struct Stats {
+    pub strength: u64,
+    pub intellect: u64,
+    pub agility: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct CharacterStats {
+    pub level: u64,
+    pub experience: u64,
+    /// Null at the max level
+    pub experience_to_next_level: Option<u64>,
+    pub stats: Stats,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Class {
     Warrior,
     Mage,
     Ranger,
 }
 
-impl Class {
-    fn to_string(&self) -> String {
+impl Character {
+    pub fn inventory_slots(&self, config: &InventoryConfig) -> u64 {
+        match self.class {
+            Class::Warrior => config.warrior_slots,
+            Class::Mage => config.mage_slots,
+            Class::Ranger => config.ranger_slots,
+        }
+    }
+
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    /// Stats given by the class and the level: the class' main stat starts at 10 and gains 3 per
+    /// level, the others start at 5 and gain 1.
+    pub fn base_stats(&self) -> Stats {
+        let levels = self.level - 1;
+        let (main, other) = (10 + 3 * levels, 5 + levels);
+        match self.class {
+            Class::Warrior => Stats {
+                strength: main,
+                intellect: other,
+                agility: other,
+            },
+            Class::Mage => Stats {
+                strength: other,
+                intellect: main,
+                agility: other,
+            },
+            Class::Ranger => Stats {
+                strength: other,
+                intellect: other,
+                agility: main,
+            },
+        }
+    }
+
+    /// Adds experience, levelling up as many times as it allows (nothing is kept at the max level).
+    pub fn gain_experience(&mut self, amount: u64, config: &ProgressionConfig) {
+        self.experience = self.experience.saturating_add(amount);
+        while self.level < MAX_LEVEL {
+            let needed = config.experience_to_next_level(self.level);
+            if self.experience < needed {
+                break;
+            }
+            self.experience -= needed;
+            self.level += 1;
+        }
+        if self.level == MAX_LEVEL {
+            self.experience = 0;
+        }
+    }
+
+    pub fn stats(&self, config: &ProgressionConfig) -> CharacterStats {
+        CharacterStats {
+            level: self.level,
+            experience: self.experience,
+            experience_to_next_level: (self.level < MAX_LEVEL)
+                .then(|| config.experience_to_next_level(self.level)),
+            stats: self.base_stats(),
+        }
+    }
+}
+
+impl fmt::Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Class::Warrior => "warrior".to_string(),
-            Class::Mage => "mage".to_string(),
-            Class::Ranger => "ranger".to_string(),
+            Class::Warrior => write!(f, "warrior"),
+            Class::Mage => write!(f, "mage"),
+            Class::Ranger => write!(f, "ranger"),
         }
     }
 }
@@ -87,7 +220,7 @@ async fn get_character_items_libsql_query(
     Ok(items)
 }
 
-async fn get_character_item_libsql_query(
+pub async fn get_character_item_libsql_query(
     state: &State<AppState>,
     name: &String,
     id: &Uuid,
@@ -104,6 +237,183 @@ async fn get_character_item_libsql_query(
         .transpose()
 }
 
+/// Takes `amount` from the gold `name` can spend.
+pub async fn spend_gold_libsql_query(
+    transaction: &Transaction<'_>,
+    name: &str,
+    amount: u64,
+) -> Result<()> {
+    let debited = transaction
+        .execute(
+            "UPDATE characters SET gold = gold - ?1 WHERE name = ?2 AND gold >= ?1",
+            (amount, name),
+        )
+        .await?;
+    if debited == 0 {
+        return Err(Error::InsufficientGold);
+    }
+    Ok(())
+}
+
+/// Adds `amount` to the gold `name` can spend.
+pub async fn credit_gold_libsql_query(
+    transaction: &Transaction<'_>,
+    name: &str,
+    amount: u64,
+) -> Result<()> {
+    let credited = transaction
+        .execute(
+            "UPDATE characters SET gold = gold + ?1 WHERE name = ?2",
+            (amount, name),
+        )
+        .await?;
+    if credited == 0 {
+        return Err(Error::CharacterNotFound);
+    }
+    Ok(())
+}
+
+/// Moves `amount` from the gold `name` can spend to its reserved gold.
+pub async fn reserve_gold_libsql_query(
+    transaction: &Transaction<'_>,
+    name: &str,
+    amount: u64,
+) -> Result<()> {
+    let reserved = transaction
+        .execute(
+            "UPDATE characters SET gold = gold - ?1, reserved_gold = reserved_gold + ?1
+            WHERE name = ?2 AND gold >= ?1",
+            (amount, name),
+        )
+        .await?;
+    if reserved == 0 {
+        return Err(Error::InsufficientGold);
+    }
+    Ok(())
+}
+
+/// Gives `amount` of the reserved gold of `name` back to the gold it can spend.
+pub async fn release_gold_libsql_query(
+    transaction: &Transaction<'_>,
+    name: &str,
+    amount: u64,
+) -> Result<()> {
+    let released = transaction
+        .execute(
+            "UPDATE characters SET gold = gold + ?1, reserved_gold = reserved_gold - ?1
+            WHERE name = ?2 AND reserved_gold >= ?1",
+            (amount, name),
+        )
+        .await?;
+    if released == 0 {
+        return Err(Error::InsufficientGold);
+    }
+    Ok(())
+}
+
+/// Pays `amount` out of the reserved gold of `name`, the obligation it was held for being met.
+pub async fn settle_gold_libsql_query(
+    transaction: &Transaction<'_>,
+    name: &str,
+    amount: u64,
+) -> Result<()> {
+    let settled = transaction
+        .execute(
+            "UPDATE characters SET reserved_gold = reserved_gold - ?1 WHERE name = ?2 AND reserved_gold >= ?1",
+            (amount, name),
+        )
+        .await?;
+    if settled == 0 {
+        return Err(Error::InsufficientGold);
+    }
+    Ok(())
+}
+
+/// Gives one `item` to `owner`, on a stack that isn't full yet or in a free slot.
+/// A new instance gets its stats rolled with `rng`.
+/// Returns the instance holding it, or None when the inventory is full.
+pub async fn add_to_inventory_libsql_query(
+    transaction: &Transaction<'_>,
+    owner: &Character,
+    item: &Item,
+    slots: u64,
+    rng: &mut StdRng,
+) -> Result<Option<ItemInstance>> {
+    if item.max_stack > 1 {
+        let mut query = transaction
+            .query(
+                "UPDATE items_instances SET quantity = quantity + 1 WHERE id = (
+                SELECT id FROM items_instances WHERE owner_name = ?1 AND item_id = ?2 AND quantity < ?3 LIMIT 1
+                ) RETURNING *",
+                (owner.name.as_str(), item.id.to_string(), item.max_stack),
+            )
+            .await?;
+        if let Some(row) = query.next().await? {
+            return Ok(Some(from_row(&row)?));
+        }
+    }
+
+    // A new stack takes a slot, if there's one left
+    let mut query = transaction
+        .query(
+            "INSERT INTO items_instances (id, item_name, item_id, owner_name, quantity, durability, stats, soulbound)
+            SELECT ?1, ?2, ?3, ?4, 1, ?6, ?7, ?8
+            WHERE (SELECT COUNT(*) FROM items_instances WHERE owner_name = ?4 AND equipped_slot IS NULL) < ?5
+            RETURNING *",
+            libsql::params![
+                Uuid::new_v4().to_string(),
+                item.name.as_str(),
+                item.id.to_string(),
+                owner.name.as_str(),
+                slots,
+                item.max_durability.map(|durability| durability as i64),
+                serde_json::to_string(&item.roll_stats(rng)).unwrap(),
+                item.binds_on_loot,
+            ],
+        )
+        .await?;
+    let instance = query.next().await?; //None if the inventory is full
+    instance
+        .map(|row| from_row(&row).map_err(Error::from))
+        .transpose()
+}
+
+/// Reads a character from inside a transaction, seeing its uncommitted changes.
+pub async fn get_character_in_transaction_libsql_query(
+    transaction: &Transaction<'_>,
+    name: &str,
+) -> Result<Character> {
+    let mut query = transaction
+        .query("SELECT * FROM characters WHERE name = ?1", [name])
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::CharacterNotFound);
+    };
+    Ok(from_row(&row)?)
+}
+
+/// Errors if more instances than `slots` are left in the owner's bags.
+pub async fn check_inventory_libsql_query(
+    transaction: &Transaction<'_>,
+    owner: &Character,
+    slots: u64,
+) -> Result<()> {
+    let mut query = transaction
+        .query(
+            "SELECT COUNT(*) FROM items_instances WHERE owner_name = ?1 AND equipped_slot IS NULL",
+            [owner.name.as_str()],
+        )
+        .await?;
+    let used = match query.next().await? {
+        Some(row) => row.get::<u64>(0)?,
+        None => 0,
+    };
+    if used > slots {
+        return Err(Error::InventoryFull);
+    }
+    Ok(())
+}
+
 async fn get_character_auctions_libsql_query(
     state: &State<AppState>,
     name: &String,
@@ -120,6 +430,14 @@ async fn get_character_auctions_libsql_query(
 }
 
 // =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/characters",
+    tag = "characters",
+    responses(
+        (status = 200, description = "Every character", body = Vec<Character>),
+    )
+)]
 pub async fn get_characters(state: State<AppState>) -> Result<Json<Vec<Character>>> {
     let characters = get_characters_libsql_query(&state).await?;
     Ok(Json(characters))
@@ -131,15 +449,29 @@ pub async fn get_characters(state: State<AppState>) -> Result<Json<Vec<Character
     // (header, serde_json::to_string(&characters).unwrap())
 }
 
+#[utoipa::path(
+    post,
+    path = "/characters",
+    tag = "characters",
+    request_body = Character,
+    responses(
+        (status = 201, description = "The created character", body = Character),
+        (status = 400, description = "The name provided is empty.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn post_character(
     state: State<AppState>,
-    Json(character): Json<Character>,
+    Json(mut character): Json<Character>,
 ) -> Result<(StatusCode, Json<Character>)> {
     if character.name.is_empty() {
         return Err(Error::EmptyName);
     }
-    state
-        .conn
+    character.level = 1;
+    character.experience = 0;
+    character.reserved_gold = 0;
+    let transaction = state.conn.transaction().await?;
+    transaction
         .execute(
             "INSERT INTO characters (name, class, gold) VALUES (?1, ?2, ?3)",
             (
@@ -149,46 +481,213 @@ pub async fn post_character(
             ),
         )
         .await?;
+    record_gold_flow_libsql_query(
+        &transaction,
+        GoldSource::CharacterCreation,
+        &character.name,
+        character.gold as i64,
+        state.clock.now(),
+    )
+    .await?;
+    transaction.commit().await?;
 
     Ok((StatusCode::CREATED, Json(character)))
 }
 
-pub async fn get_character(Extension(character): Extension<Character>) -> Json<Character> {
-    Json(character)
+#[utoipa::path(
+    get,
+    path = "/characters/{name}",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character"), CharacterQuery),
+    responses(
+        (status = 200, description = "The character (with its equipment and total stats when asked)", body = CharacterDetails),
+        (status = 400, description = "The include parameter is not valid.", body = String, content_type = "text/plain"),
+        (status = 404, description = "This character does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_character(
+    state: State<AppState>,
+    Extension(character): Extension<Character>,
+    Query(query): Query<CharacterQuery>,
+) -> Result<Json<CharacterDetails>> {
+    let (equipment, total_stats) = match query.include {
+        Some(CharacterInclude::Equipment) => {
+            let equipment = get_equipment_libsql_query(&state, &character.name).await?;
+            let stats = total_stats(&character, &equipment);
+            (Some(equipment), Some(stats))
+        }
+        None => (None, None),
+    };
+    Ok(Json(CharacterDetails {
+        character,
+        equipment,
+        total_stats,
+    }))
 }
 
+#[utoipa::path(
+    patch,
+    path = "/characters/{name}",
+    tag = "characters",
+    request_body = CharacterGoldUpdate,
+    params(("name" = String, Path, description = "Name of the character")),
+    responses(
+        (status = 200, description = "The updated character", body = Character),
+        (status = 404, description = "This character does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn patch_character(
     state: State<AppState>,
     Extension(mut character): Extension<Character>,
     Json(character_patch): Json<CharacterGoldUpdate>,
 ) -> Result<Json<Character>> {
-    character.gold = character_patch.gold;
-    state
-        .conn
+    let transaction = state.conn.transaction().await?;
+    // The gold is read again so that the recorded adjustment is exact
+    let mut query = transaction
+        .query(
+            "SELECT gold FROM characters WHERE name = ?1",
+            [character.name.as_str()],
+        )
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::CharacterNotFound);
+    };
+    let previous_gold = row.get::<u64>(0)?;
+    transaction
         .execute(
             "UPDATE characters SET gold = ?1 WHERE name = ?2",
             (character_patch.gold, character.clone().name),
         )
         .await?;
+    record_gold_flow_libsql_query(
+        &transaction,
+        GoldSource::AdminAdjustment,
+        &character.name,
+        character_patch.gold as i64 - previous_gold as i64,
+        state.clock.now(),
+    )
+    .await?;
+    transaction.commit().await?;
+    character.gold = character_patch.gold;
 
     Ok(Json(character))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/characters/{name}",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character")),
+    responses(
+        (status = 200, description = "The deleted character", body = Character),
+        (status = 404, description = "This character does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn delete_character(
     state: State<AppState>,
     Extension(character): Extension<Character>,
 ) -> Result<Json<Character>> {
-    state
-        .conn
-        .execute(
-            "DELETE FROM characters WHERE name = ?1;",
-            [character.clone().name],
+    let transaction = state.conn.transaction().await?;
+    // The statement has to be done with before committing
+    let gold = {
+        let mut query = transaction
+            .query(
+                "DELETE FROM characters WHERE name = ?1 RETURNING gold + reserved_gold",
+                [character.clone().name],
+            )
+            .await?;
+        let row = query.next().await?; //None if it was already deleted
+        row.map(|row| row.get::<u64>(0)).transpose()?
+    };
+    if let Some(gold) = gold {
+        record_gold_flow_libsql_query(
+            &transaction,
+            GoldSource::CharacterDeletion,
+            &character.name,
+            -(gold as i64),
+            state.clock.now(),
         )
         .await?;
+    }
+    transaction.commit().await?;
 
     Ok(Json(character))
 }
 
+#[utoipa::path(
+    get,
+    path = "/characters/{name}/stats",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character")),
+    responses(
+        (status = 200, description = "The character's level, experience and stats", body = CharacterStats),
+        (status = 404, description = "This character does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_character_stats(
+    state: State<AppState>,
+    Extension(character): Extension<Character>,
+) -> Json<CharacterStats> {
+    Json(character.stats(&state.config.progression))
+}
+
+#[utoipa::path(
+    post,
+    path = "/characters/{name}/experience",
+    tag = "characters",
+    request_body = ExperienceGain,
+    params(("name" = String, Path, description = "Name of the character")),
+    responses(
+        (status = 200, description = "The character's level, experience and stats after the gain", body = CharacterStats),
+        (status = 404, description = "This character does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_character_experience(
+    state: State<AppState>,
+    Extension(character): Extension<Character>,
+    Json(gain): Json<ExperienceGain>,
+) -> Result<Json<CharacterStats>> {
+    // Read again in the transaction so concurrent gains add up
+    let transaction = state.conn.transaction().await?;
+    let mut query = transaction
+        .query(
+            "SELECT * FROM characters WHERE name = ?1",
+            [character.name.as_str()],
+        )
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::CharacterNotFound);
+    };
+    let mut character: Character = from_row(&row)?;
+
+    character.gain_experience(gain.amount, &state.config.progression);
+    transaction
+        .execute(
+            "UPDATE characters SET level = ?1, experience = ?2 WHERE name = ?3",
+            (
+                character.level,
+                character.experience,
+                character.name.as_str(),
+            ),
+        )
+        .await?;
+    transaction.commit().await?;
+
+    Ok(Json(character.stats(&state.config.progression)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/characters/{name}/items",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character")),
+    responses(
+        (status = 200, description = "Every item instance owned by the character", body = Vec<ItemInstance>),
+        (status = 404, description = "This character does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn get_character_items(
     Extension(character): Extension<Character>,
     state: State<AppState>,
@@ -197,43 +696,68 @@ pub async fn get_character_items(
     Ok(Json(items))
 }
 
-pub async fn get_character_item(Extension(item): Extension<Item>) -> Json<Item> {
+#[utoipa::path(
+    get,
+    path = "/characters/{name}/items/{item_id}",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character"), ("item_id" = Uuid, Path, description = "Id of the item instance")),
+    responses(
+        (status = 200, description = "The item instance", body = ItemInstance),
+        (status = 404, description = "The character or the item instance does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_character_item(Extension(item): Extension<ItemInstance>) -> Json<ItemInstance> {
     Json(item)
 }
 
+#[utoipa::path(
+    post,
+    path = "/characters/{name}/items/{item_id}",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character"), ("item_id" = Uuid, Path, description = "Id of the item definition to loot")),
+    responses(
+        (status = 201, description = "The instance holding the looted item (a new one, or a stack it joined)", body = ItemInstance),
+        (status = 403, description = "The inventory is full.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The character or the item does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn post_character_item(
     state: State<AppState>,
     Extension(character): Extension<Character>,
     Extension(item): Extension<Item>,
 ) -> Result<(StatusCode, Json<ItemInstance>)> {
-    let new_id = Uuid::new_v4();
-    let new_item_instance = ItemInstance {
-        id: new_id,
-        item_name: item.name,
-        item_id: item.id,
-        owner_name: character.name,
-    };
+    let slots = character.inventory_slots(&state.config.inventory);
+    let mut rng = StdRng::from_entropy();
 
-    state
-        .conn
-        .execute(
-            "INSERT INTO items_instances (id, item_name, item_id, owner_name) VALUES (?1, ?2, ?3, ?4)",
-            (
-                new_item_instance.id.to_string(),
-                new_item_instance.item_name.as_str(),
-                new_item_instance.item_id.to_string(),
-                new_item_instance.owner_name.as_str(),
-            ),
-        )
-        .await?;
+    let transaction = state.conn.transaction().await?;
+    let Some(item_instance) =
+        add_to_inventory_libsql_query(&transaction, &character, &item, slots, &mut rng).await?
+    else {
+        return Err(Error::InventoryFull);
+    };
+    transaction.commit().await?;
 
-    Ok((StatusCode::CREATED, Json(new_item_instance)))
+    Ok((StatusCode::CREATED, Json(item_instance)))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/characters/{name}/items/{item_id}",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character"), ("item_id" = Uuid, Path, description = "Id of the item instance")),
+    responses(
+        (status = 200, description = "The removed item instance", body = ItemInstance),
+        (status = 403, description = "This item is equipped.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The character or the item instance does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn delete_character_item_instance(
     state: State<AppState>,
     Extension(item): Extension<ItemInstance>,
 ) -> Result<Json<ItemInstance>> {
+    if item.equipped_slot.is_some() {
+        return Err(Error::ItemEquipped);
+    }
     state
         .conn
         .execute(
@@ -245,6 +769,16 @@ pub async fn delete_character_item_instance(
     Ok(Json(item))
 }
 
+#[utoipa::path(
+    get,
+    path = "/characters/{name}/auctions",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character")),
+    responses(
+        (status = 200, description = "Every auction created by the character", body = Vec<Auction>),
+        (status = 404, description = "This character does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn get_character_auctions(
     Extension(character): Extension<Character>,
     state: State<AppState>,
@@ -253,44 +787,143 @@ pub async fn get_character_auctions(
     Ok(Json(auctions))
 }
 
+#[utoipa::path(
+    get,
+    path = "/characters/{name}/auctions/{id}",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character"), ("id" = Uuid, Path, description = "Id of the auction")),
+    responses(
+        (status = 200, description = "The item put up in the auction", body = Item),
+        (status = 404, description = "The character or the auction does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn get_character_auction(Extension(item): Extension<Item>) -> Json<Item> {
     Json(item)
 }
 
+#[utoipa::path(
+    get,
+    path = "/characters/{name}/auctions/{id}",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character"), ("id" = Uuid, Path, description = "Id of the auction")),
+    responses(
+        (status = 200, description = "The auction", body = Auction),
+        (status = 404, description = "The character or the auction does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+// v2: returns the auction itself (v1 returns the auctioned item)
+pub async fn get_character_auction_v2(Extension(auction): Extension<Auction>) -> Json<Auction> {
+    Json(auction)
+}
+
+#[utoipa::path(
+    post,
+    path = "/characters/{name}/auctions",
+    tag = "characters",
+    request_body = ItemInstance,
+    params(("name" = String, Path, description = "Name of the character")),
+    responses(
+        (status = 201, description = "The created auction, its deposit taken from the seller, already sold at the best open buy order's price if one matches", body = Auction),
+        (status = 403, description = "This item is soulbound or equipped, or the seller cannot pay the deposit.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The character or the item instance does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn post_character_auction(
     state: State<AppState>,
     Extension(character): Extension<Character>,
     Json(item): Json<ItemInstance>,
-) -> Result<(StatusCode, Option<Json<Auction>>)> {
-    let Some(_) = get_character_item_libsql_query(&state, &character.name, &item.id).await? else {
+) -> Result<(StatusCode, Json<Auction>)> {
+    let Some(instance) = get_character_item_libsql_query(&state, &character.name, &item.id).await?
+    else {
         return Err(Error::ItemInstanceNotFound);
     };
+    if instance.soulbound {
+        return Err(Error::ItemSoulbound);
+    }
+    if instance.equipped_slot.is_some() {
+        return Err(Error::ItemEquipped);
+    }
+    let Some(item) = get_item_libsql_query(&state, &instance.item_id).await? else {
+        return Err(Error::ItemNotFound);
+    };
 
+    let Some(deposit) = state
+        .config
+        .economy
+        .auction_price
+        .checked_mul(state.config.economy.auction_deposit_percent)
+        .map(|deposit| deposit / 100)
+    else {
+        return Err(Error::GoldOverflow);
+    };
     let new_id = Uuid::new_v4();
-    let new_creation_date = Utc::now();
-    let new_end_date = new_creation_date + TimeDelta::minutes(1);
-    let new_auction = Auction {
+    let new_creation_date = timestamp::truncate(state.clock.now());
+    let new_end_date =
+        new_creation_date + TimeDelta::seconds(state.config.economy.auction_duration_secs as i64);
+    let mut new_auction = Auction {
         id: new_id,
-        auctioned_item_id: item.item_id,
+        auctioned_item_id: instance.item_id,
+        auctioned_instance_id: Some(instance.id),
         seller_name: character.name,
         creation_date: new_creation_date,
         end_date: new_end_date,
-        price: 100,
+        price: state.config.economy.auction_price,
         status: AuctionStatus::Active,
+        durability: instance.durability,
+        enchantment: instance.enchantment,
+        stats: instance.stats,
+        deposit,
     };
 
-    state
-        .conn
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+    spend_gold_libsql_query(&transaction, &new_auction.seller_name, new_auction.deposit).await?;
+    transaction
         .execute(
-            "INSERT INTO auctions (id, auctioned_item_id, seller_name, creation_date, end_date, price, status) 
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            (new_auction.id.to_string(), new_auction.auctioned_item_id.to_string(), new_auction.seller_name.as_str(), new_auction.creation_date.format("%Y-%m-%d %H:%M:%S").to_string(), new_auction.end_date.format("%Y-%m-%d %H:%M:%S").to_string(), new_auction.price, new_auction.status.to_string()),
+            "INSERT INTO auctions (id, auctioned_item_id, seller_name, creation_date, end_date, price, status, auctioned_instance_id, durability, enchantment, stats, deposit) 
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            libsql::params![new_auction.id.to_string(), new_auction.auctioned_item_id.to_string(), new_auction.seller_name.as_str(), timestamp::to_millis(&new_auction.creation_date), timestamp::to_millis(&new_auction.end_date), new_auction.price, new_auction.status.to_string(), instance.id.to_string(), new_auction.durability.map(|durability| durability as i64), new_auction.enchantment, serde_json::to_string(&new_auction.stats).unwrap(), new_auction.deposit],
         )
         .await?;
+    record_fee_libsql_query(
+        &transaction,
+        FeeKind::ListingDeposit,
+        &new_auction.seller_name,
+        &new_auction.id,
+        new_auction.deposit,
+        new_creation_date,
+    )
+    .await?;
+
+    // The best open buy order buys the item right away
+    if let Some(order) = match_buy_orders_libsql_query(
+        &transaction,
+        &state.config,
+        &new_auction,
+        &item,
+        state.clock.now(),
+    )
+    .await?
+    {
+        new_auction.status = AuctionStatus::Sold;
+        new_auction.price = order.max_price;
+    }
+    transaction.commit().await?;
 
-    Ok((StatusCode::CREATED, Some(Json(new_auction))))
+    Ok((StatusCode::CREATED, Json(new_auction)))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/characters/{name}/auctions/{id}",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character"), ("id" = Uuid, Path, description = "Id of the auction")),
+    responses(
+        (status = 200, description = "The cancelled auction (its deposit is not refunded)", body = Auction),
+        (status = 404, description = "The character or the auction does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn delete_character_auction(
     state: State<AppState>,
     Extension(auction): Extension<Auction>,
@@ -392,12 +1025,33 @@ pub async fn middleware_character_and_auction_exist(
     };
     request.extensions_mut().insert(character);
 
+    // The auction has to be created by this character
     let auction = match response_auction {
         Ok(None) => return Error::AuctionNotFound.into_response(),
         Err(e) => return e.into_response(),
+        Ok(Some(auction)) if auction.seller_name != name => {
+            return Error::AuctionNotFound.into_response();
+        }
         Ok(Some(auction)) => auction,
     };
     request.extensions_mut().insert(auction);
 
     next.run(request).await
 }
+
+// v1 only: the auction route answers with the auctioned item
+pub async fn middleware_auctioned_item_exists(
+    state: State<AppState>,
+    Extension(auction): Extension<Auction>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let item = match get_item_libsql_query(&state, &auction.auctioned_item_id).await {
+        Ok(None) => return Error::ItemNotFound.into_response(),
+        Err(e) => return e.into_response(),
+        Ok(Some(item)) => item,
+    };
+    request.extensions_mut().insert(item);
+
+    next.run(request).await
+}
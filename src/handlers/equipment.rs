@@ -0,0 +1,258 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    AppState,
+    errors::{Error, Result},
+    handlers::{
+        characters::{Character, check_inventory_libsql_query, get_character_libsql_query},
+        items::{Item, ItemInstance, ItemKind},
+    },
+    into_rows,
+};
+use axum::{
+    extract::{Extension, Json, Path, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use libsql::de::from_row;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EquipmentSlot {
+    Head,
+    Chest,
+    Hands,
+    Legs,
+    Feet,
+    MainHand,
+    OffHand,
+}
+
+impl fmt::Display for EquipmentSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EquipmentSlot::Head => write!(f, "head"),
+            EquipmentSlot::Chest => write!(f, "chest"),
+            EquipmentSlot::Hands => write!(f, "hands"),
+            EquipmentSlot::Legs => write!(f, "legs"),
+            EquipmentSlot::Feet => write!(f, "feet"),
+            EquipmentSlot::MainHand => write!(f, "main_hand"),
+            EquipmentSlot::OffHand => write!(f, "off_hand"),
+        }
+    }
+}
+
+impl EquipmentSlot {
+    /// Weapons are held in either hand, armor is worn anywhere else (or held in the off hand, as
+    /// a shield).
+    fn fits(&self, kind: ItemKind) -> bool {
+        match kind {
+            ItemKind::Weapon => matches!(self, EquipmentSlot::MainHand | EquipmentSlot::OffHand),
+            ItemKind::Armor => !matches!(self, EquipmentSlot::MainHand),
+            ItemKind::Consumable | ItemKind::Material => false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct EquipRequest {
+    /// Id of an item instance owned by the character
+    instance_id: Uuid,
+}
+
+/// Base stats of the character plus the stats rolled on everything it has equipped.
+pub fn total_stats(character: &Character, equipment: &[ItemInstance]) -> BTreeMap<String, i64> {
+    let base = character.base_stats();
+    let mut stats = BTreeMap::from([
+        ("strength".to_string(), base.strength as i64),
+        ("intellect".to_string(), base.intellect as i64),
+        ("agility".to_string(), base.agility as i64),
+    ]);
+    for (stat, value) in equipment.iter().flat_map(|instance| &instance.stats) {
+        *stats.entry(stat.clone()).or_default() += value;
+    }
+    stats
+}
+
+// =========================Query functions=========================
+pub async fn get_equipment_libsql_query(
+    state: &State<AppState>,
+    name: &str,
+) -> Result<Vec<ItemInstance>> {
+    let query = state
+        .conn
+        .query(
+            "SELECT * FROM items_instances WHERE owner_name = ?1 AND equipped_slot IS NOT NULL",
+            [name],
+        )
+        .await?;
+    let equipment: Vec<ItemInstance> = into_rows(query).await?;
+    Ok(equipment)
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/characters/{name}/equipment",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character")),
+    responses(
+        (status = 200, description = "Every item instance the character has equipped", body = Vec<ItemInstance>),
+        (status = 404, description = "This character does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_character_equipment(
+    state: State<AppState>,
+    Extension(character): Extension<Character>,
+) -> Result<Json<Vec<ItemInstance>>> {
+    let equipment = get_equipment_libsql_query(&state, &character.name).await?;
+    Ok(Json(equipment))
+}
+
+#[utoipa::path(
+    post,
+    path = "/characters/{name}/equipment/{slot}",
+    tag = "characters",
+    request_body = EquipRequest,
+    params(("name" = String, Path, description = "Name of the character"), ("slot" = EquipmentSlot, Path, description = "Equipment slot")),
+    responses(
+        (status = 200, description = "The equipped item instance (what was in the slot goes back to the inventory)", body = ItemInstance),
+        (status = 400, description = "The slot does not exist.", body = String, content_type = "text/plain"),
+        (status = 403, description = "The item does not fit in the slot, the character's class or level does not allow it, or the inventory is full.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The character or the item instance does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_character_equipment(
+    state: State<AppState>,
+    Extension(character): Extension<Character>,
+    Extension(slot): Extension<EquipmentSlot>,
+    Json(request): Json<EquipRequest>,
+) -> Result<Json<ItemInstance>> {
+    let slots = character.inventory_slots(&state.config.inventory);
+    let transaction = state.conn.transaction().await?;
+
+    let mut query = transaction
+        .query(
+            "SELECT * FROM items_instances WHERE id = ?1 AND owner_name = ?2",
+            (request.instance_id.to_string(), character.name.as_str()),
+        )
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::ItemInstanceNotFound);
+    };
+    let mut instance: ItemInstance = from_row(&row)?;
+
+    let mut query = transaction
+        .query(
+            "SELECT * FROM items WHERE id = ?1",
+            [instance.item_id.to_string()],
+        )
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::ItemNotFound);
+    };
+    let item: Item = from_row(&row)?;
+
+    if !slot.fits(item.kind) {
+        return Err(Error::CannotEquip(
+            "This item cannot be equipped in this slot.",
+        ));
+    }
+    if let Some(class) = item.class_restriction
+        && class != character.class()
+    {
+        return Err(Error::CannotEquip(
+            "The character's class cannot use this item.",
+        ));
+    }
+    if item.level_requirement > character.level {
+        return Err(Error::CannotEquip(
+            "The character's level is too low for this item.",
+        ));
+    }
+
+    // Whatever is in the slot goes back to the inventory
+    transaction
+        .execute(
+            "UPDATE items_instances SET equipped_slot = NULL WHERE owner_name = ?1 AND equipped_slot = ?2",
+            (character.name.as_str(), slot.to_string()),
+        )
+        .await?;
+    transaction
+        .execute(
+            "UPDATE items_instances SET equipped_slot = ?1 WHERE id = ?2",
+            (slot.to_string(), instance.id.to_string()),
+        )
+        .await?;
+    check_inventory_libsql_query(&transaction, &character, slots).await?;
+    transaction.commit().await?;
+
+    instance.equipped_slot = Some(slot);
+    Ok(Json(instance))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/characters/{name}/equipment/{slot}",
+    tag = "characters",
+    params(("name" = String, Path, description = "Name of the character"), ("slot" = EquipmentSlot, Path, description = "Equipment slot")),
+    responses(
+        (status = 200, description = "The unequipped item instance, back in the inventory", body = ItemInstance),
+        (status = 400, description = "The slot does not exist.", body = String, content_type = "text/plain"),
+        (status = 403, description = "The inventory is full.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The character does not exist, or nothing is equipped in this slot.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn delete_character_equipment(
+    state: State<AppState>,
+    Extension(character): Extension<Character>,
+    Extension(slot): Extension<EquipmentSlot>,
+) -> Result<Json<ItemInstance>> {
+    let slots = character.inventory_slots(&state.config.inventory);
+    let transaction = state.conn.transaction().await?;
+
+    // The statement has to be done with before committing
+    let instance = {
+        let mut query = transaction
+            .query(
+                "UPDATE items_instances SET equipped_slot = NULL WHERE owner_name = ?1 AND equipped_slot = ?2
+                RETURNING *",
+                (character.name.as_str(), slot.to_string()),
+            )
+            .await?;
+        let instance = query.next().await?; //None if nothing is equipped
+        instance
+            .map(|row| from_row::<ItemInstance>(&row))
+            .transpose()?
+    };
+    let Some(instance) = instance else {
+        return Err(Error::EquipmentSlotEmpty);
+    };
+    check_inventory_libsql_query(&transaction, &character, slots).await?;
+    transaction.commit().await?;
+
+    Ok(Json(instance))
+}
+
+// =========================Middleware=========================
+pub async fn middleware_character_and_equipment_slot_exist(
+    state: State<AppState>,
+    Path((name, slot)): Path<(String, EquipmentSlot)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let response = get_character_libsql_query(&state, &name).await;
+    match response {
+        Ok(None) => Error::CharacterNotFound.into_response(),
+        Err(e) => e.into_response(),
+        Ok(Some(character)) => {
+            request.extensions_mut().insert(character);
+            request.extensions_mut().insert(slot);
+            next.run(request).await
+        }
+    }
+}
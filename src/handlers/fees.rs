@@ -0,0 +1,175 @@
+use std::fmt;
+
+use crate::{
+    AppState,
+    db::{Transaction, timestamp},
+    errors::Result,
+    into_rows,
+};
+use axum::extract::{Json, Query, State};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Gold taken from (or given back to) a character by the marketplace.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct Fee {
+    pub id: Uuid,
+    pub kind: FeeKind,
+    pub character_name: String,
+    pub auction_id: Uuid,
+    pub amount: u64,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub date: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeKind {
+    /// Paid by the seller when listing an auction
+    ListingDeposit,
+    /// The listing deposit, given back when the auction sells
+    DepositRefund,
+    /// Withheld from the seller's proceeds
+    SalesTax,
+}
+
+impl fmt::Display for FeeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeeKind::ListingDeposit => write!(f, "listing_deposit"),
+            FeeKind::DepositRefund => write!(f, "deposit_refund"),
+            FeeKind::SalesTax => write!(f, "sales_tax"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FeeQuery {
+    kind: Option<FeeKind>,
+    /// Only the fees paid by (or refunded to) this character
+    character: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct FeeSummary {
+    pub deposits_charged: u64,
+    pub deposits_refunded: u64,
+    /// Deposits of the auctions still active, which may still be refunded
+    pub deposits_held: u64,
+    /// Deposits of the auctions that expired or were cancelled
+    pub deposits_forfeited: u64,
+    pub sales_tax: u64,
+    /// Forfeited deposits plus sales tax
+    pub removed_from_circulation: u64,
+}
+
+// =========================Query functions=========================
+/// Records a fee in the ledger, nothing is recorded for a zero amount.
+pub async fn record_fee_libsql_query(
+    transaction: &Transaction<'_>,
+    kind: FeeKind,
+    character_name: &str,
+    auction_id: &Uuid,
+    amount: u64,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    transaction
+        .execute(
+            "INSERT INTO fees (id, kind, character_name, auction_id, amount, date)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (
+                Uuid::new_v4().to_string(),
+                kind.to_string(),
+                character_name,
+                auction_id.to_string(),
+                amount,
+                timestamp::to_millis(&now),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn get_fees_libsql_query(state: &State<AppState>, filter: &FeeQuery) -> Result<Vec<Fee>> {
+    let query = state
+        .conn
+        .query(
+            "SELECT * FROM fees WHERE (?1 IS NULL OR kind = ?1) AND (?2 IS NULL OR character_name = ?2)
+            ORDER BY date",
+            (
+                filter.kind.map(|kind| kind.to_string()),
+                filter.character.clone(),
+            ),
+        )
+        .await?;
+    let fees: Vec<Fee> = into_rows(query).await?;
+    Ok(fees)
+}
+
+async fn get_fee_summary_libsql_query(state: &State<AppState>) -> Result<FeeSummary> {
+    let mut query = state
+        .conn
+        .query(
+            "SELECT
+            (SELECT COALESCE(SUM(amount), 0) FROM fees WHERE kind = 'listing_deposit'),
+            (SELECT COALESCE(SUM(amount), 0) FROM fees WHERE kind = 'deposit_refund'),
+            (SELECT COALESCE(SUM(amount), 0) FROM fees WHERE kind = 'sales_tax'),
+            (SELECT COALESCE(SUM(deposit), 0) FROM auctions WHERE status = 'active')",
+            (),
+        )
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Ok(FeeSummary::default());
+    };
+    let deposits_charged = row.get::<u64>(0)?;
+    let deposits_refunded = row.get::<u64>(1)?;
+    let sales_tax = row.get::<u64>(2)?;
+    let deposits_held = row.get::<u64>(3)?;
+    let deposits_forfeited = deposits_charged.saturating_sub(deposits_refunded + deposits_held);
+    Ok(FeeSummary {
+        deposits_charged,
+        deposits_refunded,
+        deposits_held,
+        deposits_forfeited,
+        sales_tax,
+        removed_from_circulation: deposits_forfeited + sales_tax,
+    })
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/fees",
+    tag = "auctions",
+    params(FeeQuery),
+    responses(
+        (status = 200, description = "Every marketplace fee, oldest first, optionally filtered by kind and character", body = Vec<Fee>),
+        (status = 400, description = "The kind is not one of listing_deposit, deposit_refund or sales_tax.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_fees(
+    state: State<AppState>,
+    Query(filter): Query<FeeQuery>,
+) -> Result<Json<Vec<Fee>>> {
+    let fees = get_fees_libsql_query(&state, &filter).await?;
+    Ok(Json(fees))
+}
+
+#[utoipa::path(
+    get,
+    path = "/fees/summary",
+    tag = "auctions",
+    responses(
+        (status = 200, description = "Totals of the marketplace fees, and the gold they removed from circulation", body = FeeSummary),
+    )
+)]
+pub async fn get_fee_summary(state: State<AppState>) -> Result<Json<FeeSummary>> {
+    let summary = get_fee_summary_libsql_query(&state).await?;
+    Ok(Json(summary))
+}
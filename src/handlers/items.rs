@@ -1,50 +1,323 @@
+use std::{collections::BTreeMap, fmt};
+
 use crate::{
     AppState,
+    db::json,
     errors::{Error, Result},
-    handlers::auctions::{Auction, get_auction_libsql_query},
+    handlers::{
+        auctions::{Auction, get_auction_libsql_query},
+        characters::Class,
+        equipment::EquipmentSlot,
+    },
     into_rows,
 };
 use axum::{
-    extract::{Extension, Json, Path, Request, State},
+    extract::{Extension, Json, Path, Query, Request, State},
     http::StatusCode,
     middleware::Next,
     response::{IntoResponse, Response},
 };
 use libsql::de::from_row;
+use rand::{Rng, rngs::StdRng};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+pub const MAX_LEVEL: u64 = 100;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Item {
     pub id: Uuid,
     pub name: String,
+    /// How many of this item fit in one inventory slot (1 for unique items)
+    pub max_stack: u64,
+    pub kind: ItemKind,
+    pub rarity: Rarity,
+    pub level_requirement: u64,
+    /// The only class that can use the item, any class can when it's null
+    pub class_restriction: Option<Class>,
+    /// Reference price in gold
+    pub base_value: u64,
+    pub description: String,
+    /// Free-form JSON object (damage, armor, effects...)
+    #[serde(deserialize_with = "json::deserialize")]
+    #[schema(value_type = Object)]
+    pub attributes: Value,
+    /// Durability of new instances, null when the item doesn't wear out
+    pub max_durability: Option<u64>,
+    /// Stats rolled for every new instance, keyed by stat name
+    #[serde(deserialize_with = "json::deserialize")]
+    pub stat_ranges: BTreeMap<String, StatRange>,
+    /// Looted instances are soulbound to their owner
+    pub binds_on_loot: bool,
+}
+
+/// Inclusive range a stat is rolled in.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+pub struct StatRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    Weapon,
+    Armor,
+    Consumable,
+    Material,
+}
+
+impl fmt::Display for ItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ItemKind::Weapon => write!(f, "weapon"),
+            ItemKind::Armor => write!(f, "armor"),
+            ItemKind::Consumable => write!(f, "consumable"),
+            ItemKind::Material => write!(f, "material"),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Rarity {
+    Common,
+    Uncommon,
+    Rare,
+    Epic,
+    Legendary,
+}
+
+impl fmt::Display for Rarity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rarity::Common => write!(f, "common"),
+            Rarity::Uncommon => write!(f, "uncommon"),
+            Rarity::Rare => write!(f, "rare"),
+            Rarity::Epic => write!(f, "epic"),
+            Rarity::Legendary => write!(f, "legendary"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct NewItem {
     name: String,
+    #[serde(default = "default_quantity")]
+    max_stack: u64,
+    #[serde(default = "default_kind")]
+    kind: ItemKind,
+    #[serde(default = "default_rarity")]
+    rarity: Rarity,
+    #[serde(default = "default_level_requirement")]
+    level_requirement: u64,
+    #[serde(default)]
+    class_restriction: Option<Class>,
+    #[serde(default)]
+    base_value: u64,
+    #[serde(default)]
+    description: String,
+    #[serde(default = "default_attributes")]
+    #[schema(value_type = Object)]
+    attributes: Value,
+    #[serde(default)]
+    max_durability: Option<u64>,
+    #[serde(default)]
+    stat_ranges: BTreeMap<String, StatRange>,
+    #[serde(default)]
+    binds_on_loot: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ItemInstance {
     pub id: Uuid,
     pub item_name: String,
     pub item_id: Uuid,
     pub owner_name: String,
+    #[serde(default = "default_quantity")]
+    pub quantity: u64,
+    /// Null when the item doesn't wear out
+    #[serde(default)]
+    pub durability: Option<u64>,
+    #[serde(default)]
+    pub enchantment: u64,
+    /// Stats rolled when the instance was looted
+    #[serde(default, deserialize_with = "json::deserialize")]
+    pub stats: BTreeMap<String, i64>,
+    /// Soulbound instances can't change hands
+    #[serde(default)]
+    pub soulbound: bool,
+    /// Equipped instances can't change hands either
+    #[serde(default)]
+    pub equipped_slot: Option<EquipmentSlot>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ItemNameUpdate {
-    name: String,
+fn default_quantity() -> u64 {
+    1
+}
+
+fn default_kind() -> ItemKind {
+    ItemKind::Material
+}
+
+fn default_rarity() -> Rarity {
+    Rarity::Common
+}
+
+fn default_level_requirement() -> u64 {
+    1
+}
+
+fn default_attributes() -> Value {
+    Value::Object(Default::default())
+}
+
+// Lets `"field": null` (Some(None)) be told apart from a missing field (None)
+fn deserialize_some<'de, T, D>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Changes to an item definition, missing fields are left as they are (its max stack can't change).
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct ItemUpdate {
+    name: Option<String>,
+    kind: Option<ItemKind>,
+    rarity: Option<Rarity>,
+    level_requirement: Option<u64>,
+    /// Null removes the restriction
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[schema(value_type = Option<Class>)]
+    class_restriction: Option<Option<Class>>,
+    base_value: Option<u64>,
+    description: Option<String>,
+    #[schema(value_type = Option<Object>)]
+    attributes: Option<Value>,
+    /// Null removes the durability (only new instances are affected)
+    #[serde(default, deserialize_with = "deserialize_some")]
+    #[schema(value_type = Option<u64>)]
+    max_durability: Option<Option<u64>>,
+    stat_ranges: Option<BTreeMap<String, StatRange>>,
+    binds_on_loot: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ItemFilter {
+    kind: Option<ItemKind>,
+    rarity: Option<Rarity>,
+    /// Only the items this class can use
+    class: Option<Class>,
+    min_level: Option<u64>,
+    max_level: Option<u64>,
+}
+
+impl Item {
+    fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            return Err(Error::EmptyName);
+        }
+        if self.max_stack == 0 {
+            return Err(Error::InvalidItem("The max stack must be at least 1."));
+        }
+        if matches!(self.kind, ItemKind::Weapon | ItemKind::Armor) && self.max_stack > 1 {
+            return Err(Error::InvalidItem("Weapons and armor cannot be stacked."));
+        }
+        if !(1..=MAX_LEVEL).contains(&self.level_requirement) {
+            return Err(Error::InvalidItem(
+                "The level requirement must be between 1 and 100.",
+            ));
+        }
+        if self.description.chars().count() > MAX_DESCRIPTION_LENGTH {
+            return Err(Error::InvalidItem(
+                "The description cannot be longer than 1000 characters.",
+            ));
+        }
+        if !self.attributes.is_object() {
+            return Err(Error::InvalidItem("The attributes must be a JSON object."));
+        }
+        if self.max_durability == Some(0) {
+            return Err(Error::InvalidItem("The max durability must be at least 1."));
+        }
+        if self.stat_ranges.values().any(|range| range.min > range.max) {
+            return Err(Error::InvalidItem(
+                "A stat range's min cannot be above its max.",
+            ));
+        }
+        // Stacked instances share their properties
+        if self.max_stack > 1 && (self.max_durability.is_some() || !self.stat_ranges.is_empty()) {
+            return Err(Error::InvalidItem(
+                "Stackable items cannot have durability or stat rolls.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rolls the stats of a new instance.
+    pub fn roll_stats(&self, rng: &mut StdRng) -> BTreeMap<String, i64> {
+        self.stat_ranges
+            .iter()
+            .map(|(stat, range)| (stat.clone(), rng.gen_range(range.min..=range.max)))
+            .collect()
+    }
 }
 
 // =========================Query functions=========================
-async fn get_items_libsql_query(state: &State<AppState>) -> Result<Vec<Item>> {
-    let query = state.conn.query("SELECT * FROM items", ()).await?;
+async fn get_items_libsql_query(state: &State<AppState>, filter: &ItemFilter) -> Result<Vec<Item>> {
+    let query = state
+        .conn
+        .query(
+            "SELECT * FROM items WHERE (?1 IS NULL OR kind = ?1)
+            AND (?2 IS NULL OR rarity = ?2)
+            AND (?3 IS NULL OR class_restriction IS NULL OR class_restriction = ?3)
+            AND (?4 IS NULL OR level_requirement >= ?4)
+            AND (?5 IS NULL OR level_requirement <= ?5)",
+            (
+                filter.kind.map(|kind| kind.to_string()),
+                filter.rarity.map(|rarity| rarity.to_string()),
+                filter.class.map(|class| class.to_string()),
+                filter.min_level.map(|level| level as i64),
+                filter.max_level.map(|level| level as i64),
+            ),
+        )
+        .await?;
     let items: Vec<Item> = into_rows(query).await?;
     Ok(items)
 }
 
+async fn update_item_libsql_query(state: &State<AppState>, item: &Item) -> Result<()> {
+    state
+        .conn
+        .execute(
+            "UPDATE items SET name = ?1, kind = ?2, rarity = ?3, level_requirement = ?4,
+            class_restriction = ?5, base_value = ?6, description = ?7, attributes = ?8,
+            max_durability = ?9, stat_ranges = ?10, binds_on_loot = ?11
+            WHERE id = ?12",
+            libsql::params![
+                item.name.as_str(),
+                item.kind.to_string(),
+                item.rarity.to_string(),
+                item.level_requirement,
+                item.class_restriction.map(|class| class.to_string()),
+                item.base_value,
+                item.description.as_str(),
+                item.attributes.to_string(),
+                item.max_durability.map(|durability| durability as i64),
+                serde_json::to_string(&item.stat_ranges).unwrap(),
+                item.binds_on_loot,
+                item.id.to_string(),
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
 pub async fn get_item_libsql_query(state: &State<AppState>, id: &Uuid) -> Result<Option<Item>> {
     let mut query = state
         .conn
@@ -87,8 +360,21 @@ async fn get_item_auctions_libsql_query(
 }
 
 // =========================Handlers=========================
-pub async fn get_items(state: State<AppState>) -> Result<Json<Vec<Item>>> {
-    let items = get_items_libsql_query(&state).await?;
+#[utoipa::path(
+    get,
+    path = "/items",
+    tag = "items",
+    params(ItemFilter),
+    responses(
+        (status = 200, description = "Every item definition matching the filters", body = Vec<Item>),
+        (status = 400, description = "A filter is not valid.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_items(
+    state: State<AppState>,
+    Query(filter): Query<ItemFilter>,
+) -> Result<Json<Vec<Item>>> {
+    let items = get_items_libsql_query(&state, &filter).await?;
     Ok(Json(items))
     // let mut header = HeaderMap::new();
     // header.insert(
@@ -98,52 +384,149 @@ pub async fn get_items(state: State<AppState>) -> Result<Json<Vec<Item>>> {
     // (header, serde_json::to_string(&characters).unwrap())
 }
 
+#[utoipa::path(
+    post,
+    path = "/items",
+    tag = "items",
+    request_body = NewItem,
+    responses(
+        (status = 201, description = "The created item definition", body = Item),
+        (status = 400, description = "The name provided is empty, or a field is not valid (max stack of 0, stacked weapon or armor, level requirement out of 1..=100, description over 1000 characters, attributes that aren't an object).", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn post_item(
     state: State<AppState>,
     Json(new_item): Json<NewItem>,
 ) -> Result<(StatusCode, Json<Item>)> {
-    if new_item.name.is_empty() {
-        return Err(Error::EmptyName);
-    }
-
     let new_id = Uuid::new_v4();
     let item = Item {
         id: new_id,
         name: new_item.name,
+        max_stack: new_item.max_stack,
+        kind: new_item.kind,
+        rarity: new_item.rarity,
+        level_requirement: new_item.level_requirement,
+        class_restriction: new_item.class_restriction,
+        base_value: new_item.base_value,
+        description: new_item.description,
+        attributes: new_item.attributes,
+        max_durability: new_item.max_durability,
+        stat_ranges: new_item.stat_ranges,
+        binds_on_loot: new_item.binds_on_loot,
     };
+    item.validate()?;
 
     state
         .conn
         .execute(
-            "INSERT INTO items (id, name) VALUES (?1, ?2)",
-            (item.id.to_string(), item.name.as_str()),
+            "INSERT INTO items (id, name, max_stack, kind, rarity, level_requirement,
+            class_restriction, base_value, description, attributes, max_durability, stat_ranges,
+            binds_on_loot)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            libsql::params![
+                item.id.to_string(),
+                item.name.as_str(),
+                item.max_stack,
+                item.kind.to_string(),
+                item.rarity.to_string(),
+                item.level_requirement,
+                item.class_restriction.map(|class| class.to_string()),
+                item.base_value,
+                item.description.as_str(),
+                item.attributes.to_string(),
+                item.max_durability.map(|durability| durability as i64),
+                serde_json::to_string(&item.stat_ranges).unwrap(),
+                item.binds_on_loot,
+            ],
         )
         .await?;
 
     Ok((StatusCode::CREATED, Json(item)))
 }
 
+#[utoipa::path(
+    get,
+    path = "/items/{id}",
+    tag = "items",
+    params(("id" = Uuid, Path, description = "Id of the item definition")),
+    responses(
+        (status = 200, description = "The item definition", body = Item),
+        (status = 404, description = "This item does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn get_item(Extension(item): Extension<Item>) -> Json<Item> {
     Json(item)
 }
 
+#[utoipa::path(
+    patch,
+    path = "/items/{id}",
+    tag = "items",
+    request_body = ItemUpdate,
+    params(("id" = Uuid, Path, description = "Id of the item definition")),
+    responses(
+        (status = 200, description = "The updated item definition", body = Item),
+        (status = 400, description = "The name provided is empty, or a field is not valid.", body = String, content_type = "text/plain"),
+        (status = 404, description = "This item does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn patch_item(
     state: State<AppState>,
     Extension(mut item): Extension<Item>,
-    Json(item_patch): Json<ItemNameUpdate>,
+    Json(item_patch): Json<ItemUpdate>,
 ) -> Result<Json<Item>> {
-    item.name = item_patch.name.clone();
-    state
-        .conn
-        .execute(
-            "UPDATE items SET name = ?1 WHERE id = ?2;",
-            (item_patch.name.as_str(), item.id.to_string()),
-        )
-        .await?;
+    if let Some(name) = item_patch.name {
+        item.name = name;
+    }
+    if let Some(kind) = item_patch.kind {
+        item.kind = kind;
+    }
+    if let Some(rarity) = item_patch.rarity {
+        item.rarity = rarity;
+    }
+    if let Some(level_requirement) = item_patch.level_requirement {
+        item.level_requirement = level_requirement;
+    }
+    if let Some(class_restriction) = item_patch.class_restriction {
+        item.class_restriction = class_restriction;
+    }
+    if let Some(base_value) = item_patch.base_value {
+        item.base_value = base_value;
+    }
+    if let Some(description) = item_patch.description {
+        item.description = description;
+    }
+    if let Some(attributes) = item_patch.attributes {
+        item.attributes = attributes;
+    }
+    if let Some(max_durability) = item_patch.max_durability {
+        item.max_durability = max_durability;
+    }
+    if let Some(stat_ranges) = item_patch.stat_ranges {
+        item.stat_ranges = stat_ranges;
+    }
+    if let Some(binds_on_loot) = item_patch.binds_on_loot {
+        item.binds_on_loot = binds_on_loot;
+    }
+    item.validate()?;
+
+    update_item_libsql_query(&state, &item).await?;
 
     Ok(Json(item))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/items/{id}",
+    tag = "items",
+    params(("id" = Uuid, Path, description = "Id of the item definition")),
+    responses(
+        (status = 200, description = "The deleted item definition", body = Item),
+        (status = 404, description = "This item does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn delete_item(
     state: State<AppState>,
     Extension(item): Extension<Item>,
@@ -156,6 +539,16 @@ pub async fn delete_item(
     Ok(Json(item))
 }
 
+#[utoipa::path(
+    get,
+    path = "/items/{id}/auctions",
+    tag = "items",
+    params(("id" = Uuid, Path, description = "Id of the item definition")),
+    responses(
+        (status = 200, description = "Every auction of this item", body = Vec<Auction>),
+        (status = 404, description = "This item does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn get_item_auctions(
     Extension(item): Extension<Item>,
     state: State<AppState>,
@@ -164,6 +557,16 @@ pub async fn get_item_auctions(
     Ok(Json(auctions))
 }
 
+#[utoipa::path(
+    get,
+    path = "/items/{id}/auctions/{auction_id}",
+    tag = "items",
+    params(("id" = Uuid, Path, description = "Id of the item definition"), ("auction_id" = Uuid, Path, description = "Id of the auction")),
+    responses(
+        (status = 200, description = "The auction", body = Auction),
+        (status = 404, description = "The item or the auction does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
 pub async fn get_item_auction(Extension(auction): Extension<Auction>) -> Json<Auction> {
     Json(auction)
 }
@@ -186,25 +589,29 @@ pub async fn middleware_item_exists(
     }
 }
 
-pub async fn middleware_item_instance_and_auction_exist(
+pub async fn middleware_item_and_auction_exist(
     state: State<AppState>,
     Path((item_id, auction_id)): Path<(Uuid, Uuid)>,
     mut request: Request,
     next: Next,
 ) -> Response {
-    let response_item = get_item_instance_libsql_query(&state, &item_id).await;
+    let response_item = get_item_libsql_query(&state, &item_id).await;
     let response_auction = get_auction_libsql_query(&state, &auction_id).await;
 
     let item = match response_item {
-        Ok(None) => return Error::ItemInstanceNotFound.into_response(),
+        Ok(None) => return Error::ItemNotFound.into_response(),
         Err(e) => return e.into_response(),
         Ok(Some(item)) => item,
     };
     request.extensions_mut().insert(item);
 
+    // The auction has to be for this item
     let auction = match response_auction {
         Ok(None) => return Error::AuctionNotFound.into_response(),
         Err(e) => return e.into_response(),
+        Ok(Some(auction)) if auction.auctioned_item_id != item_id => {
+            return Error::AuctionNotFound.into_response();
+        }
         Ok(Some(auction)) => auction,
     };
     request.extensions_mut().insert(auction);
@@ -0,0 +1,562 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    AppState,
+    db::json,
+    errors::{Error, Result},
+    handlers::{
+        characters::{
+            Character, add_to_inventory_libsql_query, credit_gold_libsql_query,
+            get_character_libsql_query,
+        },
+        items::{Item, ItemInstance, Rarity, get_item_libsql_query},
+    },
+    into_rows,
+    reports::{GoldSource, record_gold_flow_libsql_query},
+};
+use axum::{
+    extract::{Extension, Json, Path, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use libsql::de::from_row;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+const MAX_ROLLS: u64 = 100;
+const MAX_QUANTITY: u64 = 1000;
+const MAX_GOLD: u64 = 1_000_000_000;
+const MAX_WEIGHT: u64 = 1_000_000;
+const MAX_DROPPED_UNITS: u64 = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct LootTable {
+    pub id: Uuid,
+    pub name: String,
+    /// How many weighted picks are made (guaranteed entries always drop on top of them)
+    pub rolls: u64,
+    /// Weight of picking nothing
+    pub nothing_weight: u64,
+    pub min_gold: u64,
+    pub max_gold: u64,
+    #[serde(deserialize_with = "json::deserialize")]
+    pub entries: Vec<LootEntry>,
+}
+
+/// A possible drop: a given item, or any item of a rarity tier.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct LootEntry {
+    #[serde(default)]
+    pub item_id: Option<Uuid>,
+    #[serde(default)]
+    pub rarity: Option<Rarity>,
+    #[serde(default = "default_one")]
+    pub weight: u64,
+    #[serde(default = "default_one")]
+    pub min_quantity: u64,
+    #[serde(default = "default_one")]
+    pub max_quantity: u64,
+    /// Drops on every roll of the table instead of being picked
+    #[serde(default)]
+    pub guaranteed: bool,
+}
+
+fn default_one() -> u64 {
+    1
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct NewLootTable {
+    name: String,
+    #[serde(default = "default_one")]
+    rolls: u64,
+    #[serde(default)]
+    nothing_weight: u64,
+    #[serde(default)]
+    min_gold: u64,
+    #[serde(default)]
+    max_gold: u64,
+    #[serde(default)]
+    entries: Vec<LootEntry>,
+}
+
+/// Changes to a loot table, missing fields are left as they are.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct LootTableUpdate {
+    name: Option<String>,
+    rolls: Option<u64>,
+    nothing_weight: Option<u64>,
+    min_gold: Option<u64>,
+    max_gold: Option<u64>,
+    entries: Option<Vec<LootEntry>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct LootRequest {
+    /// Rolling again with the same seed gives the same drop, a random one is used when missing
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct LootDrop {
+    /// Position of the entry in the table
+    pub entry: usize,
+    pub item_id: Uuid,
+    pub item_name: String,
+    pub quantity: u64,
+    pub guaranteed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct LootRoll {
+    pub seed: u64,
+    pub table_id: Uuid,
+    pub character_name: String,
+    pub gold: u64,
+    /// What dropped, in the order it was rolled
+    pub drops: Vec<LootDrop>,
+    /// The inventory instances holding the drops
+    pub instances: Vec<ItemInstance>,
+}
+
+impl LootTable {
+    fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            return Err(Error::EmptyName);
+        }
+        if self.rolls > MAX_ROLLS {
+            return Err(Error::InvalidLootTable(
+                "A loot table cannot roll more than 100 times.",
+            ));
+        }
+        if self.min_gold > self.max_gold {
+            return Err(Error::InvalidLootTable(
+                "The min gold cannot be above the max gold.",
+            ));
+        }
+        if self.max_gold > MAX_GOLD {
+            return Err(Error::InvalidLootTable(
+                "A loot table cannot drop more than 1000000000 gold.",
+            ));
+        }
+        if self.nothing_weight > MAX_WEIGHT {
+            return Err(Error::InvalidLootTable("A weight cannot be above 1000000."));
+        }
+        for entry in &self.entries {
+            if entry.item_id.is_some() == entry.rarity.is_some() {
+                return Err(Error::InvalidLootTable(
+                    "A loot entry needs either an item or a rarity.",
+                ));
+            }
+            if entry.min_quantity == 0
+                || entry.min_quantity > entry.max_quantity
+                || entry.max_quantity > MAX_QUANTITY
+            {
+                return Err(Error::InvalidLootTable(
+                    "A loot entry's quantities must go from at least 1 up to at most 1000.",
+                ));
+            }
+            if entry.weight > MAX_WEIGHT {
+                return Err(Error::InvalidLootTable("A weight cannot be above 1000000."));
+            }
+        }
+
+        // Each unit dropped can take an inventory row of its own
+        let picked = self
+            .entries
+            .iter()
+            .filter(|entry| !entry.guaranteed)
+            .map(|entry| entry.max_quantity)
+            .max()
+            .unwrap_or(0);
+        let guaranteed: u64 = self
+            .entries
+            .iter()
+            .filter(|entry| entry.guaranteed)
+            .map(|entry| entry.max_quantity)
+            .sum();
+        if self.rolls * picked + guaranteed > MAX_DROPPED_UNITS {
+            return Err(Error::InvalidLootTable(
+                "A loot table cannot drop more than 1000 items at once.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rolls the table, `candidates` holding the items each entry can drop.
+    /// Entries without candidates (deleted items, empty rarity tiers) never drop.
+    fn roll(&self, candidates: &[Vec<Item>], rng: &mut StdRng) -> (Vec<(LootDrop, Item)>, u64) {
+        let mut drops = Vec::new();
+        let mut drop_entry = |index: usize, rng: &mut StdRng| {
+            let entry = &self.entries[index];
+            let items = &candidates[index];
+            if items.is_empty() {
+                return;
+            }
+            let item = &items[rng.gen_range(0..items.len())];
+            let quantity = rng.gen_range(entry.min_quantity..=entry.max_quantity);
+            drops.push((
+                LootDrop {
+                    entry: index,
+                    item_id: item.id,
+                    item_name: item.name.clone(),
+                    quantity,
+                    guaranteed: entry.guaranteed,
+                },
+                item.clone(),
+            ));
+        };
+
+        for (index, entry) in self.entries.iter().enumerate() {
+            if entry.guaranteed {
+                drop_entry(index, rng);
+            }
+        }
+
+        let weighted: Vec<(usize, u64)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(index, entry)| !entry.guaranteed && !candidates[*index].is_empty())
+            .map(|(index, entry)| (index, entry.weight))
+            .collect();
+        let total = weighted.iter().map(|(_, weight)| weight).sum::<u64>() + self.nothing_weight;
+        if total > 0 {
+            for _ in 0..self.rolls {
+                let mut pick = rng.gen_range(0..total);
+                for (index, weight) in &weighted {
+                    if pick < *weight {
+                        drop_entry(*index, rng);
+                        break;
+                    }
+                    pick -= weight;
+                }
+            }
+        }
+
+        let gold = rng.gen_range(self.min_gold..=self.max_gold);
+        (drops, gold)
+    }
+}
+
+// =========================Query functions=========================
+async fn get_loot_tables_libsql_query(state: &State<AppState>) -> Result<Vec<LootTable>> {
+    let query = state.conn.query("SELECT * FROM loot_tables", ()).await?;
+    let tables: Vec<LootTable> = into_rows(query).await?;
+    Ok(tables)
+}
+
+pub async fn get_loot_table_libsql_query(
+    state: &State<AppState>,
+    id: &Uuid,
+) -> Result<Option<LootTable>> {
+    let mut query = state
+        .conn
+        .query("SELECT * FROM loot_tables WHERE id = ?1", [id.to_string()])
+        .await?;
+    let table = query.next().await?; //None if there are no more rows
+    table
+        .map(|row| from_row(&row).map_err(Error::from))
+        .transpose()
+}
+
+async fn get_items_of_rarity_libsql_query(
+    state: &State<AppState>,
+    rarity: Rarity,
+) -> Result<Vec<Item>> {
+    // Ordered so that a seed always picks the same item
+    let query = state
+        .conn
+        .query(
+            "SELECT * FROM items WHERE rarity = ?1 ORDER BY id",
+            [rarity.to_string()],
+        )
+        .await?;
+    let items: Vec<Item> = into_rows(query).await?;
+    Ok(items)
+}
+
+// Entries can only point to existing items
+async fn check_entries_libsql_query(state: &State<AppState>, table: &LootTable) -> Result<()> {
+    for item_id in table.entries.iter().filter_map(|entry| entry.item_id) {
+        if get_item_libsql_query(state, &item_id).await?.is_none() {
+            return Err(Error::ItemNotFound);
+        }
+    }
+    Ok(())
+}
+
+async fn save_loot_table_libsql_query(state: &State<AppState>, table: &LootTable) -> Result<()> {
+    state
+        .conn
+        .execute(
+            "INSERT INTO loot_tables (id, name, rolls, nothing_weight, min_gold, max_gold, entries)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT (id) DO UPDATE SET name = ?2, rolls = ?3, nothing_weight = ?4,
+            min_gold = ?5, max_gold = ?6, entries = ?7",
+            (
+                table.id.to_string(),
+                table.name.as_str(),
+                table.rolls,
+                table.nothing_weight,
+                table.min_gold,
+                table.max_gold,
+                serde_json::to_string(&table.entries).unwrap(),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/loot-tables",
+    tag = "loot",
+    responses(
+        (status = 200, description = "Every loot table", body = Vec<LootTable>),
+    )
+)]
+pub async fn get_loot_tables(state: State<AppState>) -> Result<Json<Vec<LootTable>>> {
+    let tables = get_loot_tables_libsql_query(&state).await?;
+    Ok(Json(tables))
+}
+
+#[utoipa::path(
+    post,
+    path = "/loot-tables",
+    tag = "loot",
+    request_body = NewLootTable,
+    responses(
+        (status = 201, description = "The created loot table", body = LootTable),
+        (status = 400, description = "The name provided is empty, or the table is not valid.", body = String, content_type = "text/plain"),
+        (status = 404, description = "An entry's item does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_loot_table(
+    state: State<AppState>,
+    Json(new_table): Json<NewLootTable>,
+) -> Result<(StatusCode, Json<LootTable>)> {
+    let table = LootTable {
+        id: Uuid::new_v4(),
+        name: new_table.name,
+        rolls: new_table.rolls,
+        nothing_weight: new_table.nothing_weight,
+        min_gold: new_table.min_gold,
+        max_gold: new_table.max_gold,
+        entries: new_table.entries,
+    };
+    table.validate()?;
+    check_entries_libsql_query(&state, &table).await?;
+    save_loot_table_libsql_query(&state, &table).await?;
+
+    Ok((StatusCode::CREATED, Json(table)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/loot-tables/{id}",
+    tag = "loot",
+    params(("id" = Uuid, Path, description = "Id of the loot table")),
+    responses(
+        (status = 200, description = "The loot table", body = LootTable),
+        (status = 404, description = "This loot table does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_loot_table(Extension(table): Extension<LootTable>) -> Json<LootTable> {
+    Json(table)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/loot-tables/{id}",
+    tag = "loot",
+    request_body = LootTableUpdate,
+    params(("id" = Uuid, Path, description = "Id of the loot table")),
+    responses(
+        (status = 200, description = "The updated loot table", body = LootTable),
+        (status = 400, description = "The name provided is empty, or the table is not valid.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The loot table or an entry's item does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn patch_loot_table(
+    state: State<AppState>,
+    Extension(mut table): Extension<LootTable>,
+    Json(table_patch): Json<LootTableUpdate>,
+) -> Result<Json<LootTable>> {
+    if let Some(name) = table_patch.name {
+        table.name = name;
+    }
+    if let Some(rolls) = table_patch.rolls {
+        table.rolls = rolls;
+    }
+    if let Some(nothing_weight) = table_patch.nothing_weight {
+        table.nothing_weight = nothing_weight;
+    }
+    if let Some(min_gold) = table_patch.min_gold {
+        table.min_gold = min_gold;
+    }
+    if let Some(max_gold) = table_patch.max_gold {
+        table.max_gold = max_gold;
+    }
+    if let Some(entries) = table_patch.entries {
+        table.entries = entries;
+    }
+    table.validate()?;
+    check_entries_libsql_query(&state, &table).await?;
+    save_loot_table_libsql_query(&state, &table).await?;
+
+    Ok(Json(table))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/loot-tables/{id}",
+    tag = "loot",
+    params(("id" = Uuid, Path, description = "Id of the loot table")),
+    responses(
+        (status = 200, description = "The deleted loot table", body = LootTable),
+        (status = 404, description = "This loot table does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn delete_loot_table(
+    state: State<AppState>,
+    Extension(table): Extension<LootTable>,
+) -> Result<Json<LootTable>> {
+    state
+        .conn
+        .execute(
+            "DELETE FROM loot_tables WHERE id = ?1",
+            [table.id.to_string()],
+        )
+        .await?;
+
+    Ok(Json(table))
+}
+
+#[utoipa::path(
+    post,
+    path = "/characters/{name}/loot/{table_id}",
+    tag = "loot",
+    request_body = LootRequest,
+    params(("name" = String, Path, description = "Name of the character"), ("table_id" = Uuid, Path, description = "Id of the loot table")),
+    responses(
+        (status = 201, description = "The roll log, with the gold and the items added to the character", body = LootRoll),
+        (status = 403, description = "The inventory is full (nothing is given then).", body = String, content_type = "text/plain"),
+        (status = 404, description = "The character or the loot table does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_character_loot(
+    state: State<AppState>,
+    Extension(character): Extension<Character>,
+    Extension(table): Extension<LootTable>,
+    Json(request): Json<LootRequest>,
+) -> Result<(StatusCode, Json<LootRoll>)> {
+    let mut candidates = Vec::with_capacity(table.entries.len());
+    for entry in &table.entries {
+        let items = match (entry.item_id, entry.rarity) {
+            (Some(item_id), _) => get_item_libsql_query(&state, &item_id)
+                .await?
+                .into_iter()
+                .collect(),
+            (None, Some(rarity)) => get_items_of_rarity_libsql_query(&state, rarity).await?,
+            (None, None) => Vec::new(),
+        };
+        candidates.push(items);
+    }
+
+    let seed = request.seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (drops, gold) = table.roll(&candidates, &mut rng);
+    let slots = character.inventory_slots(&state.config.inventory);
+
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+    let mut instances = BTreeMap::new();
+    for (drop, item) in &drops {
+        for _ in 0..drop.quantity {
+            let Some(instance) =
+                add_to_inventory_libsql_query(&transaction, &character, item, slots, &mut rng)
+                    .await?
+            else {
+                return Err(Error::InventoryFull);
+            };
+            instances.insert(instance.id, instance);
+        }
+    }
+    if gold > 0 {
+        credit_gold_libsql_query(&transaction, &character.name, gold).await?;
+        record_gold_flow_libsql_query(
+            &transaction,
+            GoldSource::Loot,
+            &character.name,
+            i64::try_from(gold).map_err(|_| Error::GoldOverflow)?,
+            state.clock.now(),
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(LootRoll {
+            seed,
+            table_id: table.id,
+            character_name: character.name,
+            gold,
+            drops: drops.into_iter().map(|(drop, _)| drop).collect(),
+            instances: instances.into_values().collect(),
+        }),
+    ))
+}
+
+// =========================Middleware=========================
+pub async fn middleware_loot_table_exists(
+    state: State<AppState>,
+    Path(id): Path<Uuid>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let response = get_loot_table_libsql_query(&state, &id).await;
+    match response {
+        Ok(None) => Error::LootTableNotFound.into_response(),
+        Err(e) => e.into_response(),
+        Ok(Some(table)) => {
+            request.extensions_mut().insert(table);
+            next.run(request).await
+        }
+    }
+}
+
+pub async fn middleware_character_and_loot_table_exist(
+    state: State<AppState>,
+    Path((name, id)): Path<(String, Uuid)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let response_character = get_character_libsql_query(&state, &name).await;
+    let response_table = get_loot_table_libsql_query(&state, &id).await;
+
+    let character = match response_character {
+        Ok(None) => return Error::CharacterNotFound.into_response(),
+        Err(e) => return e.into_response(),
+        Ok(Some(character)) => character,
+    };
+    request.extensions_mut().insert(character);
+
+    let table = match response_table {
+        Ok(None) => return Error::LootTableNotFound.into_response(),
+        Err(e) => return e.into_response(),
+        Ok(Some(table)) => table,
+    };
+    request.extensions_mut().insert(table);
+
+    next.run(request).await
+}
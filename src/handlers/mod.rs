@@ -1,3 +1,10 @@
 pub mod auctions;
+pub mod buy_orders;
 pub mod characters;
+pub mod equipment;
+pub mod fees;
 pub mod items;
+pub mod loot;
+pub mod recipes;
+pub mod trades;
+pub mod vendors;
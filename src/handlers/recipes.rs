@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    AppState,
+    db::json,
+    errors::{Error, Result},
+    handlers::{
+        characters::{
+            Character, add_to_inventory_libsql_query, get_character_libsql_query,
+            spend_gold_libsql_query,
+        },
+        items::{ItemInstance, get_item_libsql_query},
+    },
+    into_rows,
+    reports::{GoldSource, record_gold_flow_libsql_query},
+};
+use axum::{
+    extract::{Extension, Json, Path, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use libsql::de::from_row;
+use rand::{SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+const MAX_QUANTITY: u64 = 1000;
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct Recipe {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(deserialize_with = "json::deserialize")]
+    pub inputs: Vec<Ingredient>,
+    pub gold_cost: u64,
+    pub output_item_id: Uuid,
+    pub output_quantity: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct Ingredient {
+    pub item_id: Uuid,
+    pub quantity: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct NewRecipe {
+    name: String,
+    inputs: Vec<Ingredient>,
+    #[serde(default)]
+    gold_cost: u64,
+    output_item_id: Uuid,
+    #[serde(default = "default_output_quantity")]
+    output_quantity: u64,
+}
+
+fn default_output_quantity() -> u64 {
+    1
+}
+
+impl Recipe {
+    fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            return Err(Error::EmptyName);
+        }
+        if self.inputs.is_empty() {
+            return Err(Error::InvalidRecipe("A recipe needs at least one input."));
+        }
+        let mut item_ids = BTreeSet::new();
+        for input in &self.inputs {
+            if input.quantity == 0 || input.quantity > MAX_QUANTITY {
+                return Err(Error::InvalidRecipe(
+                    "An input's quantity must be between 1 and 1000.",
+                ));
+            }
+            if !item_ids.insert(input.item_id) {
+                return Err(Error::InvalidRecipe(
+                    "An item cannot be listed twice in the inputs.",
+                ));
+            }
+        }
+        if self.output_quantity == 0 || self.output_quantity > MAX_QUANTITY {
+            return Err(Error::InvalidRecipe(
+                "The output quantity must be between 1 and 1000.",
+            ));
+        }
+        Ok(())
+    }
+}
+
+// =========================Query functions=========================
+async fn get_recipes_libsql_query(state: &State<AppState>) -> Result<Vec<Recipe>> {
+    let query = state.conn.query("SELECT * FROM recipes", ()).await?;
+    let recipes: Vec<Recipe> = into_rows(query).await?;
+    Ok(recipes)
+}
+
+pub async fn get_recipe_libsql_query(state: &State<AppState>, id: &Uuid) -> Result<Option<Recipe>> {
+    let mut query = state
+        .conn
+        .query("SELECT * FROM recipes WHERE id = ?1", [id.to_string()])
+        .await?;
+    let recipe = query.next().await?; //None if there are no more rows
+    recipe
+        .map(|row| from_row(&row).map_err(Error::from))
+        .transpose()
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/recipes",
+    tag = "crafting",
+    responses(
+        (status = 200, description = "Every crafting recipe", body = Vec<Recipe>),
+    )
+)]
+pub async fn get_recipes(state: State<AppState>) -> Result<Json<Vec<Recipe>>> {
+    let recipes = get_recipes_libsql_query(&state).await?;
+    Ok(Json(recipes))
+}
+
+#[utoipa::path(
+    post,
+    path = "/recipes",
+    tag = "crafting",
+    request_body = NewRecipe,
+    responses(
+        (status = 201, description = "The created recipe", body = Recipe),
+        (status = 400, description = "The name provided is empty, or the recipe is not valid.", body = String, content_type = "text/plain"),
+        (status = 404, description = "An input or the output item does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_recipe(
+    state: State<AppState>,
+    Json(new_recipe): Json<NewRecipe>,
+) -> Result<(StatusCode, Json<Recipe>)> {
+    let recipe = Recipe {
+        id: Uuid::new_v4(),
+        name: new_recipe.name,
+        inputs: new_recipe.inputs,
+        gold_cost: new_recipe.gold_cost,
+        output_item_id: new_recipe.output_item_id,
+        output_quantity: new_recipe.output_quantity,
+    };
+    recipe.validate()?;
+    for item_id in recipe
+        .inputs
+        .iter()
+        .map(|input| &input.item_id)
+        .chain([&recipe.output_item_id])
+    {
+        if get_item_libsql_query(&state, item_id).await?.is_none() {
+            return Err(Error::ItemNotFound);
+        }
+    }
+
+    state
+        .conn
+        .execute(
+            "INSERT INTO recipes (id, name, inputs, gold_cost, output_item_id, output_quantity)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (
+                recipe.id.to_string(),
+                recipe.name.as_str(),
+                serde_json::to_string(&recipe.inputs).unwrap(),
+                recipe.gold_cost,
+                recipe.output_item_id.to_string(),
+                recipe.output_quantity,
+            ),
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(recipe)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/recipes/{id}",
+    tag = "crafting",
+    params(("id" = Uuid, Path, description = "Id of the recipe")),
+    responses(
+        (status = 200, description = "The recipe", body = Recipe),
+        (status = 404, description = "This recipe does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_recipe(Extension(recipe): Extension<Recipe>) -> Json<Recipe> {
+    Json(recipe)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/recipes/{id}",
+    tag = "crafting",
+    params(("id" = Uuid, Path, description = "Id of the recipe")),
+    responses(
+        (status = 200, description = "The deleted recipe", body = Recipe),
+        (status = 404, description = "This recipe does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn delete_recipe(
+    state: State<AppState>,
+    Extension(recipe): Extension<Recipe>,
+) -> Result<Json<Recipe>> {
+    state
+        .conn
+        .execute("DELETE FROM recipes WHERE id = ?1", [recipe.id.to_string()])
+        .await?;
+
+    Ok(Json(recipe))
+}
+
+#[utoipa::path(
+    post,
+    path = "/characters/{name}/craft/{recipe_id}",
+    tag = "crafting",
+    params(("name" = String, Path, description = "Name of the character"), ("recipe_id" = Uuid, Path, description = "Id of the recipe")),
+    responses(
+        (status = 201, description = "The inventory instances holding the crafted items", body = Vec<ItemInstance>),
+        (status = 403, description = "Ingredients are missing (they are listed), the character does not have enough gold, or the inventory is full.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The character, the recipe or one of its items does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_character_craft(
+    state: State<AppState>,
+    Extension(character): Extension<Character>,
+    Extension(recipe): Extension<Recipe>,
+) -> Result<(StatusCode, Json<Vec<ItemInstance>>)> {
+    let Some(output) = get_item_libsql_query(&state, &recipe.output_item_id).await? else {
+        return Err(Error::ItemNotFound);
+    };
+    let mut inputs = Vec::with_capacity(recipe.inputs.len());
+    for input in &recipe.inputs {
+        let Some(item) = get_item_libsql_query(&state, &input.item_id).await? else {
+            return Err(Error::ItemNotFound);
+        };
+        inputs.push((item, input.quantity));
+    }
+    let slots = character.inventory_slots(&state.config.inventory);
+
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+
+    // Equipped instances are never consumed, the smallest stacks go first
+    let mut stacks = Vec::with_capacity(inputs.len());
+    let mut missing = Vec::new();
+    for (item, quantity) in &inputs {
+        let query = transaction
+            .query(
+                "SELECT * FROM items_instances WHERE owner_name = ?1 AND item_id = ?2 AND equipped_slot IS NULL
+                ORDER BY quantity",
+                (character.name.as_str(), item.id.to_string()),
+            )
+            .await?;
+        let instances: Vec<ItemInstance> = into_rows(query).await?;
+        let owned: u64 = instances.iter().map(|instance| instance.quantity).sum();
+        if owned < *quantity {
+            missing.push((item.name.clone(), quantity - owned));
+        }
+        stacks.push((instances, *quantity));
+    }
+    if !missing.is_empty() {
+        return Err(Error::MissingIngredients(missing));
+    }
+
+    spend_gold_libsql_query(&transaction, &character.name, recipe.gold_cost).await?;
+    record_gold_flow_libsql_query(
+        &transaction,
+        GoldSource::Crafting,
+        &character.name,
+        -(recipe.gold_cost as i64),
+        state.clock.now(),
+    )
+    .await?;
+
+    for (instances, mut quantity) in stacks {
+        for instance in instances {
+            if quantity == 0 {
+                break;
+            }
+            if instance.quantity <= quantity {
+                transaction
+                    .execute(
+                        "DELETE FROM items_instances WHERE id = ?1",
+                        [instance.id.to_string()],
+                    )
+                    .await?;
+                quantity -= instance.quantity;
+            } else {
+                transaction
+                    .execute(
+                        "UPDATE items_instances SET quantity = quantity - ?1 WHERE id = ?2",
+                        (quantity, instance.id.to_string()),
+                    )
+                    .await?;
+                quantity = 0;
+            }
+        }
+    }
+
+    let mut rng = StdRng::from_entropy();
+    let mut crafted = BTreeMap::new();
+    for _ in 0..recipe.output_quantity {
+        let Some(instance) =
+            add_to_inventory_libsql_query(&transaction, &character, &output, slots, &mut rng)
+                .await?
+        else {
+            return Err(Error::InventoryFull);
+        };
+        crafted.insert(instance.id, instance);
+    }
+    transaction.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(crafted.into_values().collect())))
+}
+
+// =========================Middleware=========================
+pub async fn middleware_recipe_exists(
+    state: State<AppState>,
+    Path(id): Path<Uuid>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let response = get_recipe_libsql_query(&state, &id).await;
+    match response {
+        Ok(None) => Error::RecipeNotFound.into_response(),
+        Err(e) => e.into_response(),
+        Ok(Some(recipe)) => {
+            request.extensions_mut().insert(recipe);
+            next.run(request).await
+        }
+    }
+}
+
+pub async fn middleware_character_and_recipe_exist(
+    state: State<AppState>,
+    Path((name, id)): Path<(String, Uuid)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let response_character = get_character_libsql_query(&state, &name).await;
+    let response_recipe = get_recipe_libsql_query(&state, &id).await;
+
+    let character = match response_character {
+        Ok(None) => return Error::CharacterNotFound.into_response(),
+        Err(e) => return e.into_response(),
+        Ok(Some(character)) => character,
+    };
+    request.extensions_mut().insert(character);
+
+    let recipe = match response_recipe {
+        Ok(None) => return Error::RecipeNotFound.into_response(),
+        Err(e) => return e.into_response(),
+        Ok(Some(recipe)) => recipe,
+    };
+    request.extensions_mut().insert(recipe);
+
+    next.run(request).await
+}
@@ -0,0 +1,520 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    AppState,
+    db::{Transaction, json, timestamp},
+    errors::{Error, Result},
+    handlers::{
+        characters::{
+            Character, check_inventory_libsql_query, credit_gold_libsql_query,
+            get_character_in_transaction_libsql_query, get_character_item_libsql_query,
+            get_character_libsql_query, release_gold_libsql_query, reserve_gold_libsql_query,
+            settle_gold_libsql_query,
+        },
+        items::ItemInstance,
+    },
+    into_rows,
+};
+use axum::{
+    extract::{Extension, Json, Path, Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use libsql::de::from_row;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Items and gold swapped between two characters once both of them accepted.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct Trade {
+    pub id: Uuid,
+    pub proposer_name: String,
+    pub recipient_name: String,
+    /// Item instances given by the proposer (whole stacks)
+    #[serde(deserialize_with = "json::deserialize")]
+    pub proposer_items: Vec<Uuid>,
+    /// Item instances given by the recipient (whole stacks)
+    #[serde(deserialize_with = "json::deserialize")]
+    pub recipient_items: Vec<Uuid>,
+    pub proposer_gold: u64,
+    pub recipient_gold: u64,
+    pub proposer_accepted: bool,
+    pub recipient_accepted: bool,
+    pub status: TradeStatus,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub creation_date: DateTime<Utc>,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub end_date: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeStatus {
+    Pending,
+    Completed,
+    Cancelled,
+    Expired,
+}
+
+impl fmt::Display for TradeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradeStatus::Pending => write!(f, "pending"),
+            TradeStatus::Completed => write!(f, "completed"),
+            TradeStatus::Cancelled => write!(f, "cancelled"),
+            TradeStatus::Expired => write!(f, "expired"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct NewTrade {
+    proposer_name: String,
+    recipient_name: String,
+    #[serde(default)]
+    proposer_items: Vec<Uuid>,
+    #[serde(default)]
+    recipient_items: Vec<Uuid>,
+    #[serde(default)]
+    proposer_gold: u64,
+    #[serde(default)]
+    recipient_gold: u64,
+}
+
+/// The character accepting or cancelling the trade.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct TradeParty {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TradeQuery {
+    status: Option<TradeStatus>,
+    /// Only the trades this character is part of
+    character: Option<String>,
+}
+
+impl Trade {
+    fn is_pending(&self, now: DateTime<Utc>) -> bool {
+        self.status == TradeStatus::Pending && now <= self.end_date
+    }
+}
+
+// Soulbound and equipped instances can't be traded
+fn check_tradable(instance: &ItemInstance) -> Result<()> {
+    if instance.soulbound {
+        return Err(Error::ItemSoulbound);
+    }
+    if instance.equipped_slot.is_some() {
+        return Err(Error::ItemEquipped);
+    }
+    Ok(())
+}
+
+// =========================Query functions=========================
+async fn get_trades_libsql_query(
+    state: &State<AppState>,
+    filter: &TradeQuery,
+) -> Result<Vec<Trade>> {
+    let query = state
+        .conn
+        .query(
+            "SELECT * FROM trades WHERE (?1 IS NULL OR status = ?1)
+            AND (?2 IS NULL OR proposer_name = ?2 OR recipient_name = ?2)",
+            (
+                filter.status.map(|status| status.to_string()),
+                filter.character.clone(),
+            ),
+        )
+        .await?;
+    let trades: Vec<Trade> = into_rows(query).await?;
+    Ok(trades)
+}
+
+pub async fn get_trade_libsql_query(state: &State<AppState>, id: &Uuid) -> Result<Option<Trade>> {
+    let mut query = state
+        .conn
+        .query("SELECT * FROM trades WHERE id = ?1", [id.to_string()])
+        .await?;
+    let trade = query.next().await?; //None if there are no more rows
+    trade
+        .map(|row| from_row(&row).map_err(Error::from))
+        .transpose()
+}
+
+/// Marks every pending trade whose end date is before `now` as expired, releasing the gold
+/// reserved by the characters who accepted it.
+pub async fn expire_trades_libsql_query(state: &AppState, now: DateTime<Utc>) -> Result<u64> {
+    let transaction = state.conn.transaction().await?;
+    let query = transaction
+        .query(
+            "SELECT * FROM trades WHERE end_date < ?1 AND status = 'pending'",
+            [timestamp::to_millis(&now)],
+        )
+        .await?;
+    let trades: Vec<Trade> = into_rows(query).await?;
+    for trade in &trades {
+        release_accepted_gold_libsql_query(&transaction, trade).await?;
+    }
+    let expired = transaction
+        .execute(
+            "UPDATE trades SET status = 'expired' WHERE end_date < ?1 AND status = 'pending'",
+            [timestamp::to_millis(&now)],
+        )
+        .await?;
+    transaction.commit().await?;
+    Ok(expired)
+}
+
+/// Gives back the gold reserved by whoever already accepted `trade`.
+async fn release_accepted_gold_libsql_query(
+    transaction: &Transaction<'_>,
+    trade: &Trade,
+) -> Result<()> {
+    if trade.proposer_accepted {
+        release_gold_libsql_query(transaction, &trade.proposer_name, trade.proposer_gold).await?;
+    }
+    if trade.recipient_accepted {
+        release_gold_libsql_query(transaction, &trade.recipient_name, trade.recipient_gold).await?;
+    }
+    Ok(())
+}
+
+/// Hands `items` and `gold` (reserved when `from` accepted) from `from` over to `to`.
+async fn transfer_libsql_query(
+    transaction: &Transaction<'_>,
+    from: &Character,
+    to: &Character,
+    items: &[Uuid],
+    gold: u64,
+) -> Result<()> {
+    for id in items {
+        let mut query = transaction
+            .query(
+                "SELECT * FROM items_instances WHERE id = ?1 AND owner_name = ?2",
+                (id.to_string(), from.name.as_str()),
+            )
+            .await?;
+        let Some(row) = query.next().await? else {
+            return Err(Error::ItemInstanceNotFound);
+        };
+        check_tradable(&from_row(&row)?)?;
+
+        transaction
+            .execute(
+                "UPDATE items_instances SET owner_name = ?1 WHERE id = ?2",
+                (to.name.as_str(), id.to_string()),
+            )
+            .await?;
+    }
+
+    settle_gold_libsql_query(transaction, &from.name, gold).await?;
+    credit_gold_libsql_query(transaction, &to.name, gold).await?;
+    Ok(())
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/trades",
+    tag = "trades",
+    params(TradeQuery),
+    responses(
+        (status = 200, description = "Every trade, optionally filtered by status and character", body = Vec<Trade>),
+        (status = 400, description = "The status is not one of pending, completed, cancelled or expired.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_trades(
+    state: State<AppState>,
+    Query(filter): Query<TradeQuery>,
+) -> Result<Json<Vec<Trade>>> {
+    let trades = get_trades_libsql_query(&state, &filter).await?;
+    Ok(Json(trades))
+}
+
+#[utoipa::path(
+    post,
+    path = "/trades",
+    tag = "trades",
+    request_body = NewTrade,
+    responses(
+        (status = 201, description = "The proposed trade, waiting for both characters to accept it", body = Trade),
+        (status = 400, description = "The trade is empty, between a character and itself, or offers an item instance twice.", body = String, content_type = "text/plain"),
+        (status = 403, description = "A character does not have the gold it offers, or an item is soulbound or equipped.", body = String, content_type = "text/plain"),
+        (status = 404, description = "A character does not exist, or does not own an item instance it offers.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_trade(
+    state: State<AppState>,
+    Json(new_trade): Json<NewTrade>,
+) -> Result<(StatusCode, Json<Trade>)> {
+    if new_trade.proposer_name == new_trade.recipient_name {
+        return Err(Error::InvalidTrade("A character cannot trade with itself."));
+    }
+    if new_trade.proposer_items.is_empty()
+        && new_trade.recipient_items.is_empty()
+        && new_trade.proposer_gold == 0
+        && new_trade.recipient_gold == 0
+    {
+        return Err(Error::InvalidTrade("The trade is empty."));
+    }
+    let offered = new_trade
+        .proposer_items
+        .iter()
+        .chain(&new_trade.recipient_items);
+    let mut unique = HashSet::new();
+    if !offered.clone().all(|id| unique.insert(id)) {
+        return Err(Error::InvalidTrade("An item instance is offered twice."));
+    }
+
+    let sides = [
+        (
+            &new_trade.proposer_name,
+            &new_trade.proposer_items,
+            new_trade.proposer_gold,
+        ),
+        (
+            &new_trade.recipient_name,
+            &new_trade.recipient_items,
+            new_trade.recipient_gold,
+        ),
+    ];
+    for (name, items, gold) in sides {
+        let Some(character) = get_character_libsql_query(&state, name).await? else {
+            return Err(Error::CharacterNotFound);
+        };
+        if gold > character.gold {
+            return Err(Error::InsufficientGold);
+        }
+        for id in items {
+            let Some(instance) = get_character_item_libsql_query(&state, name, id).await? else {
+                return Err(Error::ItemInstanceNotFound);
+            };
+            check_tradable(&instance)?;
+        }
+    }
+
+    let creation_date = timestamp::truncate(state.clock.now());
+    let trade = Trade {
+        id: Uuid::new_v4(),
+        proposer_name: new_trade.proposer_name,
+        recipient_name: new_trade.recipient_name,
+        proposer_items: new_trade.proposer_items,
+        recipient_items: new_trade.recipient_items,
+        proposer_gold: new_trade.proposer_gold,
+        recipient_gold: new_trade.recipient_gold,
+        proposer_accepted: false,
+        recipient_accepted: false,
+        status: TradeStatus::Pending,
+        creation_date,
+        end_date: creation_date
+            + TimeDelta::seconds(state.config.economy.trade_duration_secs as i64),
+    };
+
+    state
+        .conn
+        .execute(
+            "INSERT INTO trades (id, proposer_name, recipient_name, proposer_items, recipient_items,
+            proposer_gold, recipient_gold, proposer_accepted, recipient_accepted, status, creation_date, end_date)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            libsql::params![
+                trade.id.to_string(),
+                trade.proposer_name.as_str(),
+                trade.recipient_name.as_str(),
+                serde_json::to_string(&trade.proposer_items).unwrap(),
+                serde_json::to_string(&trade.recipient_items).unwrap(),
+                trade.proposer_gold,
+                trade.recipient_gold,
+                trade.proposer_accepted,
+                trade.recipient_accepted,
+                trade.status.to_string(),
+                timestamp::to_millis(&trade.creation_date),
+                timestamp::to_millis(&trade.end_date),
+            ],
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(trade)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/trades/{id}",
+    tag = "trades",
+    params(("id" = Uuid, Path, description = "Id of the trade")),
+    responses(
+        (status = 200, description = "The trade", body = Trade),
+        (status = 404, description = "This trade does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_trade(Extension(trade): Extension<Trade>) -> Json<Trade> {
+    Json(trade)
+}
+
+#[utoipa::path(
+    post,
+    path = "/trades/{id}/accept",
+    tag = "trades",
+    request_body = TradeParty,
+    params(("id" = Uuid, Path, description = "Id of the trade")),
+    responses(
+        (status = 200, description = "The trade, completed if both characters accepted it, the gold offered by the accepting character being reserved until then", body = Trade),
+        (status = 403, description = "The character is not part of the trade, or the swap failed (not enough gold, soulbound or equipped item, or full inventory).", body = String, content_type = "text/plain"),
+        (status = 404, description = "The trade does not exist or is not pending, or an item instance is no longer owned.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_trade_accept(
+    state: State<AppState>,
+    Extension(trade): Extension<Trade>,
+    Json(party): Json<TradeParty>,
+) -> Result<Json<Trade>> {
+    let now = state.clock.now();
+
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+    let mut query = transaction
+        .query("SELECT * FROM trades WHERE id = ?1", [trade.id.to_string()])
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::TradeNotFound);
+    };
+    let mut trade: Trade = from_row(&row)?;
+
+    if !trade.is_pending(now) {
+        return Err(Error::TradeNotPending);
+    }
+    // The gold a character offers is reserved once it accepts, and only once
+    if party.name == trade.proposer_name {
+        if !trade.proposer_accepted {
+            reserve_gold_libsql_query(&transaction, &party.name, trade.proposer_gold).await?;
+        }
+        trade.proposer_accepted = true;
+    } else if party.name == trade.recipient_name {
+        if !trade.recipient_accepted {
+            reserve_gold_libsql_query(&transaction, &party.name, trade.recipient_gold).await?;
+        }
+        trade.recipient_accepted = true;
+    } else {
+        return Err(Error::NotTradeParty);
+    }
+
+    if trade.proposer_accepted && trade.recipient_accepted {
+        let proposer =
+            get_character_in_transaction_libsql_query(&transaction, &trade.proposer_name).await?;
+        let recipient =
+            get_character_in_transaction_libsql_query(&transaction, &trade.recipient_name).await?;
+
+        transfer_libsql_query(
+            &transaction,
+            &proposer,
+            &recipient,
+            &trade.proposer_items,
+            trade.proposer_gold,
+        )
+        .await?;
+        transfer_libsql_query(
+            &transaction,
+            &recipient,
+            &proposer,
+            &trade.recipient_items,
+            trade.recipient_gold,
+        )
+        .await?;
+        for character in [&proposer, &recipient] {
+            let slots = character.inventory_slots(&state.config.inventory);
+            check_inventory_libsql_query(&transaction, character, slots).await?;
+        }
+        trade.status = TradeStatus::Completed;
+    }
+
+    transaction
+        .execute(
+            "UPDATE trades SET proposer_accepted = ?1, recipient_accepted = ?2, status = ?3 WHERE id = ?4",
+            (
+                trade.proposer_accepted,
+                trade.recipient_accepted,
+                trade.status.to_string(),
+                trade.id.to_string(),
+            ),
+        )
+        .await?;
+    transaction.commit().await?;
+
+    Ok(Json(trade))
+}
+
+#[utoipa::path(
+    post,
+    path = "/trades/{id}/cancel",
+    tag = "trades",
+    request_body = TradeParty,
+    params(("id" = Uuid, Path, description = "Id of the trade")),
+    responses(
+        (status = 200, description = "The cancelled trade, the gold reserved by its characters given back", body = Trade),
+        (status = 403, description = "The character is not part of the trade.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The trade does not exist or is not pending.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_trade_cancel(
+    state: State<AppState>,
+    Extension(mut trade): Extension<Trade>,
+    Json(party): Json<TradeParty>,
+) -> Result<Json<Trade>> {
+    if party.name != trade.proposer_name && party.name != trade.recipient_name {
+        return Err(Error::NotTradeParty);
+    }
+
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+    let mut query = transaction
+        .query("SELECT * FROM trades WHERE id = ?1", [trade.id.to_string()])
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::TradeNotFound);
+    };
+    trade = from_row(&row)?;
+
+    // Only a pending trade can be cancelled
+    let cancelled = transaction
+        .execute(
+            "UPDATE trades SET status = 'cancelled' WHERE id = ?1 AND status = 'pending' AND end_date >= ?2",
+            (
+                trade.id.to_string(),
+                timestamp::to_millis(&state.clock.now()),
+            ),
+        )
+        .await?;
+    if cancelled == 0 {
+        return Err(Error::TradeNotPending);
+    }
+    release_accepted_gold_libsql_query(&transaction, &trade).await?;
+    transaction.commit().await?;
+
+    trade.status = TradeStatus::Cancelled;
+    Ok(Json(trade))
+}
+
+// =========================Middleware=========================
+pub async fn middleware_trade_exists(
+    state: State<AppState>,
+    Path(id): Path<Uuid>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let response = get_trade_libsql_query(&state, &id).await;
+    match response {
+        Ok(None) => Error::TradeNotFound.into_response(),
+        Err(e) => e.into_response(),
+        Ok(Some(trade)) => {
+            request.extensions_mut().insert(trade);
+            next.run(request).await
+        }
+    }
+}
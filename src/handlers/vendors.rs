@@ -0,0 +1,594 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    AppState,
+    db::{Transaction, timestamp},
+    errors::{Error, Result},
+    handlers::{
+        characters::{
+            add_to_inventory_libsql_query, credit_gold_libsql_query, get_character_libsql_query,
+            spend_gold_libsql_query,
+        },
+        items::{Item, ItemInstance, get_item_libsql_query},
+    },
+    into_rows,
+    reports::{GoldSource, record_gold_flow_libsql_query},
+};
+use axum::{
+    extract::{Extension, Json, Path, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use libsql::de::from_row;
+use rand::{SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+const MAX_QUANTITY: u64 = 1000;
+const MAX_STOCK: u64 = 1_000_000;
+const MAX_RESTOCK_INTERVAL_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// An NPC selling items for gold, and buying any item back.
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct Vendor {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub offers: Vec<VendorOffer>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct VendorOffer {
+    pub item_id: Uuid,
+    pub item_name: String,
+    /// Gold paid for each unit bought from the vendor
+    pub price: u64,
+    /// Gold received for each unit sold back to the vendor, never above the cheapest offer of the
+    /// item by any vendor
+    pub sell_back_price: u64,
+    /// Units left, unlimited when missing
+    pub stock: Option<u64>,
+    pub max_stock: Option<u64>,
+    /// The stock goes back to `max_stock` at this interval
+    pub restock_interval_secs: Option<u64>,
+    #[serde(default, deserialize_with = "timestamp::deserialize_option")]
+    pub restock_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct NewVendor {
+    name: String,
+    #[serde(default)]
+    offers: Vec<NewVendorOffer>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct NewVendorOffer {
+    item_id: Uuid,
+    /// Defaults to the item's base value
+    #[serde(default)]
+    price: Option<u64>,
+    /// Unlimited when missing
+    #[serde(default)]
+    stock: Option<u64>,
+    #[serde(default)]
+    restock_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct VendorPurchase {
+    character_name: String,
+    item_id: Uuid,
+    #[serde(default = "default_quantity")]
+    quantity: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct VendorSale {
+    character_name: String,
+    instance_id: Uuid,
+    #[serde(default = "default_quantity")]
+    quantity: u64,
+}
+
+fn default_quantity() -> u64 {
+    1
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct VendorReceipt {
+    pub vendor_id: Uuid,
+    pub character_name: String,
+    pub item_id: Uuid,
+    pub quantity: u64,
+    /// Gold paid by the character when buying, received when selling
+    pub gold: u64,
+    /// The character's instances of the item after the operation
+    pub instances: Vec<ItemInstance>,
+}
+
+fn check_quantity(quantity: u64) -> Result<()> {
+    if quantity == 0 || quantity > MAX_QUANTITY {
+        return Err(Error::InvalidVendor(
+            "The quantity must be between 1 and 1000.",
+        ));
+    }
+    Ok(())
+}
+
+/// What a vendor pays for one of an item, `percent` (at most 100) of its base value, computed so
+/// that it can't overflow.
+fn sell_back_price(base_value: u64, percent: u64) -> u64 {
+    base_value / 100 * percent + base_value % 100 * percent / 100
+}
+
+// =========================Query functions=========================
+async fn get_vendor_offers_libsql_query(
+    state: &State<AppState>,
+    vendor_id: &Uuid,
+) -> Result<Vec<VendorOffer>> {
+    let query = state
+        .conn
+        .query(
+            "SELECT o.item_id, i.name AS item_name, o.price,
+            MIN(i.base_value / 100 * ?2 + i.base_value % 100 * ?2 / 100, (SELECT MIN(price) FROM vendor_offers WHERE item_id = o.item_id)) AS sell_back_price,
+            o.stock, o.max_stock, o.restock_interval_secs, o.restock_date
+            FROM vendor_offers o JOIN items i ON i.id = o.item_id
+            WHERE o.vendor_id = ?1 ORDER BY i.name",
+            (
+                vendor_id.to_string(),
+                state.config.economy.vendor_sell_back_percent,
+            ),
+        )
+        .await?;
+    let offers: Vec<VendorOffer> = into_rows(query).await?;
+    Ok(offers)
+}
+
+async fn get_vendors_libsql_query(state: &State<AppState>) -> Result<Vec<Vendor>> {
+    let query = state
+        .conn
+        .query("SELECT * FROM vendors ORDER BY name", ())
+        .await?;
+    let mut vendors: Vec<Vendor> = into_rows(query).await?;
+    for vendor in &mut vendors {
+        vendor.offers = get_vendor_offers_libsql_query(state, &vendor.id).await?;
+    }
+    Ok(vendors)
+}
+
+pub async fn get_vendor_libsql_query(state: &State<AppState>, id: &Uuid) -> Result<Option<Vendor>> {
+    let mut query = state
+        .conn
+        .query("SELECT * FROM vendors WHERE id = ?1", [id.to_string()])
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Ok(None);
+    };
+    let mut vendor: Vendor = from_row(&row)?;
+    vendor.offers = get_vendor_offers_libsql_query(state, &vendor.id).await?;
+    Ok(Some(vendor))
+}
+
+/// Refills the stock of every offer whose restock date passed, and schedules the next restock.
+pub async fn restock_vendors_libsql_query(state: &AppState, now: DateTime<Utc>) -> Result<u64> {
+    // Skipped restocks are not made up for, the schedule just moves past `now`
+    let restocked = state
+        .conn
+        .execute(
+            "UPDATE vendor_offers SET stock = max_stock,
+            restock_date = restock_date + restock_interval_secs * 1000 * ((?1 - restock_date) / (restock_interval_secs * 1000) + 1)
+            WHERE restock_date <= ?1",
+            [timestamp::to_millis(&now)],
+        )
+        .await?;
+    Ok(restocked)
+}
+
+async fn get_owned_instances_libsql_query(
+    transaction: &Transaction<'_>,
+    owner: &str,
+    item_id: &Uuid,
+) -> Result<Vec<ItemInstance>> {
+    let query = transaction
+        .query(
+            "SELECT * FROM items_instances WHERE owner_name = ?1 AND item_id = ?2",
+            (owner, item_id.to_string()),
+        )
+        .await?;
+    let instances: Vec<ItemInstance> = into_rows(query).await?;
+    Ok(instances)
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/vendors",
+    tag = "vendors",
+    responses(
+        (status = 200, description = "Every vendor with what it sells", body = Vec<Vendor>),
+    )
+)]
+pub async fn get_vendors(state: State<AppState>) -> Result<Json<Vec<Vendor>>> {
+    let vendors = get_vendors_libsql_query(&state).await?;
+    Ok(Json(vendors))
+}
+
+#[utoipa::path(
+    post,
+    path = "/vendors",
+    tag = "vendors",
+    request_body = NewVendor,
+    responses(
+        (status = 201, description = "The created vendor", body = Vendor),
+        (status = 400, description = "The name provided is empty, or an offer is not valid or priced below the sell-back price.", body = String, content_type = "text/plain"),
+        (status = 404, description = "An offered item does not exist.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_vendor(
+    state: State<AppState>,
+    Json(new_vendor): Json<NewVendor>,
+) -> Result<(StatusCode, Json<Vendor>)> {
+    if new_vendor.name.is_empty() {
+        return Err(Error::EmptyName);
+    }
+    let mut item_ids = BTreeSet::new();
+    let mut items = Vec::with_capacity(new_vendor.offers.len());
+    for offer in &new_vendor.offers {
+        if !item_ids.insert(offer.item_id) {
+            return Err(Error::InvalidVendor(
+                "An item cannot be offered twice by a vendor.",
+            ));
+        }
+        if offer
+            .stock
+            .is_some_and(|stock| stock == 0 || stock > MAX_STOCK)
+        {
+            return Err(Error::InvalidVendor(
+                "A limited stock must be between 1 and 1000000.",
+            ));
+        }
+        match (offer.stock, offer.restock_interval_secs) {
+            (_, Some(secs)) if secs == 0 || secs > MAX_RESTOCK_INTERVAL_SECS => {
+                return Err(Error::InvalidVendor(
+                    "The restock interval must be between 1 second and a year.",
+                ));
+            }
+            (None, Some(_)) => {
+                return Err(Error::InvalidVendor(
+                    "Only a limited stock can be restocked.",
+                ));
+            }
+            _ => {}
+        }
+        let Some(item) = get_item_libsql_query(&state, &offer.item_id).await? else {
+            return Err(Error::ItemNotFound);
+        };
+        // Otherwise buying and selling back would make gold out of nothing
+        let sell_back_price = sell_back_price(
+            item.base_value,
+            state.config.economy.vendor_sell_back_percent,
+        );
+        if offer.price.is_some_and(|price| price < sell_back_price) {
+            return Err(Error::InvalidVendor(
+                "The price cannot be below the sell-back price.",
+            ));
+        }
+        items.push(item);
+    }
+
+    let id = Uuid::new_v4();
+    let now = state.clock.now();
+    let transaction = state.conn.transaction().await?;
+    transaction
+        .execute(
+            "INSERT INTO vendors (id, name) VALUES (?1, ?2)",
+            (id.to_string(), new_vendor.name.as_str()),
+        )
+        .await?;
+    for (offer, item) in new_vendor.offers.iter().zip(&items) {
+        let restock_date = offer
+            .restock_interval_secs
+            .map(|secs| timestamp::to_millis(&(now + TimeDelta::seconds(secs as i64))));
+        transaction
+            .execute(
+                "INSERT INTO vendor_offers (vendor_id, item_id, price, stock, max_stock, restock_interval_secs, restock_date)
+                VALUES (?1, ?2, ?3, ?4, ?4, ?5, ?6)",
+                (
+                    id.to_string(),
+                    item.id.to_string(),
+                    offer.price.unwrap_or(item.base_value),
+                    offer.stock.map(|stock| stock as i64),
+                    offer.restock_interval_secs.map(|secs| secs as i64),
+                    restock_date,
+                ),
+            )
+            .await?;
+    }
+    transaction.commit().await?;
+
+    let Some(vendor) = get_vendor_libsql_query(&state, &id).await? else {
+        return Err(Error::VendorNotFound);
+    };
+    Ok((StatusCode::CREATED, Json(vendor)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/vendors/{id}",
+    tag = "vendors",
+    params(("id" = Uuid, Path, description = "Id of the vendor")),
+    responses(
+        (status = 200, description = "The vendor with what it sells", body = Vendor),
+        (status = 404, description = "This vendor does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_vendor(Extension(vendor): Extension<Vendor>) -> Json<Vendor> {
+    Json(vendor)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/vendors/{id}",
+    tag = "vendors",
+    params(("id" = Uuid, Path, description = "Id of the vendor")),
+    responses(
+        (status = 200, description = "The deleted vendor", body = Vendor),
+        (status = 404, description = "This vendor does not exist.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn delete_vendor(
+    state: State<AppState>,
+    Extension(vendor): Extension<Vendor>,
+) -> Result<Json<Vendor>> {
+    state
+        .conn
+        .execute("DELETE FROM vendors WHERE id = ?1", [vendor.id.to_string()])
+        .await?;
+
+    Ok(Json(vendor))
+}
+
+#[utoipa::path(
+    post,
+    path = "/vendors/{id}/buy",
+    tag = "vendors",
+    request_body = VendorPurchase,
+    params(("id" = Uuid, Path, description = "Id of the vendor")),
+    responses(
+        (status = 201, description = "The bought items, now in the character's inventory", body = VendorReceipt),
+        (status = 400, description = "The quantity is not valid, or the purchase costs too much gold.", body = String, content_type = "text/plain"),
+        (status = 403, description = "The character does not have enough gold, the vendor is out of stock, or the inventory is full.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The vendor or the character does not exist, or the vendor does not sell this item.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_vendor_buy(
+    state: State<AppState>,
+    Extension(vendor): Extension<Vendor>,
+    Json(purchase): Json<VendorPurchase>,
+) -> Result<(StatusCode, Json<VendorReceipt>)> {
+    check_quantity(purchase.quantity)?;
+    let Some(character) = get_character_libsql_query(&state, &purchase.character_name).await?
+    else {
+        return Err(Error::CharacterNotFound);
+    };
+    let Some(offer) = vendor
+        .offers
+        .iter()
+        .find(|offer| offer.item_id == purchase.item_id)
+    else {
+        return Err(Error::NotSoldByVendor);
+    };
+    let Some(item) = get_item_libsql_query(&state, &offer.item_id).await? else {
+        return Err(Error::ItemNotFound);
+    };
+    let slots = character.inventory_slots(&state.config.inventory);
+
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+    let taken = transaction
+        .execute(
+            "UPDATE vendor_offers SET stock = stock - ?1
+            WHERE vendor_id = ?2 AND item_id = ?3 AND (stock IS NULL OR stock >= ?1)",
+            (
+                purchase.quantity,
+                vendor.id.to_string(),
+                item.id.to_string(),
+            ),
+        )
+        .await?;
+    if taken == 0 {
+        return Err(Error::OutOfStock);
+    }
+
+    // The price is read again in case the offer changed since the vendor was loaded
+    let mut query = transaction
+        .query(
+            "SELECT price FROM vendor_offers WHERE vendor_id = ?1 AND item_id = ?2",
+            (vendor.id.to_string(), item.id.to_string()),
+        )
+        .await?;
+    let price = match query.next().await? {
+        Some(row) => row.get::<u64>(0)?,
+        None => return Err(Error::NotSoldByVendor),
+    };
+    let Some(gold) = price
+        .checked_mul(purchase.quantity)
+        .filter(|gold| *gold <= i64::MAX as u64)
+    else {
+        return Err(Error::InvalidVendor("The purchase is too large."));
+    };
+    spend_gold_libsql_query(&transaction, &character.name, gold).await?;
+    record_gold_flow_libsql_query(
+        &transaction,
+        GoldSource::VendorPurchase,
+        &character.name,
+        -(gold as i64),
+        state.clock.now(),
+    )
+    .await?;
+
+    let mut rng = StdRng::from_entropy();
+    let mut bought = BTreeMap::new();
+    for _ in 0..purchase.quantity {
+        let Some(instance) =
+            add_to_inventory_libsql_query(&transaction, &character, &item, slots, &mut rng).await?
+        else {
+            return Err(Error::InventoryFull);
+        };
+        bought.insert(instance.id, instance);
+    }
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(VendorReceipt {
+            vendor_id: vendor.id,
+            character_name: character.name,
+            item_id: item.id,
+            quantity: purchase.quantity,
+            gold,
+            instances: bought.into_values().collect(),
+        }),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/vendors/{id}/sell",
+    tag = "vendors",
+    request_body = VendorSale,
+    params(("id" = Uuid, Path, description = "Id of the vendor")),
+    responses(
+        (status = 200, description = "The sold items, and what the character has left of them", body = VendorReceipt),
+        (status = 400, description = "The quantity is not valid, above what the instance holds, or the sale is worth too much gold.", body = String, content_type = "text/plain"),
+        (status = 403, description = "The item is equipped.", body = String, content_type = "text/plain"),
+        (status = 404, description = "The vendor or the character does not exist, or the character does not own the item instance.", body = String, content_type = "text/plain"),
+        (status = 422, description = "The body is not a valid JSON document for this route.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_vendor_sell(
+    state: State<AppState>,
+    Extension(vendor): Extension<Vendor>,
+    Json(sale): Json<VendorSale>,
+) -> Result<Json<VendorReceipt>> {
+    check_quantity(sale.quantity)?;
+    let Some(character) = get_character_libsql_query(&state, &sale.character_name).await? else {
+        return Err(Error::CharacterNotFound);
+    };
+
+    // Everything below is rolled back if any step fails
+    let transaction = state.conn.transaction().await?;
+    let mut query = transaction
+        .query(
+            "SELECT * FROM items_instances WHERE id = ?1 AND owner_name = ?2",
+            (sale.instance_id.to_string(), character.name.as_str()),
+        )
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::ItemInstanceNotFound);
+    };
+    let instance: ItemInstance = from_row(&row)?;
+    if instance.equipped_slot.is_some() {
+        return Err(Error::ItemEquipped);
+    }
+    if sale.quantity > instance.quantity {
+        return Err(Error::InvalidVendor(
+            "The instance does not hold that many items.",
+        ));
+    }
+
+    let mut query = transaction
+        .query(
+            "SELECT * FROM items WHERE id = ?1",
+            [instance.item_id.to_string()],
+        )
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Err(Error::ItemNotFound);
+    };
+    let item: Item = from_row(&row)?;
+
+    if sale.quantity == instance.quantity {
+        transaction
+            .execute(
+                "DELETE FROM items_instances WHERE id = ?1",
+                [instance.id.to_string()],
+            )
+            .await?;
+    } else {
+        transaction
+            .execute(
+                "UPDATE items_instances SET quantity = quantity - ?1 WHERE id = ?2",
+                (sale.quantity, instance.id.to_string()),
+            )
+            .await?;
+    }
+    // The base value can have been raised since the item was offered, selling back never pays
+    // more than buying it again from the cheapest vendor costs
+    let mut query = transaction
+        .query(
+            "SELECT MIN(price) FROM vendor_offers WHERE item_id = ?1",
+            [item.id.to_string()],
+        )
+        .await?;
+    let cheapest_offer = match query.next().await? {
+        Some(row) => row.get::<Option<u64>>(0)?,
+        None => None,
+    };
+    let sell_back_price = sell_back_price(
+        item.base_value,
+        state.config.economy.vendor_sell_back_percent,
+    )
+    .min(cheapest_offer.unwrap_or(u64::MAX));
+    let Some(gold) = sell_back_price
+        .checked_mul(sale.quantity)
+        .filter(|gold| *gold <= i64::MAX as u64)
+    else {
+        return Err(Error::InvalidVendor("The sale is too large."));
+    };
+    credit_gold_libsql_query(&transaction, &character.name, gold).await?;
+    record_gold_flow_libsql_query(
+        &transaction,
+        GoldSource::VendorSale,
+        &character.name,
+        gold as i64,
+        state.clock.now(),
+    )
+    .await?;
+    let instances =
+        get_owned_instances_libsql_query(&transaction, &character.name, &item.id).await?;
+    transaction.commit().await?;
+
+    Ok(Json(VendorReceipt {
+        vendor_id: vendor.id,
+        character_name: character.name,
+        item_id: item.id,
+        quantity: sale.quantity,
+        gold,
+        instances,
+    }))
+}
+
+// =========================Middleware=========================
+pub async fn middleware_vendor_exists(
+    state: State<AppState>,
+    Path(id): Path<Uuid>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let response = get_vendor_libsql_query(&state, &id).await;
+    match response {
+        Ok(None) => Error::VendorNotFound.into_response(),
+        Err(e) => e.into_response(),
+        Ok(Some(vendor)) => {
+            request.extensions_mut().insert(vendor);
+            next.run(request).await
+        }
+    }
+}
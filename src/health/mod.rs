@@ -0,0 +1,55 @@
+use axum::{
+    extract::{Json, State},
+    http::StatusCode,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{AppState, db::pending_migrations};
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct Readiness {
+    database: bool,
+    migrations: bool,
+    replica: bool,
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "operations",
+    responses(
+        (status = 200, description = "The server is up"),
+    )
+)]
+pub async fn get_healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "operations",
+    responses(
+        (status = 200, description = "The server is ready to serve traffic", body = Readiness),
+        (status = 503, description = "The database, the migrations or the replica is not ready", body = Readiness),
+    )
+)]
+pub async fn get_readyz(state: State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let database = state.conn.query("SELECT 1", ()).await.is_ok();
+    let migrations = matches!(pending_migrations(&state.conn).await, Ok(0));
+    let replica = state.replica.is_fresh();
+
+    let readiness = Readiness {
+        database,
+        migrations,
+        replica,
+    };
+    let status = if database && migrations && replica {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
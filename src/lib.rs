@@ -0,0 +1,560 @@
+use std::sync::Arc;
+
+use axum::{Router, middleware};
+use futures::TryStreamExt;
+use libsql::{Builder, de::from_row};
+use serde::Deserialize;
+use tokio::{task::JoinHandle, time::sleep};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    clock::{Clock, SystemClock},
+    config::Config,
+    errors::{Error, Result},
+    handlers::{
+        auctions::{
+            expire_auctions_libsql_query, get_auction, get_auctions, middleware_auction_exists,
+            post_auction,
+        },
+        buy_orders::{
+            get_buy_order, get_buy_orders, get_item_orderbook, middleware_buy_order_exists,
+            post_buy_order, post_buy_order_cancel,
+        },
+        characters::{
+            delete_character, delete_character_auction, delete_character_item_instance,
+            get_character, get_character_auction, get_character_auction_v2, get_character_auctions,
+            get_character_item, get_character_items, get_character_stats, get_characters,
+            middleware_auctioned_item_exists, middleware_character_and_auction_exist,
+            middleware_character_and_item_exist, middleware_character_and_item_instance_exist,
+            middleware_character_exists, patch_character, post_character, post_character_auction,
+            post_character_experience, post_character_item,
+        },
+        equipment::{
+            delete_character_equipment, get_character_equipment,
+            middleware_character_and_equipment_slot_exist, post_character_equipment,
+        },
+        fees::{get_fee_summary, get_fees},
+        items::{
+            delete_item, get_item, get_item_auction, get_item_auctions, get_items,
+            middleware_item_and_auction_exist, middleware_item_exists, patch_item, post_item,
+        },
+        loot::{
+            delete_loot_table, get_loot_table, get_loot_tables,
+            middleware_character_and_loot_table_exist, middleware_loot_table_exists,
+            patch_loot_table, post_character_loot, post_loot_table,
+        },
+        recipes::{
+            delete_recipe, get_recipe, get_recipes, middleware_character_and_recipe_exist,
+            middleware_recipe_exists, post_character_craft, post_recipe,
+        },
+        trades::{
+            expire_trades_libsql_query, get_trade, get_trades, middleware_trade_exists, post_trade,
+            post_trade_accept, post_trade_cancel,
+        },
+        vendors::{
+            delete_vendor, get_vendor, get_vendors, middleware_vendor_exists, post_vendor,
+            post_vendor_buy, post_vendor_sell, restock_vendors_libsql_query,
+        },
+    },
+    health::{get_healthz, get_readyz},
+    metrics::{Metrics, get_metrics, middleware_track_metrics},
+    openapi::{get_docs, get_openapi},
+    rate_limit::{RateLimiter, middleware_rate_limit},
+    replica::{Replica, get_replica_status, middleware_sync_after_write, post_replica_sync},
+    reports::{
+        get_auction_volume, get_gold_flows, get_gold_supply, get_price_trends,
+        get_richest_characters,
+    },
+    versions::{ApiVersion, middleware_deprecated_v1},
+};
+
+pub mod clock;
+pub mod config;
+pub mod db;
+pub mod errors;
+pub mod handlers;
+pub mod health;
+pub mod metrics;
+pub mod openapi;
+pub mod rate_limit;
+pub mod replica;
+pub mod reports;
+pub mod versions;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub conn: db::Connection,
+    pub clock: Arc<dyn Clock>,
+    pub config: Arc<Config>,
+    pub metrics: Arc<Metrics>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub replica: Arc<Replica>,
+}
+
+/// Opens the database described by the config (an embedded replica of the Turso primary, or a
+/// local database when no URL is set) and applies the pending migrations.
+pub async fn init_db(config: Config) -> Result<AppState> {
+    let metrics = Arc::new(Metrics::default());
+
+    let replica = if config.database.is_local() {
+        let db = db::open_local(&config.database.replica_path).await?;
+        Replica::local(db, metrics.clone())
+    } else {
+        let db = Builder::new_remote_replica(
+            config.database.replica_path.clone(),
+            config.database.url.clone(),
+            config.database.auth_token.clone(),
+        )
+        .read_your_writes(true)
+        .build()
+        .await?;
+        // The replica is considered stale once a few syncs in a row have been missed
+        Replica::remote(db, metrics.clone(), config.sync_interval() * 3)
+    };
+
+    let replica = Arc::new(replica);
+    let connection = db::Connection::new(replica.clone(), metrics.clone()).await?;
+    db::run_migrations(&connection).await?;
+
+    Ok(AppState {
+        conn: connection,
+        clock: Arc::new(SystemClock),
+        rate_limiter: Arc::new(RateLimiter::new(&config.rate_limit)),
+        config: Arc::new(config),
+        metrics,
+        replica,
+    })
+}
+
+/// Routes of the RPG resources (characters, items, auctions) as served by one version of the API.
+fn api_routes(state: &AppState, version: ApiVersion) -> Router<AppState> {
+    // Characters router
+    let characters = axum::Router::new().route(
+        "/characters",
+        axum::routing::get(get_characters).post(post_character),
+    );
+
+    let characters_name = axum::Router::new()
+        .route(
+            "/characters/{name}",
+            axum::routing::get(get_character)
+                .patch(patch_character)
+                .delete(delete_character),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_exists,
+        ));
+
+    let characters_name_progression = axum::Router::new()
+        .route(
+            "/characters/{name}/stats",
+            axum::routing::get(get_character_stats),
+        )
+        .route(
+            "/characters/{name}/experience",
+            axum::routing::post(post_character_experience),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_exists,
+        ));
+
+    let characters_name_equipment = axum::Router::new()
+        .route(
+            "/characters/{name}/equipment",
+            axum::routing::get(get_character_equipment),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_exists,
+        ));
+
+    let characters_name_equipment_slot = axum::Router::new()
+        .route(
+            "/characters/{name}/equipment/{slot}",
+            axum::routing::post(post_character_equipment).delete(delete_character_equipment),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_and_equipment_slot_exist,
+        ));
+
+    let characters_name_items = axum::Router::new()
+        .route(
+            "/characters/{name}/items",
+            axum::routing::get(get_character_items),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_exists,
+        ));
+
+    let characters_name_items_item_id = axum::Router::new()
+        .route(
+            "/characters/{name}/items/{item_id}",
+            axum::routing::get(get_character_item).delete(delete_character_item_instance),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_and_item_instance_exist,
+        ));
+
+    let characters_name_items_item_id_post = axum::Router::new()
+        .route(
+            "/characters/{name}/items/{item_id}",
+            axum::routing::post(post_character_item),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_and_item_exist,
+        ));
+
+    let characters_name_auctions = axum::Router::new()
+        .route(
+            "/characters/{name}/auctions",
+            axum::routing::get(get_character_auctions).post(post_character_auction),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_exists,
+        ));
+
+    let characters_name_auctions_id = axum::Router::new()
+        .route(
+            "/characters/{name}/auctions/{id}",
+            match version {
+                ApiVersion::V1 => axum::routing::get(get_character_auction).layer(
+                    middleware::from_fn_with_state(state.clone(), middleware_auctioned_item_exists),
+                ),
+                ApiVersion::V2 => axum::routing::get(get_character_auction_v2),
+            }
+            .delete(delete_character_auction),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_and_auction_exist,
+        ));
+
+    // Items router
+    let items = axum::Router::new().route("/items", axum::routing::get(get_items).post(post_item));
+
+    let items_id = axum::Router::new()
+        .route(
+            "/items/{id}",
+            axum::routing::get(get_item)
+                .patch(patch_item)
+                .delete(delete_item),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_item_exists,
+        ));
+
+    let items_id_auctions = axum::Router::new()
+        .route(
+            "/items/{id}/auctions",
+            axum::routing::get(get_item_auctions),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_item_exists,
+        ));
+
+    let items_id_auctions_auction_id = axum::Router::new()
+        .route(
+            "/items/{id}/auctions/{auction_id}",
+            axum::routing::get(get_item_auction),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_item_and_auction_exist,
+        ));
+
+    let items_id_orderbook = axum::Router::new()
+        .route(
+            "/items/{id}/orderbook",
+            axum::routing::get(get_item_orderbook),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_item_exists,
+        ));
+
+    // Auctions router
+    let auctions = axum::Router::new().route("/auctions", axum::routing::get(get_auctions));
+
+    let auctions_id = axum::Router::new()
+        .route("/auctions/{id}", axum::routing::get(get_auction))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_auction_exists,
+        ));
+
+    let auctions_id_purchase = axum::Router::new()
+        .route("/auctions/{id}/purchase", axum::routing::post(post_auction))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_auction_exists,
+        ));
+
+    // Buy orders router
+    let buy_orders = axum::Router::new().route(
+        "/buy-orders",
+        axum::routing::get(get_buy_orders).post(post_buy_order),
+    );
+
+    let buy_orders_id = axum::Router::new()
+        .route("/buy-orders/{id}", axum::routing::get(get_buy_order))
+        .route(
+            "/buy-orders/{id}/cancel",
+            axum::routing::post(post_buy_order_cancel),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_buy_order_exists,
+        ));
+
+    // Trades router
+    let trades =
+        axum::Router::new().route("/trades", axum::routing::get(get_trades).post(post_trade));
+
+    let trades_id = axum::Router::new()
+        .route("/trades/{id}", axum::routing::get(get_trade))
+        .route(
+            "/trades/{id}/accept",
+            axum::routing::post(post_trade_accept),
+        )
+        .route(
+            "/trades/{id}/cancel",
+            axum::routing::post(post_trade_cancel),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_trade_exists,
+        ));
+
+    // Loot tables router
+    let loot_tables = axum::Router::new().route(
+        "/loot-tables",
+        axum::routing::get(get_loot_tables).post(post_loot_table),
+    );
+
+    let loot_tables_id = axum::Router::new()
+        .route(
+            "/loot-tables/{id}",
+            axum::routing::get(get_loot_table)
+                .patch(patch_loot_table)
+                .delete(delete_loot_table),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_loot_table_exists,
+        ));
+
+    let characters_name_loot = axum::Router::new()
+        .route(
+            "/characters/{name}/loot/{table_id}",
+            axum::routing::post(post_character_loot),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_and_loot_table_exist,
+        ));
+
+    // Recipes router
+    let recipes = axum::Router::new().route(
+        "/recipes",
+        axum::routing::get(get_recipes).post(post_recipe),
+    );
+
+    let recipes_id = axum::Router::new()
+        .route(
+            "/recipes/{id}",
+            axum::routing::get(get_recipe).delete(delete_recipe),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_recipe_exists,
+        ));
+
+    let characters_name_craft = axum::Router::new()
+        .route(
+            "/characters/{name}/craft/{recipe_id}",
+            axum::routing::post(post_character_craft),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_character_and_recipe_exist,
+        ));
+
+    // Fees router
+    let fees = axum::Router::new()
+        .route("/fees", axum::routing::get(get_fees))
+        .route("/fees/summary", axum::routing::get(get_fee_summary));
+
+    // Vendors router
+    let vendors = axum::Router::new().route(
+        "/vendors",
+        axum::routing::get(get_vendors).post(post_vendor),
+    );
+
+    let vendors_id = axum::Router::new()
+        .route(
+            "/vendors/{id}",
+            axum::routing::get(get_vendor).delete(delete_vendor),
+        )
+        .route("/vendors/{id}/buy", axum::routing::post(post_vendor_buy))
+        .route("/vendors/{id}/sell", axum::routing::post(post_vendor_sell))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_vendor_exists,
+        ));
+
+    Router::new()
+        .merge(characters)
+        .merge(characters_name)
+        .merge(characters_name_progression)
+        .merge(characters_name_equipment)
+        .merge(characters_name_equipment_slot)
+        .merge(characters_name_items)
+        .merge(characters_name_items_item_id)
+        .merge(characters_name_items_item_id_post)
+        .merge(characters_name_auctions)
+        .merge(characters_name_auctions_id)
+        .merge(items)
+        .merge(items_id)
+        .merge(items_id_auctions)
+        .merge(items_id_auctions_auction_id)
+        .merge(items_id_orderbook)
+        .merge(auctions)
+        .merge(auctions_id)
+        .merge(auctions_id_purchase)
+        .merge(fees)
+        .merge(buy_orders)
+        .merge(buy_orders_id)
+        .merge(trades)
+        .merge(trades_id)
+        .merge(loot_tables)
+        .merge(loot_tables_id)
+        .merge(characters_name_loot)
+        .merge(recipes)
+        .merge(recipes_id)
+        .merge(characters_name_craft)
+        .merge(vendors)
+        .merge(vendors_id)
+}
+
+/// Router serving the whole RPG API, ready to be served or nested in another app.
+/// The resources are served under /v1 and /v2, the root being a deprecated alias of /v1.
+pub fn build_app(state: AppState) -> Router {
+    // Versioned API routers
+    // Every version shares the same rate limits (the operations routes aren't limited)
+    let rate_limit = middleware::from_fn_with_state(state.clone(), middleware_rate_limit);
+    let v1 = api_routes(&state, ApiVersion::V1)
+        .layer(rate_limit.clone())
+        .layer(middleware::from_fn(middleware_deprecated_v1));
+    let v2 = api_routes(&state, ApiVersion::V2).layer(rate_limit);
+
+    // Metrics router
+    let metrics = axum::Router::new().route("/metrics", axum::routing::get(get_metrics));
+
+    // Health router
+    let health = axum::Router::new()
+        .route("/healthz", axum::routing::get(get_healthz))
+        .route("/readyz", axum::routing::get(get_readyz));
+
+    // Admin router
+    let admin = axum::Router::new()
+        .route("/admin/replica", axum::routing::get(get_replica_status))
+        .route(
+            "/admin/replica/sync",
+            axum::routing::post(post_replica_sync),
+        )
+        .route("/admin/economy/gold", axum::routing::get(get_gold_supply))
+        .route(
+            "/admin/economy/richest",
+            axum::routing::get(get_richest_characters),
+        )
+        .route(
+            "/admin/economy/auctions",
+            axum::routing::get(get_auction_volume),
+        )
+        .route(
+            "/admin/economy/prices",
+            axum::routing::get(get_price_trends),
+        )
+        .route("/admin/economy/flows", axum::routing::get(get_gold_flows));
+
+    // API docs router
+    let docs = axum::Router::new()
+        .route("/openapi.json", axum::routing::get(get_openapi))
+        .route("/docs", axum::routing::get(get_docs));
+
+    // Main router (all routers merged), the root keeps serving v1 for the clients already shipped
+    Router::new()
+        .nest("/v1", v1.clone())
+        .nest("/v2", v2)
+        .merge(v1)
+        .merge(metrics)
+        .merge(health)
+        .merge(admin)
+        .merge(docs)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_sync_after_write,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            middleware_track_metrics,
+        ))
+        .with_state(state)
+}
+
+pub async fn into_rows<T>(rows: libsql::Rows) -> Result<Vec<T>>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    let items = rows
+        .into_stream()
+        .map_err(Error::from)
+        .and_then(|r| async move { from_row::<T>(&r).map_err(Error::from) })
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(items)
+}
+
+pub fn spawn_auction_status_updater(
+    state: AppState,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = sleep(state.config.expiry_interval()) => {}
+            }
+
+            let now = state.clock.now();
+            let result = expire_auctions_libsql_query(&state, now).await;
+
+            match result {
+                Ok(expired) => {
+                    state.metrics.observe_expiry_sweep(expired);
+                    println!("Auction statuses updated.")
+                }
+                Err(e) => println!("Failed to update auction statuses: {}", e),
+            }
+
+            // Stale trade proposals expire along the way
+            if let Err(e) = expire_trades_libsql_query(&state, now).await {
+                println!("Failed to update trade statuses: {}", e);
+            }
+
+            // Vendors restock on their own schedule
+            if let Err(e) = restock_vendors_libsql_query(&state, now).await {
+                println!("Failed to restock vendors: {}", e);
+            }
+        }
+    })
+}
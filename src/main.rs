@@ -1,298 +1,110 @@
-use std::time::Duration;
-
-use axum::{Router, middleware};
-use chrono::Utc;
-use errors::{Error, Result};
-use futures::TryStreamExt;
-
-use libsql::Builder;
-use libsql::de::from_row;
-use serde::Deserialize;
-
-use handlers::{
-    auctions::get_auctions,
-    characters::{
-        delete_character, get_character, get_characters, middleware_character_exists,
-        patch_character, post_character,
-    },
-    items::{delete_item, get_item, get_items, middleware_item_exists, patch_item, post_item},
+use std::{future::IntoFuture, net::SocketAddr};
+
+use clap::Parser;
+use rpg_server::{
+    build_app,
+    config::{Cli, Config},
+    errors::Result,
+    init_db,
+    replica::spawn_replica_syncer,
+    spawn_auction_status_updater,
 };
-use tokio::time::sleep;
-
-use crate::handlers::{
-    auctions::{get_auction, middleware_auction_exists, post_auction},
-    characters::{
-        delete_character_auction, delete_character_item_instance, get_character_auction,
-        get_character_auctions, get_character_item, get_character_items,
-        middleware_character_and_auction_exist, middleware_character_and_item_exist,
-        middleware_character_and_item_instance_exist, post_character_auction, post_character_item,
-    },
-    items::{get_item_auction, get_item_auctions, middleware_item_instance_and_auction_exist},
-};
-mod errors;
-mod handlers;
+use tokio::{signal, time::timeout};
+use tokio_util::sync::CancellationToken;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Setting up DB
+    // Loading config (file < environment < flags)
     dotenv::dotenv().ok();
-    let db_url = std::env::var("TURSO_DATABASE_URL").expect("TURSO DATABASE URL not set");
-    let db_token = std::env::var("TURSO_AUTH_TOKEN").expect("TURSO DATABASE TOKEN not set");
+    let cli = Cli::parse();
+    let config = Config::load(&cli)?;
+    if cli.print_config {
+        print!("{}", config.to_redacted_toml());
+        return config.validate();
+    }
+    config.validate()?;
 
-    let db = Builder::new_remote_replica("local.db", db_url, db_token)
-        .build()
+    // Setting up DB
+    let state = init_db(config).await?;
+
+    let router = build_app(state.clone());
+    let listener = tokio::net::TcpListener::bind(&state.config.server.bind_address)
         .await
         .unwrap();
 
-    let connection = db.connect()?;
-
-    let state = AppState { conn: connection };
-
-    // Creating characters DB if it doesn't already exist
-    state
-        .conn
-        .execute(
-            "CREATE TABLE IF NOT EXISTS characters (
-        name TEXT PRIMARY KEY,
-        class TEXT NOT NULL CHECK (class IN ('warrior', 'mage', 'ranger')),
-        gold INTEGER NOT NULL CHECK (gold >= 0)
-        )",
-            (),
-        )
-        .await?;
-
-    // Creating items DB if it doesn't already exist
-    state
-        .conn
-        .execute(
-            "CREATE TABLE IF NOT EXISTS items (
-        id TEXT PRIMARY KEY CHECK (length(id) = 36),
-        name TEXT NOT NULL UNIQUE
-        )",
-            (),
-        )
-        .await?;
-
-    // Creating items_instances DB if it doesn't already exist
-    state
-        .conn
-        .execute(
-            "CREATE TABLE IF NOT EXISTS items_instances (
-        id TEXT PRIMARY KEY CHECK (length(id) = 36),
-        item_name TEXT NOT NULL,
-        item_id TEXT NOT NULL CHECK (length(item_id) = 36),
-        owner_name TEXT NOT NULL,
-        FOREIGN KEY (item_name) REFERENCES items(name) ON DELETE CASCADE ON UPDATE CASCADE,
-        FOREIGN KEY (item_id) REFERENCES items(id) ON DELETE CASCADE,
-        FOREIGN KEY (owner_name) REFERENCES characters(name) ON DELETE CASCADE
-        )",
-            (),
-        )
-        .await?;
-
-    // Creating auctions DB if it doesn't already exist
-    state
-        .conn
-        .execute(
-            "CREATE TABLE IF NOT EXISTS auctions (
-        id TEXT PRIMARY KEY CHECK (length(id) = 36),
-        auctioned_item_id TEXT NOT NULL CHECK (length(item_auctioned_id) = 36),
-        seller_name TEXT NOT NULL,
-        creation_date TEXT NOT NULL, 
-        end_date TEXT NOT NULL, 
-        price INTEGER NOT NULL CHECK (price >= 0), 
-        status TEXT NOT NULL CHECK (status IN ('active', 'sold', 'expired')), 
-        FOREIGN KEY (auctioned_item_id) REFERENCES items(id) ON DELETE CASCADE,
-        FOREIGN KEY (seller_name) REFERENCES characters(name) ON DELETE CASCADE
-        )",
-            (),
+    // Shared by the server and every background task so they all stop together
+    let shutdown = CancellationToken::new();
+
+    state.replica.sync().await?;
+    let tasks = vec![
+        spawn_auction_status_updater(state.clone(), shutdown.clone()),
+        spawn_replica_syncer(
+            state.replica.clone(),
+            state.config.sync_interval(),
+            shutdown.clone(),
+        ),
+    ];
+
+    let mut server = tokio::spawn(
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
         )
-        .await?;
-
-    // Characters router
-    let characters = axum::Router::new().route(
-        "/characters",
-        axum::routing::get(get_characters).post(post_character),
+        .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+        .into_future(),
     );
-
-    let characters_name = axum::Router::new()
-        .route(
-            "/characters/{name}",
-            axum::routing::get(get_character)
-                .patch(patch_character)
-                .delete(delete_character),
-        )
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_character_exists,
-        ));
-
-    let characters_name_items = axum::Router::new()
-        .route(
-            "/characters/{name}/items",
-            axum::routing::get(get_character_items),
-        )
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_character_exists,
-        ));
-
-    let characters_name_items_item_id = axum::Router::new()
-        .route(
-            "/characters/{name}/items/{item_id}",
-            axum::routing::get(get_character_item).delete(delete_character_item_instance),
-        )
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_character_and_item_instance_exist,
-        ));
-
-    let characters_name_items_item_id_post = axum::Router::new()
-        .route(
-            "/characters/{name}/items/{item_id}",
-            axum::routing::post(post_character_item),
-        )
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_character_and_item_exist,
-        ));
-
-    let characters_name_auctions = axum::Router::new()
-        .route(
-            "/characters/{name}/auctions",
-            axum::routing::get(get_character_auctions).post(post_character_auction),
-        )
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_character_exists,
-        ));
-
-    let characters_name_auctions_id = axum::Router::new()
-        .route(
-            "/characters/{name}/auctions/{id}",
-            axum::routing::get(get_character_auction).delete(delete_character_auction),
-        )
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_character_and_auction_exist,
-        ));
-
-    // Items router
-    let items = axum::Router::new().route("/items", axum::routing::get(get_items).post(post_item));
-
-    let items_id = axum::Router::new()
-        .route(
-            "/items/{id}",
-            axum::routing::get(get_item)
-                .patch(patch_item)
-                .delete(delete_item),
-        )
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_item_exists,
-        ));
-
-    let items_id_auctions = axum::Router::new()
-        .route(
-            "/items/{id}/auctions",
-            axum::routing::get(get_item_auctions),
-        )
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_item_exists,
-        ));
-
-    let items_id_auctions_auction_id = axum::Router::new()
-        .route(
-            "/items/{id}/auctions/{auction_id}",
-            axum::routing::get(get_item_auction),
-        )
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_item_instance_and_auction_exist,
-        ));
-
-    // Auctions router
-    let auctions = axum::Router::new().route("/auctions", axum::routing::get(get_auctions));
-
-    let auctions_id = axum::Router::new()
-        .route("/auctions/{id}", axum::routing::get(get_auction))
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_auction_exists,
-        ));
-
-    let auctions_id_purchase = axum::Router::new()
-        .route("/auctions/{id}/purchase", axum::routing::post(post_auction))
-        .layer(middleware::from_fn_with_state(
-            state.clone(),
-            middleware_auction_exists,
-        ));
-
-    // Main router (all routers merged)
-    let router = Router::new()
-        .merge(characters)
-        .merge(characters_name)
-        .merge(characters_name_items)
-        .merge(characters_name_items_item_id)
-        .merge(characters_name_items_item_id_post)
-        .merge(characters_name_auctions)
-        .merge(characters_name_auctions_id)
-        .merge(items)
-        .merge(items_id)
-        .merge(items_id_auctions)
-        .merge(items_id_auctions_auction_id)
-        .merge(auctions)
-        .merge(auctions_id)
-        .merge(auctions_id_purchase)
-        .with_state(state.clone());
-    let address: &'static str = "0.0.0.0:3001";
-    let listener = tokio::net::TcpListener::bind(address).await.unwrap();
-
-    spawn_auction_status_updater(state.conn);
-
-    db.sync().await?;
-    axum::serve(listener, router).await.unwrap();
+    let server_stopped = tokio::select! {
+        _ = shutdown_signal() => {
+            println!("Shutdown requested, draining requests...");
+            false
+        }
+        result = &mut server => {
+            println!("Server stopped unexpectedly: {:?}", result);
+            true
+        }
+    };
+    shutdown.cancel();
+
+    // A stopped server has nothing left to drain, and its handle can't be awaited again
+    if !server_stopped
+        && timeout(state.config.drain_timeout(), &mut server)
+            .await
+            .is_err()
+    {
+        println!("Drain timeout reached, dropping remaining requests.");
+        server.abort();
+    }
+    for task in tasks {
+        let _ = task.await;
+    }
+
+    // Last sync so the replica on disk is as fresh as possible for the next boot
+    state.replica.sync().await?;
+    println!("Shutdown complete.");
 
     Ok(())
 }
 
-#[derive(Clone)]
-pub struct AppState {
-    pub conn: libsql::Connection,
-}
-
-pub async fn into_rows<T>(rows: libsql::Rows) -> Result<Vec<T>>
-where
-    T: for<'de> Deserialize<'de>,
-{
-    let items = rows
-        .into_stream()
-        .map_err(Error::from)
-        .and_then(|r| async move { from_row::<T>(&r).map_err(Error::from) })
-        .try_collect::<Vec<_>>()
-        .await?;
-    Ok(items)
-}
-
-fn spawn_auction_status_updater(conn: libsql::Connection) {
-    tokio::spawn(async move {
-        loop {
-            sleep(Duration::from_secs(30)).await;
-
-            let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-            let result = conn
-                .execute(
-                    "UPDATE auctions SET status = 'expired' WHERE end_date < ?1 AND status = 'active'",
-                    [now],
-                )
-                .await;
-
-            match result {
-                Ok(_) => println!("Auction statuses updated."),
-                Err(e) => println!("Failed to update auction statuses: {}", e),
-            }
-        }
-    });
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install the SIGINT handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install the SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 }
@@ -0,0 +1,311 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header::CONTENT_TYPE,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{AppState, errors::Result};
+
+// Default Prometheus buckets (in seconds)
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+const SWEEP_BUCKETS: &[f64] = &[0.0, 1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0];
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    buckets: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(buckets: &'static [f64]) -> Self {
+        Histogram {
+            buckets,
+            counts: vec![0; buckets.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.buckets.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        for (bound, count) in self.buckets.iter().zip(self.counts.iter()) {
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{{labels}{separator}le=\"{bound}\"}} {count}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{separator}le=\"+Inf\"}} {}",
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+#[derive(Debug)]
+struct Registry {
+    http_requests: BTreeMap<(String, String, u16), u64>,
+    http_durations: BTreeMap<(String, String), Histogram>,
+    db_queries: BTreeMap<(String, String), Histogram>,
+    expiry_sweeps: Histogram,
+    replica_syncs: Histogram,
+    replica_sync_errors: u64,
+}
+
+/// In-process metrics, rendered in the Prometheus text format by `GET /metrics`.
+#[derive(Debug)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            registry: Mutex::new(Registry {
+                http_requests: BTreeMap::new(),
+                http_durations: BTreeMap::new(),
+                db_queries: BTreeMap::new(),
+                expiry_sweeps: Histogram::new(SWEEP_BUCKETS),
+                replica_syncs: Histogram::new(LATENCY_BUCKETS),
+                replica_sync_errors: 0,
+            }),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn observe_http_request(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .http_requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_insert(0) += 1;
+        registry
+            .http_durations
+            .entry((method.to_string(), route.to_string()))
+            .or_insert_with(|| Histogram::new(LATENCY_BUCKETS))
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn observe_db_query(&self, sql: &str, duration: Duration) {
+        let (operation, table) = describe_sql(sql);
+        let mut registry = self.registry.lock().unwrap();
+        registry
+            .db_queries
+            .entry((operation, table))
+            .or_insert_with(|| Histogram::new(LATENCY_BUCKETS))
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn observe_expiry_sweep(&self, expired: u64) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.expiry_sweeps.observe(expired as f64);
+    }
+
+    pub fn observe_replica_sync(&self, duration: Duration, success: bool) {
+        let mut registry = self.registry.lock().unwrap();
+        registry.replica_syncs.observe(duration.as_secs_f64());
+        if !success {
+            registry.replica_sync_errors += 1;
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        let registry = self.registry.lock().unwrap();
+
+        out.push_str("# HELP rpg_http_requests_total Total number of HTTP requests.\n");
+        out.push_str("# TYPE rpg_http_requests_total counter\n");
+        for ((method, route, status), count) in &registry.http_requests {
+            let _ = writeln!(
+                out,
+                "rpg_http_requests_total{{method=\"{method}\",route=\"{route}\",status=\"{status}\"}} {count}"
+            );
+        }
+
+        out.push_str(
+            "# HELP rpg_http_request_duration_seconds HTTP request latencies in seconds.\n",
+        );
+        out.push_str("# TYPE rpg_http_request_duration_seconds histogram\n");
+        for ((method, route), histogram) in &registry.http_durations {
+            histogram.render(
+                out,
+                "rpg_http_request_duration_seconds",
+                &format!("method=\"{method}\",route=\"{route}\""),
+            );
+        }
+
+        out.push_str("# HELP rpg_db_query_duration_seconds Database query latencies in seconds.\n");
+        out.push_str("# TYPE rpg_db_query_duration_seconds histogram\n");
+        for ((operation, table), histogram) in &registry.db_queries {
+            histogram.render(
+                out,
+                "rpg_db_query_duration_seconds",
+                &format!("operation=\"{operation}\",table=\"{table}\""),
+            );
+        }
+
+        out.push_str(
+            "# HELP rpg_auction_expiry_sweep_expired Auctions expired per status updater sweep.\n",
+        );
+        out.push_str("# TYPE rpg_auction_expiry_sweep_expired histogram\n");
+        registry
+            .expiry_sweeps
+            .render(out, "rpg_auction_expiry_sweep_expired", "");
+
+        out.push_str(
+            "# HELP rpg_replica_sync_duration_seconds Embedded replica sync durations in seconds.\n",
+        );
+        out.push_str("# TYPE rpg_replica_sync_duration_seconds histogram\n");
+        registry
+            .replica_syncs
+            .render(out, "rpg_replica_sync_duration_seconds", "");
+
+        out.push_str(
+            "# HELP rpg_replica_sync_errors_total Total number of failed embedded replica syncs.\n",
+        );
+        out.push_str("# TYPE rpg_replica_sync_errors_total counter\n");
+        let _ = writeln!(
+            out,
+            "rpg_replica_sync_errors_total {}",
+            registry.replica_sync_errors
+        );
+    }
+}
+
+// Turns "SELECT * FROM auctions WHERE ..." into ("select", "auctions")
+fn describe_sql(sql: &str) -> (String, String) {
+    let words: Vec<String> = sql
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric() && c != '_'))
+        .map(|word| word.to_lowercase())
+        .collect();
+    let operation = words.first().cloned().unwrap_or_default();
+    let table_keyword = match operation.as_str() {
+        "select" | "delete" => "from",
+        "insert" => "into",
+        "update" => "update",
+        _ => "table",
+    };
+    let table = words
+        .iter()
+        .position(|word| word == table_keyword)
+        .and_then(|index| {
+            words[index + 1..]
+                .iter()
+                .find(|word| !matches!(word.as_str(), "if" | "not" | "exists"))
+        })
+        .cloned()
+        .unwrap_or_else(|| "none".to_string());
+    (operation, table)
+}
+
+// =========================Query functions=========================
+async fn get_auction_status_counts_libsql_query(
+    state: &State<AppState>,
+) -> Result<Vec<(String, u64)>> {
+    let mut query = state
+        .conn
+        .query("SELECT status, COUNT(*) FROM auctions GROUP BY status", ())
+        .await?;
+    let mut counts = Vec::new();
+    while let Some(row) = query.next().await? {
+        counts.push((row.get::<String>(0)?, row.get::<u64>(1)?));
+    }
+    Ok(counts)
+}
+
+async fn get_total_gold_libsql_query(state: &State<AppState>) -> Result<u64> {
+    let mut query = state
+        .conn
+        .query(
+            "SELECT COALESCE(SUM(gold + reserved_gold), 0) FROM characters",
+            (),
+        )
+        .await?;
+    let total = match query.next().await? {
+        Some(row) => row.get::<u64>(0)?,
+        None => 0,
+    };
+    Ok(total)
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain; version=0.0.4"),
+    )
+)]
+pub async fn get_metrics(state: State<AppState>) -> Result<Response> {
+    let status_counts = get_auction_status_counts_libsql_query(&state).await?;
+    let total_gold = get_total_gold_libsql_query(&state).await?;
+
+    let mut out = String::new();
+    out.push_str("# HELP rpg_auctions Number of auctions by status.\n");
+    out.push_str("# TYPE rpg_auctions gauge\n");
+    for status in ["active", "sold", "expired"] {
+        let count = status_counts
+            .iter()
+            .find(|(name, _)| name == status)
+            .map_or(0, |(_, count)| *count);
+        let _ = writeln!(out, "rpg_auctions{{status=\"{status}\"}} {count}");
+    }
+    out.push_str("# HELP rpg_gold_in_circulation Total gold owned by all characters, reserved gold included.\n");
+    out.push_str("# TYPE rpg_gold_in_circulation gauge\n");
+    let _ = writeln!(out, "rpg_gold_in_circulation {total_gold}");
+
+    state.metrics.render(&mut out);
+
+    Ok(([(CONTENT_TYPE, "text/plain; version=0.0.4")], out).into_response())
+}
+
+// =========================Middleware=========================
+pub async fn middleware_track_metrics(
+    state: State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", |path| path.as_str())
+        .to_string();
+
+    let response = next.run(request).await;
+
+    state.metrics.observe_http_request(
+        &method,
+        &route,
+        response.status().as_u16(),
+        start.elapsed(),
+    );
+    response
+}
@@ -0,0 +1,246 @@
+use axum::{extract::Json, response::Html};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::{
+        self, Content, Deprecated, ObjectBuilder, ResponseBuilder, Type, header::HeaderBuilder,
+    },
+};
+
+use crate::{handlers, health, metrics, replica, reports};
+
+/// OpenAPI 3.1 description of every route of the router, built from the handlers' annotations.
+/// A route added to `build_app` has to be listed here too (the drift test checks it).
+#[derive(OpenApi)]
+#[openapi(
+    info(description = "REST API managing the characters, items and auctions of an RPG economy."),
+    nest(
+        (path = "/v1", api = ApiV1Doc),
+        (path = "/v2", api = ApiV2Doc),
+    ),
+    paths(
+        health::get_healthz,
+        health::get_readyz,
+        metrics::get_metrics,
+        replica::get_replica_status,
+        replica::post_replica_sync,
+        reports::get_gold_supply,
+        reports::get_richest_characters,
+        reports::get_auction_volume,
+        reports::get_price_trends,
+        reports::get_gold_flows,
+    ),
+    tags(
+        (name = "characters", description = "Characters and what they own"),
+        (name = "items", description = "Item definitions"),
+        (name = "auctions", description = "The auction house"),
+        (name = "trades", description = "Trades between two characters"),
+        (name = "loot", description = "Loot tables and the drops rolled from them"),
+        (name = "crafting", description = "Recipes turning items into other items"),
+        (name = "vendors", description = "NPC vendors buying and selling items"),
+        (name = "orders", description = "Buy orders, matched against the auctions of their item"),
+        (name = "operations", description = "Health, metrics and replica administration"),
+    ),
+    modifiers(&VersionedOperations)
+)]
+pub struct ApiDoc;
+
+// The RPG resources as served under /v1 (and the unprefixed alias)
+#[derive(OpenApi)]
+#[openapi(paths(
+    handlers::characters::get_characters,
+    handlers::characters::post_character,
+    handlers::characters::get_character,
+    handlers::characters::patch_character,
+    handlers::characters::delete_character,
+    handlers::characters::get_character_stats,
+    handlers::characters::post_character_experience,
+    handlers::equipment::get_character_equipment,
+    handlers::equipment::post_character_equipment,
+    handlers::equipment::delete_character_equipment,
+    handlers::characters::get_character_items,
+    handlers::characters::get_character_item,
+    handlers::characters::post_character_item,
+    handlers::characters::delete_character_item_instance,
+    handlers::characters::get_character_auctions,
+    handlers::characters::post_character_auction,
+    handlers::characters::get_character_auction,
+    handlers::characters::delete_character_auction,
+    handlers::items::get_items,
+    handlers::items::post_item,
+    handlers::items::get_item,
+    handlers::items::patch_item,
+    handlers::items::delete_item,
+    handlers::items::get_item_auctions,
+    handlers::items::get_item_auction,
+    handlers::auctions::get_auctions,
+    handlers::auctions::get_auction,
+    handlers::auctions::post_auction,
+    handlers::fees::get_fees,
+    handlers::fees::get_fee_summary,
+    handlers::trades::get_trades,
+    handlers::trades::post_trade,
+    handlers::trades::get_trade,
+    handlers::trades::post_trade_accept,
+    handlers::trades::post_trade_cancel,
+    handlers::loot::get_loot_tables,
+    handlers::loot::post_loot_table,
+    handlers::loot::get_loot_table,
+    handlers::loot::patch_loot_table,
+    handlers::loot::delete_loot_table,
+    handlers::loot::post_character_loot,
+    handlers::recipes::get_recipes,
+    handlers::recipes::post_recipe,
+    handlers::recipes::get_recipe,
+    handlers::recipes::delete_recipe,
+    handlers::recipes::post_character_craft,
+    handlers::vendors::get_vendors,
+    handlers::vendors::post_vendor,
+    handlers::vendors::get_vendor,
+    handlers::vendors::delete_vendor,
+    handlers::vendors::post_vendor_buy,
+    handlers::vendors::post_vendor_sell,
+    handlers::buy_orders::get_buy_orders,
+    handlers::buy_orders::post_buy_order,
+    handlers::buy_orders::get_buy_order,
+    handlers::buy_orders::post_buy_order_cancel,
+    handlers::buy_orders::get_item_orderbook,
+))]
+struct ApiV1Doc;
+
+// The RPG resources as served under /v2
+#[derive(OpenApi)]
+#[openapi(paths(
+    handlers::characters::get_characters,
+    handlers::characters::post_character,
+    handlers::characters::get_character,
+    handlers::characters::patch_character,
+    handlers::characters::delete_character,
+    handlers::characters::get_character_stats,
+    handlers::characters::post_character_experience,
+    handlers::equipment::get_character_equipment,
+    handlers::equipment::post_character_equipment,
+    handlers::equipment::delete_character_equipment,
+    handlers::characters::get_character_items,
+    handlers::characters::get_character_item,
+    handlers::characters::post_character_item,
+    handlers::characters::delete_character_item_instance,
+    handlers::characters::get_character_auctions,
+    handlers::characters::post_character_auction,
+    handlers::characters::get_character_auction_v2,
+    handlers::characters::delete_character_auction,
+    handlers::items::get_items,
+    handlers::items::post_item,
+    handlers::items::get_item,
+    handlers::items::patch_item,
+    handlers::items::delete_item,
+    handlers::items::get_item_auctions,
+    handlers::items::get_item_auction,
+    handlers::auctions::get_auctions,
+    handlers::auctions::get_auction,
+    handlers::auctions::post_auction,
+    handlers::fees::get_fees,
+    handlers::fees::get_fee_summary,
+    handlers::trades::get_trades,
+    handlers::trades::post_trade,
+    handlers::trades::get_trade,
+    handlers::trades::post_trade_accept,
+    handlers::trades::post_trade_cancel,
+    handlers::loot::get_loot_tables,
+    handlers::loot::post_loot_table,
+    handlers::loot::get_loot_table,
+    handlers::loot::patch_loot_table,
+    handlers::loot::delete_loot_table,
+    handlers::loot::post_character_loot,
+    handlers::recipes::get_recipes,
+    handlers::recipes::post_recipe,
+    handlers::recipes::get_recipe,
+    handlers::recipes::delete_recipe,
+    handlers::recipes::post_character_craft,
+    handlers::vendors::get_vendors,
+    handlers::vendors::post_vendor,
+    handlers::vendors::get_vendor,
+    handlers::vendors::delete_vendor,
+    handlers::vendors::post_vendor_buy,
+    handlers::vendors::post_vendor_sell,
+    handlers::buy_orders::get_buy_orders,
+    handlers::buy_orders::post_buy_order,
+    handlers::buy_orders::get_buy_order,
+    handlers::buy_orders::post_buy_order_cancel,
+    handlers::buy_orders::get_item_orderbook,
+))]
+struct ApiV2Doc;
+
+// Operation ids have to be unique across versions, v1 is deprecated and every operation can be
+// rate limited
+struct VersionedOperations;
+
+impl Modify for VersionedOperations {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        let rate_limited = ResponseBuilder::new()
+            .description("Too many requests, slow down.")
+            .header(
+                "Retry-After",
+                HeaderBuilder::new()
+                    .schema(ObjectBuilder::new().schema_type(Type::Integer))
+                    .description(Some("Seconds to wait before retrying"))
+                    .build(),
+            )
+            .content(
+                "text/plain",
+                Content::new(Some(ObjectBuilder::new().schema_type(Type::String))),
+            )
+            .build();
+
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            let Some(version) = ["v1", "v2"]
+                .into_iter()
+                .find(|version| path.starts_with(&format!("/{version}/")))
+            else {
+                continue;
+            };
+            let operations = [
+                &mut item.get,
+                &mut item.post,
+                &mut item.put,
+                &mut item.patch,
+                &mut item.delete,
+            ];
+            for operation in operations.into_iter().flatten() {
+                if let Some(id) = &operation.operation_id {
+                    operation.operation_id = Some(format!("{version}_{id}"));
+                }
+                if version == "v1" {
+                    operation.deprecated = Some(Deprecated::True);
+                }
+                operation
+                    .responses
+                    .responses
+                    .insert("429".to_string(), rate_limited.clone().into());
+            }
+        }
+    }
+}
+
+// Redoc loaded from its CDN, rendering /openapi.json
+const DOCS_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>RPG Server API</title>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+  </head>
+  <body>
+    <redoc spec-url="/openapi.json"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/latest/bundles/redoc.standalone.js"></script>
+  </body>
+</html>
+"#;
+
+// =========================Handlers=========================
+pub async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+pub async fn get_docs() -> Html<&'static str> {
+    Html(DOCS_PAGE)
+}
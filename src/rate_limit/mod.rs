@@ -0,0 +1,138 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Mutex};
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+
+use crate::{AppState, config::RateLimitConfig, errors::Error};
+
+// Above this many buckets, the ones that have refilled (idle clients) are dropped
+const MAX_IDLE_BUCKETS: usize = 10_000;
+
+/// Routes sharing a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Default,
+    /// Listing, cancelling and buying auctions, where bots snipe
+    Marketplace,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    burst: f64,
+    per_second: f64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: DateTime<Utc>,
+}
+
+/// Token buckets keyed by route group and client.
+#[derive(Debug)]
+pub struct RateLimiter {
+    default: Limit,
+    marketplace: Limit,
+    buckets: Mutex<HashMap<(RouteGroup, String), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        RateLimiter {
+            default: Limit {
+                burst: config.burst as f64,
+                per_second: config.requests_per_minute as f64 / 60.0,
+            },
+            marketplace: Limit {
+                burst: config.marketplace_burst as f64,
+                per_second: config.marketplace_requests_per_minute as f64 / 60.0,
+            },
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the client's bucket, or returns how many seconds until one is available.
+    pub fn check(&self, now: DateTime<Utc>, group: RouteGroup, client: &str) -> Result<(), u64> {
+        let limit = match group {
+            RouteGroup::Default => self.default,
+            RouteGroup::Marketplace => self.marketplace,
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > MAX_IDLE_BUCKETS {
+            let refilled = |bucket: &Bucket, limit: Limit| {
+                let elapsed = (now - bucket.updated).as_seconds_f64();
+                bucket.tokens + elapsed * limit.per_second >= limit.burst
+            };
+            buckets.retain(|(group, _), bucket| match group {
+                RouteGroup::Default => !refilled(bucket, self.default),
+                RouteGroup::Marketplace => !refilled(bucket, self.marketplace),
+            });
+        }
+
+        let bucket = buckets
+            .entry((group, client.to_string()))
+            .or_insert(Bucket {
+                tokens: limit.burst,
+                updated: now,
+            });
+        let elapsed = (now - bucket.updated).as_seconds_f64().max(0.0);
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_second).min(limit.burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / limit.per_second).ceil() as u64)
+        }
+    }
+}
+
+fn route_group(method: &Method, route: &str) -> RouteGroup {
+    let marketplace = match *method {
+        Method::POST => {
+            route.ends_with("/characters/{name}/auctions")
+                || route.ends_with("/auctions/{id}/purchase")
+        }
+        Method::DELETE => route.ends_with("/characters/{name}/auctions/{id}"),
+        _ => false,
+    };
+    if marketplace {
+        RouteGroup::Marketplace
+    } else {
+        RouteGroup::Default
+    }
+}
+
+// =========================Middleware=========================
+// There's no authentication yet so clients are told apart by their IP (keying on the character
+// named in the URL would let anyone drain someone else's bucket)
+pub async fn middleware_rate_limit(
+    state: State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("", |path| path.as_str());
+    let group = route_group(request.method(), route);
+
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or("unknown".to_string(), |ConnectInfo(address)| {
+            address.ip().to_string()
+        });
+
+    match state.rate_limiter.check(state.clock.now(), group, &client) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => Error::RateLimited(retry_after).into_response(),
+    }
+}
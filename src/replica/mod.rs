@@ -0,0 +1,213 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Json, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{task::JoinHandle, time::sleep};
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+use crate::{AppState, errors::Result, metrics::Metrics};
+
+#[derive(Debug, Serialize, Clone, Default, ToSchema)]
+pub struct ReplicaStatus {
+    /// Date of the last successful sync
+    pub last_sync: Option<DateTime<Utc>>,
+    pub last_sync_attempt: Option<DateTime<Utc>>,
+    /// Last frame of the primary's log applied locally
+    pub frame_no: Option<u64>,
+    /// Frames pulled by the last successful sync, how far behind the primary the replica was then
+    pub frames_synced: u64,
+    pub total_frames_synced: u64,
+    /// Seconds elapsed since the last successful sync. This is not how far behind the primary
+    /// the replica is, a replica that synced long ago is still up to date if nothing was written.
+    /// The embedded replica doesn't know the primary's current frame, so that lag isn't reported.
+    pub seconds_since_last_sync: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+/// Embedded replica of the Turso primary, keeping track of how fresh its data is.
+/// A local database (no primary to sync from) is always considered fresh.
+pub struct Replica {
+    db: libsql::Database,
+    metrics: Arc<Metrics>,
+    remote: bool,
+    max_sync_age: Duration,
+    status: Mutex<ReplicaStatus>,
+}
+
+impl Replica {
+    pub fn remote(db: libsql::Database, metrics: Arc<Metrics>, max_sync_age: Duration) -> Self {
+        Replica {
+            db,
+            metrics,
+            remote: true,
+            max_sync_age,
+            status: Mutex::new(ReplicaStatus::default()),
+        }
+    }
+
+    pub fn local(db: libsql::Database, metrics: Arc<Metrics>) -> Self {
+        Replica {
+            db,
+            metrics,
+            remote: false,
+            max_sync_age: Duration::MAX,
+            status: Mutex::new(ReplicaStatus::default()),
+        }
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.remote
+    }
+
+    /// Opens a new connection. On a local database it enforces foreign keys, which SQLite leaves
+    /// off by default but the cascades rely on, and waits for the other connections' writes.
+    pub async fn connect(&self) -> Result<libsql::Connection> {
+        let conn = self.db.connect()?;
+        if !self.remote {
+            conn.busy_timeout(Duration::from_secs(5))?;
+            conn.execute("PRAGMA foreign_keys = ON", ()).await?;
+        }
+        Ok(conn)
+    }
+
+    /// Pulls the new frames from the primary and records the outcome.
+    pub async fn sync(&self) -> Result<()> {
+        if !self.remote {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        let result = self.db.sync().await;
+        self.metrics
+            .observe_replica_sync(start.elapsed(), result.is_ok());
+
+        let mut status = self.status.lock().unwrap();
+        let now = Utc::now();
+        status.last_sync_attempt = Some(now);
+        match result {
+            Ok(replicated) => {
+                status.last_sync = Some(now);
+                status.frame_no = replicated.frame_no();
+                status.frames_synced = replicated.frames_synced() as u64;
+                status.total_frames_synced += replicated.frames_synced() as u64;
+                status.last_error = None;
+                Ok(())
+            }
+            Err(e) => {
+                status.last_error = Some(e.to_string());
+                Err(e.into())
+            }
+        }
+    }
+
+    pub fn status(&self) -> ReplicaStatus {
+        let mut status = self.status.lock().unwrap().clone();
+        status.seconds_since_last_sync = status
+            .last_sync
+            .map(|last_sync| (Utc::now() - last_sync).num_seconds());
+        status
+    }
+
+    /// Whether the replica has synced successfully, recently enough.
+    pub fn is_fresh(&self) -> bool {
+        if !self.remote {
+            return true;
+        }
+
+        let status = self.status();
+        let Some(seconds_since_last_sync) = status.seconds_since_last_sync else {
+            return false;
+        };
+        if status.last_error.is_some() {
+            return false;
+        }
+        seconds_since_last_sync <= self.max_sync_age.as_secs() as i64
+    }
+}
+
+// Upper bound of the delay between two failed syncs
+const MAX_SYNC_BACKOFF: Duration = Duration::from_secs(300);
+
+pub fn spawn_replica_syncer(
+    replica: Arc<Replica>,
+    interval: Duration,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut delay = interval;
+        loop {
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = sleep(delay) => {}
+            }
+
+            match replica.sync().await {
+                Ok(()) => delay = interval,
+                Err(e) => {
+                    // Exponential backoff so an unreachable primary isn't hammered
+                    delay = (delay * 2).min(MAX_SYNC_BACKOFF.max(interval));
+                    println!(
+                        "Failed to sync replica, retrying in {}s: {}",
+                        delay.as_secs(),
+                        e
+                    );
+                }
+            }
+        }
+    })
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/admin/replica",
+    tag = "operations",
+    responses(
+        (status = 200, description = "State of the embedded replica, as of its last sync", body = ReplicaStatus),
+    )
+)]
+pub async fn get_replica_status(state: State<AppState>) -> Result<Json<ReplicaStatus>> {
+    Ok(Json(state.replica.status()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/replica/sync",
+    tag = "operations",
+    responses(
+        (status = 200, description = "State of the embedded replica after the sync", body = ReplicaStatus),
+        (status = 500, description = "The sync failed.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn post_replica_sync(state: State<AppState>) -> Result<Json<ReplicaStatus>> {
+    state.replica.sync().await?;
+    Ok(Json(state.replica.status()))
+}
+
+// =========================Middleware=========================
+// Syncs the replica after a successful write so the next read sees it (read-your-writes)
+pub async fn middleware_sync_after_write(
+    state: State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_write = !request.method().is_safe();
+    let response = next.run(request).await;
+
+    if is_write
+        && response.status().is_success()
+        && let Err(e) = state.replica.sync().await
+    {
+        println!("Failed to sync replica after write: {}", e);
+    }
+    response
+}
@@ -0,0 +1,426 @@
+use std::{collections::BTreeMap, fmt};
+
+use axum::extract::{Json, Query, State};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    db::{Transaction, timestamp},
+    errors::Result,
+    into_rows,
+};
+
+const DEFAULT_DAYS: u64 = 30;
+const DEFAULT_LIMIT: u64 = 10;
+const MAX_LIMIT: u64 = 100;
+
+/// Where gold entering or leaving the economy comes from (auction fees have their own ledger).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GoldSource {
+    /// Gold a character is created with
+    CharacterCreation,
+    /// Gold set by hand through `PATCH /characters/{name}`
+    AdminAdjustment,
+    /// Gold lost with a deleted character
+    CharacterDeletion,
+    Loot,
+    VendorPurchase,
+    VendorSale,
+    Crafting,
+    /// Forfeited deposits of expired and cancelled auctions
+    AuctionDeposit,
+    AuctionTax,
+}
+
+impl fmt::Display for GoldSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoldSource::CharacterCreation => write!(f, "character_creation"),
+            GoldSource::AdminAdjustment => write!(f, "admin_adjustment"),
+            GoldSource::CharacterDeletion => write!(f, "character_deletion"),
+            GoldSource::Loot => write!(f, "loot"),
+            GoldSource::VendorPurchase => write!(f, "vendor_purchase"),
+            GoldSource::VendorSale => write!(f, "vendor_sale"),
+            GoldSource::Crafting => write!(f, "crafting"),
+            GoldSource::AuctionDeposit => write!(f, "auction_deposit"),
+            GoldSource::AuctionTax => write!(f, "auction_tax"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReportQuery {
+    /// How many days back the report goes, 30 by default
+    days: Option<u64>,
+    /// Only this item, for the price trends
+    item_id: Option<Uuid>,
+}
+
+impl ReportQuery {
+    fn since(&self, now: DateTime<Utc>) -> i64 {
+        let days = self.days.unwrap_or(DEFAULT_DAYS).min(36500);
+        timestamp::to_millis(&(now - TimeDelta::days(days as i64)))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RichestQuery {
+    /// How many characters are listed, 10 by default and 100 at most
+    limit: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct GoldSupply {
+    pub characters: u64,
+    /// Gold owned by all characters, available or reserved
+    pub total_gold: u64,
+    /// Gold reserved for pending buy orders and trades
+    pub reserved_gold: u64,
+    /// Deposits of the active auctions, out of circulation until refunded or forfeited
+    pub held_in_deposits: u64,
+    pub mean_gold: f64,
+    pub percentiles: GoldPercentiles,
+}
+
+/// Nearest-rank percentiles of the characters' gold, available or reserved.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct GoldPercentiles {
+    pub min: u64,
+    pub p10: u64,
+    pub p25: u64,
+    pub p50: u64,
+    pub p75: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct RichCharacter {
+    pub name: String,
+    pub level: u64,
+    /// Available and reserved gold
+    pub gold: u64,
+    /// Base value of everything the character owns
+    pub item_value: u64,
+    pub net_worth: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, ToSchema)]
+pub struct AuctionVolume {
+    /// UTC day, as YYYY-MM-DD
+    pub day: String,
+    pub listed: u64,
+    pub sold: u64,
+    /// Gold paid by the buyers
+    pub gold_volume: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct PriceTrend {
+    pub item_id: Uuid,
+    pub item_name: String,
+    /// UTC day, as YYYY-MM-DD
+    pub day: String,
+    pub sales: u64,
+    pub average_price: f64,
+    pub min_price: u64,
+    pub max_price: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct GoldFlow {
+    pub source: GoldSource,
+    pub created: u64,
+    pub destroyed: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct GoldFlows {
+    pub created: u64,
+    pub destroyed: u64,
+    /// Created minus destroyed, positive when the economy inflates
+    pub net: i64,
+    pub sources: Vec<GoldFlow>,
+}
+
+// =========================Query functions=========================
+/// Records gold entering (positive amount) or leaving (negative amount) the economy.
+pub async fn record_gold_flow_libsql_query(
+    transaction: &Transaction<'_>,
+    source: GoldSource,
+    character_name: &str,
+    amount: i64,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    transaction
+        .execute(
+            "INSERT INTO gold_ledger (id, source, character_name, amount, date) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                Uuid::new_v4().to_string(),
+                source.to_string(),
+                character_name,
+                amount,
+                timestamp::to_millis(&now),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn get_gold_supply_libsql_query(state: &State<AppState>) -> Result<GoldSupply> {
+    let mut query = state
+        .conn
+        .query(
+            "WITH ranked AS (
+                SELECT gold + reserved_gold AS gold, reserved_gold,
+                ROW_NUMBER() OVER (ORDER BY gold + reserved_gold) AS rank, COUNT(*) OVER () AS total
+                FROM characters
+            )
+            SELECT COUNT(*), COALESCE(SUM(gold), 0), COALESCE(AVG(gold), 0.0),
+            COALESCE(MIN(gold), 0),
+            COALESCE(MIN(CASE WHEN rank * 100 >= total * 10 THEN gold END), 0),
+            COALESCE(MIN(CASE WHEN rank * 100 >= total * 25 THEN gold END), 0),
+            COALESCE(MIN(CASE WHEN rank * 100 >= total * 50 THEN gold END), 0),
+            COALESCE(MIN(CASE WHEN rank * 100 >= total * 75 THEN gold END), 0),
+            COALESCE(MIN(CASE WHEN rank * 100 >= total * 90 THEN gold END), 0),
+            COALESCE(MIN(CASE WHEN rank * 100 >= total * 99 THEN gold END), 0),
+            COALESCE(MAX(gold), 0),
+            (SELECT COALESCE(SUM(deposit), 0) FROM auctions WHERE status = 'active'),
+            COALESCE(SUM(reserved_gold), 0)
+            FROM ranked",
+            (),
+        )
+        .await?;
+    let Some(row) = query.next().await? else {
+        return Ok(GoldSupply::default());
+    };
+    Ok(GoldSupply {
+        characters: row.get::<u64>(0)?,
+        total_gold: row.get::<u64>(1)?,
+        mean_gold: row.get::<f64>(2)?,
+        percentiles: GoldPercentiles {
+            min: row.get::<u64>(3)?,
+            p10: row.get::<u64>(4)?,
+            p25: row.get::<u64>(5)?,
+            p50: row.get::<u64>(6)?,
+            p75: row.get::<u64>(7)?,
+            p90: row.get::<u64>(8)?,
+            p99: row.get::<u64>(9)?,
+            max: row.get::<u64>(10)?,
+        },
+        held_in_deposits: row.get::<u64>(11)?,
+        reserved_gold: row.get::<u64>(12)?,
+    })
+}
+
+async fn get_richest_characters_libsql_query(
+    state: &State<AppState>,
+    limit: u64,
+) -> Result<Vec<RichCharacter>> {
+    let query = state
+        .conn
+        .query(
+            "SELECT c.name, c.level, c.gold + c.reserved_gold AS gold,
+            COALESCE(SUM(ii.quantity * i.base_value), 0) AS item_value,
+            c.gold + c.reserved_gold + COALESCE(SUM(ii.quantity * i.base_value), 0) AS net_worth
+            FROM characters c
+            LEFT JOIN items_instances ii ON ii.owner_name = c.name
+            LEFT JOIN items i ON i.id = ii.item_id
+            GROUP BY c.name
+            ORDER BY net_worth DESC, c.name
+            LIMIT ?1",
+            [limit],
+        )
+        .await?;
+    let characters: Vec<RichCharacter> = into_rows(query).await?;
+    Ok(characters)
+}
+
+async fn get_auction_volume_libsql_query(
+    state: &State<AppState>,
+    since: i64,
+) -> Result<Vec<AuctionVolume>> {
+    // Listings count on the day they were created, sales on the day they were bought
+    let query = state
+        .conn
+        .query(
+            "SELECT date(creation_date / 1000, 'unixepoch') AS day, COUNT(*) AS listed, 0 AS sold, 0 AS gold_volume
+            FROM auctions WHERE creation_date >= ?1 GROUP BY day
+            UNION ALL
+            SELECT date(COALESCE(sale_date, creation_date) / 1000, 'unixepoch') AS day, 0 AS listed,
+            COUNT(*) AS sold, SUM(price) AS gold_volume
+            FROM auctions WHERE status = 'sold' AND COALESCE(sale_date, creation_date) >= ?1 GROUP BY day",
+            [since],
+        )
+        .await?;
+    let rows: Vec<AuctionVolume> = into_rows(query).await?;
+
+    let mut days: BTreeMap<String, AuctionVolume> = BTreeMap::new();
+    for row in rows {
+        let day = days
+            .entry(row.day.clone())
+            .or_insert_with(|| AuctionVolume {
+                day: row.day,
+                ..Default::default()
+            });
+        day.listed += row.listed;
+        day.sold += row.sold;
+        day.gold_volume += row.gold_volume;
+    }
+    Ok(days.into_values().collect())
+}
+
+async fn get_price_trends_libsql_query(
+    state: &State<AppState>,
+    since: i64,
+    item_id: Option<Uuid>,
+) -> Result<Vec<PriceTrend>> {
+    let query = state
+        .conn
+        .query(
+            "SELECT a.auctioned_item_id AS item_id, i.name AS item_name,
+            date(COALESCE(a.sale_date, a.creation_date) / 1000, 'unixepoch') AS day,
+            COUNT(*) AS sales, AVG(a.price) AS average_price, MIN(a.price) AS min_price, MAX(a.price) AS max_price
+            FROM auctions a JOIN items i ON i.id = a.auctioned_item_id
+            WHERE a.status = 'sold' AND COALESCE(a.sale_date, a.creation_date) >= ?1
+            AND (?2 IS NULL OR a.auctioned_item_id = ?2)
+            GROUP BY a.auctioned_item_id, day
+            ORDER BY i.name, day",
+            (since, item_id.map(|id| id.to_string())),
+        )
+        .await?;
+    let trends: Vec<PriceTrend> = into_rows(query).await?;
+    Ok(trends)
+}
+
+async fn get_gold_flows_libsql_query(state: &State<AppState>, since: i64) -> Result<GoldFlows> {
+    // Deposits are only lost once their auction is no longer active without having been refunded
+    let query = state
+        .conn
+        .query(
+            "SELECT source, COALESCE(SUM(CASE WHEN amount > 0 THEN amount END), 0) AS created,
+            COALESCE(SUM(CASE WHEN amount < 0 THEN -amount END), 0) AS destroyed
+            FROM gold_ledger WHERE date >= ?1 GROUP BY source
+            UNION ALL
+            SELECT 'auction_tax', 0, COALESCE(SUM(amount), 0)
+            FROM fees WHERE kind = 'sales_tax' AND date >= ?1
+            UNION ALL
+            SELECT 'auction_deposit', 0, COALESCE(SUM(f.amount), 0)
+            FROM fees f WHERE f.kind = 'listing_deposit' AND f.date >= ?1
+            AND NOT EXISTS (SELECT 1 FROM fees r WHERE r.kind = 'deposit_refund' AND r.auction_id = f.auction_id)
+            AND NOT EXISTS (SELECT 1 FROM auctions a WHERE a.id = f.auction_id AND a.status = 'active')",
+            [since],
+        )
+        .await?;
+    let mut sources: Vec<GoldFlow> = into_rows(query).await?;
+    sources.retain(|flow| flow.created > 0 || flow.destroyed > 0);
+    sources.sort_by_key(|flow| flow.source);
+
+    let created = sources.iter().map(|flow| flow.created).sum::<u64>();
+    let destroyed = sources.iter().map(|flow| flow.destroyed).sum::<u64>();
+    Ok(GoldFlows {
+        created,
+        destroyed,
+        net: created as i64 - destroyed as i64,
+        sources,
+    })
+}
+
+// =========================Handlers=========================
+#[utoipa::path(
+    get,
+    path = "/admin/economy/gold",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Gold in circulation and how it is spread across characters", body = GoldSupply),
+    )
+)]
+pub async fn get_gold_supply(state: State<AppState>) -> Result<Json<GoldSupply>> {
+    let supply = get_gold_supply_libsql_query(&state).await?;
+    Ok(Json(supply))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/economy/richest",
+    tag = "operations",
+    params(RichestQuery),
+    responses(
+        (status = 200, description = "The characters with the highest net worth (gold plus the base value of their items)", body = Vec<RichCharacter>),
+        (status = 400, description = "The limit is not a number.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_richest_characters(
+    state: State<AppState>,
+    Query(query): Query<RichestQuery>,
+) -> Result<Json<Vec<RichCharacter>>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let characters = get_richest_characters_libsql_query(&state, limit).await?;
+    Ok(Json(characters))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/economy/auctions",
+    tag = "operations",
+    params(ReportQuery),
+    responses(
+        (status = 200, description = "Auctions listed and sold per day, oldest first", body = Vec<AuctionVolume>),
+        (status = 400, description = "A parameter is not valid.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_auction_volume(
+    state: State<AppState>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Json<Vec<AuctionVolume>>> {
+    let volume = get_auction_volume_libsql_query(&state, query.since(state.clock.now())).await?;
+    Ok(Json(volume))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/economy/prices",
+    tag = "operations",
+    params(ReportQuery),
+    responses(
+        (status = 200, description = "Sale prices per item and per day", body = Vec<PriceTrend>),
+        (status = 400, description = "A parameter is not valid.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_price_trends(
+    state: State<AppState>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Json<Vec<PriceTrend>>> {
+    let trends =
+        get_price_trends_libsql_query(&state, query.since(state.clock.now()), query.item_id)
+            .await?;
+    Ok(Json(trends))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/economy/flows",
+    tag = "operations",
+    params(ReportQuery),
+    responses(
+        (status = 200, description = "Gold created and destroyed per source", body = GoldFlows),
+        (status = 400, description = "A parameter is not valid.", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn get_gold_flows(
+    state: State<AppState>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Json<GoldFlows>> {
+    let flows = get_gold_flows_libsql_query(&state, query.since(state.clock.now())).await?;
+    Ok(Json(flows))
+}
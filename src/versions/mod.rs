@@ -0,0 +1,39 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+// When v1 got deprecated (RFC 9745 date: `@` followed by a Unix timestamp), 2026-10-18
+const V1_DEPRECATION: &str = "@1792281600";
+// When v1 stops being served (RFC 8594 HTTP-date)
+const V1_SUNSET: &str = "Sun, 18 Apr 2027 00:00:00 GMT";
+const V1_SUCCESSOR: &str = "</v2>; rel=\"successor-version\"";
+
+/// Versions of the RPG resources' API, each served under its own prefix (`/v1`, `/v2`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+// =========================Middleware=========================
+// Tells the clients still on v1 (or on the unprefixed alias) that it's going away and what replaces it
+pub async fn middleware_deprecated_v1(request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(
+        HeaderName::from_static("deprecation"),
+        HeaderValue::from_static(V1_DEPRECATION),
+    );
+    headers.insert(
+        HeaderName::from_static("sunset"),
+        HeaderValue::from_static(V1_SUNSET),
+    );
+    headers.insert(
+        axum::http::header::LINK,
+        HeaderValue::from_static(V1_SUCCESSOR),
+    );
+    response
+}
@@ -0,0 +1,332 @@
+mod common;
+
+use axum::http::StatusCode;
+use std::time::Duration;
+
+use chrono::TimeDelta;
+use common::{TestApp, start_time};
+use rpg_server::{
+    clock::Clock, config::Config, handlers::auctions::expire_auctions_libsql_query,
+    spawn_auction_status_updater,
+};
+use serde_json::{Value, json};
+use tokio_util::sync::CancellationToken;
+
+// Aria sells an Iron Sword to Borin for the default price (100 gold)
+async fn setup(buyer_gold: u64) -> (TestApp, Value) {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 10).await;
+    app.create_character("borin", "warrior", buyer_gold).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+    let auction = app.create_auction("aria", &instance).await;
+    (app, auction)
+}
+
+fn purchase_uri(auction: &Value) -> String {
+    format!("/auctions/{}/purchase", auction["id"].as_str().unwrap())
+}
+
+#[tokio::test]
+async fn list_filter_and_get_auctions() {
+    let (app, auction) = setup(500).await;
+
+    let (status, body) = app.get("/auctions").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+
+    let (_, active) = app.get("/auctions?status=active").await;
+    assert_eq!(active.as_array().unwrap().len(), 1);
+    let (_, sold) = app.get("/auctions?status=sold").await;
+    assert_eq!(sold, json!([]));
+    let (status, _) = app.get("/auctions?status=unknown").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = app
+        .get(&format!("/auctions/{}", auction["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["id"], auction["id"]);
+    assert_eq!(body["status"], "active");
+
+    let (status, body) = app
+        .get("/auctions/00000000-0000-0000-0000-000000000000")
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This auction does not exist.");
+}
+
+#[tokio::test]
+async fn purchase_transfers_gold_and_item() {
+    let (app, auction) = setup(500).await;
+    let buyer = app.character_body("borin").await;
+
+    let (status, body) = app.post(&purchase_uri(&auction), buyer).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["status"], "sold");
+
+    assert_eq!(app.character_body("borin").await["gold"], 400);
+    // 10 - 5 (deposit) + 100 - 5 (sales tax) + 5 (deposit refund)
+    assert_eq!(app.character_body("aria").await["gold"], 105);
+
+    let (_, seller_items) = app.get("/characters/aria/items").await;
+    assert_eq!(seller_items, json!([]));
+    let (_, buyer_items) = app.get("/characters/borin/items").await;
+    assert_eq!(buyer_items[0]["owner_name"], "borin");
+    assert_eq!(buyer_items[0]["item_id"], auction["auctioned_item_id"]);
+
+    let (_, sold) = app.get("/auctions?status=sold").await;
+    assert_eq!(sold.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn purchase_requires_enough_gold() {
+    let (app, auction) = setup(99).await;
+    let buyer = app.character_body("borin").await;
+
+    let (status, body) = app.post(&purchase_uri(&auction), buyer).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "The buyer does not have enough gold.");
+    assert_eq!(app.character_body("borin").await["gold"], 99);
+    // The deposit stays held while the auction is active
+    assert_eq!(app.character_body("aria").await["gold"], 5);
+}
+
+#[tokio::test]
+async fn seller_cannot_buy_their_own_auction() {
+    let (app, auction) = setup(500).await;
+    app.patch("/characters/aria", json!({ "gold": 1000 })).await;
+    let seller = app.character_body("aria").await;
+
+    let (status, body) = app.post(&purchase_uri(&auction), seller).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "The buyer cannot be the auction's owner.");
+    assert_eq!(app.character_body("aria").await["gold"], 1000);
+}
+
+#[tokio::test]
+async fn buyer_must_exist() {
+    let (app, auction) = setup(500).await;
+
+    let (status, body) = app
+        .post(
+            &purchase_uri(&auction),
+            json!({ "name": "ghost", "class": "ranger", "gold": 1000 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This character does not exist.");
+    assert_eq!(
+        app.get("/auctions?status=active")
+            .await
+            .1
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn auction_cannot_be_purchased_twice() {
+    let (app, auction) = setup(500).await;
+    app.create_character("cyra", "ranger", 500).await;
+
+    let buyer = app.character_body("borin").await;
+    let (status, _) = app.post(&purchase_uri(&auction), buyer).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let other_buyer = app.character_body("cyra").await;
+    let (status, body) = app.post(&purchase_uri(&auction), other_buyer).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This auction is not active.");
+    assert_eq!(app.character_body("cyra").await["gold"], 500);
+}
+
+// The purchase is all or nothing: a step failing after the gold moved rolls everything back
+#[tokio::test]
+async fn failed_purchase_leaves_gold_item_and_auction_in_place() {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config.inventory.warrior_slots = 1;
+    let app = TestApp::with_config(config).await;
+    app.create_character("aria", "mage", 10).await;
+    app.create_character("borin", "warrior", 500).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+    let auction = app.create_auction("aria", &instance).await;
+    let shield = app.create_item("Wooden Shield").await;
+    app.loot_item("borin", &shield).await;
+    let buyer = app.character_body("borin").await;
+
+    let (status, _) = app.post(&purchase_uri(&auction), buyer).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(app.character_body("borin").await["gold"], 500);
+    assert_eq!(app.character_body("aria").await["gold"], 5);
+    let (_, body) = app
+        .get(&format!("/auctions/{}", auction["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(body["status"], "active");
+    let (_, seller_items) = app.get("/characters/aria/items").await;
+    assert_eq!(seller_items[0]["id"], instance["id"]);
+}
+
+#[tokio::test]
+async fn unknown_auction_cannot_be_purchased() {
+    let (app, _) = setup(500).await;
+    let buyer = app.character_body("borin").await;
+
+    let (status, _) = app
+        .post(
+            "/auctions/00000000-0000-0000-0000-000000000000/purchase",
+            buyer,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn expiry_sweep_only_expires_ended_auctions() {
+    let (app, auction) = setup(500).await;
+
+    // Still active right on its end date
+    app.clock.advance(TimeDelta::seconds(60));
+    let expired = expire_auctions_libsql_query(&app.state, app.clock.now())
+        .await
+        .unwrap();
+    assert_eq!(expired, 0);
+    assert_eq!(
+        app.get("/auctions?status=active")
+            .await
+            .1
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+
+    app.clock.advance(TimeDelta::seconds(1));
+    let expired = expire_auctions_libsql_query(&app.state, app.clock.now())
+        .await
+        .unwrap();
+    assert_eq!(expired, 1);
+
+    let (_, body) = app
+        .get(&format!("/auctions/{}", auction["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(body["status"], "expired");
+    assert_eq!(app.get("/auctions?status=active").await.1, json!([]));
+}
+
+#[tokio::test]
+async fn expired_auction_cannot_be_purchased() {
+    let (app, auction) = setup(500).await;
+    app.clock.advance(TimeDelta::minutes(2));
+    expire_auctions_libsql_query(&app.state, app.clock.now())
+        .await
+        .unwrap();
+    let buyer = app.character_body("borin").await;
+
+    let (status, body) = app.post(&purchase_uri(&auction), buyer).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This auction is not active.");
+    assert_eq!(app.character_body("borin").await["gold"], 500);
+    assert_eq!(app.get("/characters/borin/items").await.1, json!([]));
+}
+
+#[tokio::test]
+async fn sold_auctions_are_not_expired() {
+    let (app, auction) = setup(500).await;
+    let buyer = app.character_body("borin").await;
+    app.post(&purchase_uri(&auction), buyer).await;
+
+    app.clock.advance(TimeDelta::minutes(2));
+    let expired = expire_auctions_libsql_query(&app.state, app.clock.now())
+        .await
+        .unwrap();
+    assert_eq!(expired, 0);
+    let (_, body) = app
+        .get(&format!("/auctions/{}", auction["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(body["status"], "sold");
+}
+
+#[tokio::test]
+async fn auction_dates_come_from_the_clock() {
+    let (_, auction) = setup(500).await;
+    assert_eq!(auction["creation_date"], "2025-01-01T12:00:00Z");
+    assert_eq!(auction["end_date"], "2025-01-01T12:01:00Z");
+}
+
+// Stored dates used to be written in a format they couldn't be read back from
+#[tokio::test]
+async fn auction_dates_read_back_as_they_were_stored() {
+    let (app, auction) = setup(500).await;
+
+    let (status, body) = app
+        .get(&format!("/auctions/{}", auction["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["creation_date"], auction["creation_date"]);
+    assert_eq!(body["end_date"], auction["end_date"]);
+
+    let (status, body) = app.get("/characters/aria/auctions").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["creation_date"], auction["creation_date"]);
+    assert_eq!(body[0]["end_date"], auction["end_date"]);
+}
+
+#[tokio::test]
+async fn ended_auction_cannot_be_purchased_before_the_sweep() {
+    let (app, auction) = setup(500).await;
+    app.clock.set(start_time() + TimeDelta::seconds(61));
+    let buyer = app.character_body("borin").await;
+
+    let (status, body) = app.post(&purchase_uri(&auction), buyer).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This auction is not active.");
+}
+
+// Tokio's clock is paused, the updater's sleeps pass as soon as every task is idle
+#[tokio::test(start_paused = true)]
+async fn status_updater_expires_auctions_using_the_clock() {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config.auctions.expiry_interval_secs = 1;
+    let app = TestApp::with_config(config).await;
+    app.create_character("aria", "mage", 10).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+    let auction = app.create_auction("aria", &instance).await;
+    let auction_uri = format!("/auctions/{}", auction["id"].as_str().unwrap());
+
+    let shutdown = CancellationToken::new();
+    let updater = spawn_auction_status_updater(app.state.clone(), shutdown.clone());
+
+    // A sweep ran but the auction hasn't ended for the clock
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(app.get(&auction_uri).await.1["status"], "active");
+
+    app.clock.advance(TimeDelta::minutes(2));
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert_eq!(app.get(&auction_uri).await.1["status"], "expired");
+
+    shutdown.cancel();
+    updater.await.unwrap();
+}
+
+#[tokio::test]
+async fn overflowing_deposits_are_rejected() {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config.economy.auction_price = u64::MAX;
+    let app = TestApp::with_config(config).await;
+    app.create_character("aria", "mage", 10).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+
+    let (status, body) = app.post("/characters/aria/auctions", instance).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body, "This amount of gold is too large.");
+    assert_eq!(app.character_body("aria").await["gold"], 10);
+}
@@ -0,0 +1,212 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use rpg_server::config::Config;
+use serde_json::{Value, json};
+
+// aria (mage, 50 gold) sells swords at the default auction price of 100 to borin (warrior, 500 gold)
+async fn setup(config: Config) -> (TestApp, Value) {
+    let app = TestApp::with_config(config).await;
+    app.create_character("aria", "mage", 50).await;
+    app.create_character("borin", "warrior", 500).await;
+    let sword = app.create_item("Sword").await;
+    (app, sword)
+}
+
+fn test_config() -> Config {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config
+}
+
+async fn place_order(app: &TestApp, sword: &Value, max_price: u64, quantity: u64) -> Value {
+    let (status, order) = app
+        .post(
+            "/buy-orders",
+            json!({ "character_name": "borin", "item_id": sword["id"], "max_price": max_price, "quantity": quantity }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{order}");
+    order
+}
+
+async fn orderbook(app: &TestApp, sword: &Value) -> Value {
+    let (status, book) = app
+        .get(&format!(
+            "/items/{}/orderbook",
+            sword["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{book}");
+    book
+}
+
+#[tokio::test]
+async fn listing_an_auction_fills_the_best_buy_order() {
+    let (app, sword) = setup(test_config()).await;
+    let order = place_order(&app, &sword, 150, 2).await;
+    assert_eq!(order["reserved"], 300);
+    let borin = app.character_body("borin").await;
+    assert_eq!(borin["gold"], 200);
+    assert_eq!(borin["reserved_gold"], 300);
+
+    let instance = app.loot_item("aria", &sword).await;
+    let auction = app.create_auction("aria", &instance).await;
+    assert_eq!(auction["status"], "sold");
+    assert_eq!(auction["price"], 150);
+    // 50 - 5 (deposit) + 150 - 7 (tax) + 5 (deposit refund)
+    assert_eq!(app.character_body("aria").await["gold"], 193);
+    let borin = app.character_body("borin").await;
+    assert_eq!(borin["gold"], 200);
+    assert_eq!(borin["reserved_gold"], 150);
+    let (_, items) = app.get("/characters/borin/items").await;
+    assert_eq!(items[0]["id"], instance["id"]);
+
+    let (_, order) = app
+        .get(&format!("/buy-orders/{}", order["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(order["filled"], 1);
+    assert_eq!(order["reserved"], 150);
+    assert_eq!(order["status"], "open");
+    assert_eq!(
+        orderbook(&app, &sword).await,
+        json!({
+            "item_id": sword["id"],
+            "item_name": "Sword",
+            "bids": [{ "price": 150, "quantity": 1 }],
+            "asks": []
+        })
+    );
+}
+
+#[tokio::test]
+async fn buy_orders_fill_from_listed_auctions_at_their_price() {
+    let (app, sword) = setup(test_config()).await;
+    app.patch("/characters/aria", json!({ "gold": 100 })).await;
+    for _ in 0..2 {
+        let instance = app.loot_item("aria", &sword).await;
+        app.create_auction("aria", &instance).await;
+    }
+    assert_eq!(
+        orderbook(&app, &sword).await["asks"],
+        json!([{ "price": 100, "quantity": 2 }])
+    );
+
+    // Too low to match anything
+    let low = place_order(&app, &sword, 90, 1).await;
+    assert_eq!(low["filled"], 0);
+
+    let order = place_order(&app, &sword, 120, 3).await;
+    assert_eq!(order["filled"], 2);
+    assert_eq!(order["reserved"], 120);
+    // 500 - 90 (low order) - 360 + 2 * 20 (paid below the maximum price)
+    assert_eq!(app.character_body("borin").await["gold"], 90);
+    assert_eq!(
+        orderbook(&app, &sword).await,
+        json!({
+            "item_id": sword["id"],
+            "item_name": "Sword",
+            "bids": [{ "price": 120, "quantity": 1 }, { "price": 90, "quantity": 1 }],
+            "asks": []
+        })
+    );
+
+    let (status, orders) = app.get("/buy-orders?status=open&character=borin").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(orders.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn full_inventories_leave_the_auction_listed() {
+    let mut config = test_config();
+    config.inventory.warrior_slots = 1;
+    let (app, sword) = setup(config).await;
+    let shield = app.create_item("Shield").await;
+    app.loot_item("borin", &shield).await;
+    place_order(&app, &sword, 150, 1).await;
+
+    let instance = app.loot_item("aria", &sword).await;
+    let auction = app.create_auction("aria", &instance).await;
+    assert_eq!(auction["status"], "active");
+    assert_eq!(app.character_body("borin").await["gold"], 350);
+    assert_eq!(
+        orderbook(&app, &sword).await["asks"],
+        json!([{ "price": 100, "quantity": 1 }])
+    );
+}
+
+#[tokio::test]
+async fn cancelling_gives_the_reserved_gold_back() {
+    let (app, sword) = setup(test_config()).await;
+    let order = place_order(&app, &sword, 100, 3).await;
+    let cancel_uri = format!("/buy-orders/{}/cancel", order["id"].as_str().unwrap());
+
+    let (status, _) = app
+        .post(&cancel_uri, json!({ "character_name": "aria" }))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, order) = app
+        .post(&cancel_uri, json!({ "character_name": "borin" }))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{order}");
+    assert_eq!(order["status"], "cancelled");
+    assert_eq!(order["reserved"], 0);
+    let borin = app.character_body("borin").await;
+    assert_eq!(borin["gold"], 500);
+    assert_eq!(borin["reserved_gold"], 0);
+
+    let (status, _) = app
+        .post(&cancel_uri, json!({ "character_name": "borin" }))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn invalid_buy_orders_are_rejected() {
+    let (app, sword) = setup(test_config()).await;
+
+    for (body, expected) in [
+        (
+            json!({ "character_name": "borin", "item_id": sword["id"], "max_price": 0 }),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            json!({ "character_name": "borin", "item_id": sword["id"], "max_price": 10, "quantity": 0 }),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            json!({ "character_name": "borin", "item_id": sword["id"], "max_price": 1_000_000_001u64 }),
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            json!({ "character_name": "borin", "item_id": sword["id"], "max_price": 501 }),
+            StatusCode::FORBIDDEN,
+        ),
+        (
+            json!({ "character_name": "nobody", "item_id": sword["id"], "max_price": 10 }),
+            StatusCode::NOT_FOUND,
+        ),
+    ] {
+        let (status, _) = app.post("/buy-orders", body.clone()).await;
+        assert_eq!(status, expected, "{body}");
+    }
+    assert_eq!(app.character_body("borin").await["gold"], 500);
+    assert_eq!(app.get("/buy-orders").await.1, json!([]));
+}
+
+#[tokio::test]
+async fn orders_at_the_maximum_price_are_taxed_without_overflowing() {
+    let (app, sword) = setup(test_config()).await;
+    app.patch("/characters/borin", json!({ "gold": 1_000_000_000u64 }))
+        .await;
+    place_order(&app, &sword, 1_000_000_000, 1).await;
+
+    let instance = app.loot_item("aria", &sword).await;
+    let auction = app.create_auction("aria", &instance).await;
+    assert_eq!(auction["status"], "sold");
+    // 50 + 1000000000 - 50000000 (tax)
+    assert_eq!(app.character_body("aria").await["gold"], 950_000_050u64);
+    assert_eq!(app.character_body("borin").await["reserved_gold"], 0);
+}
@@ -0,0 +1,276 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use serde_json::json;
+
+#[tokio::test]
+async fn create_and_list_characters() {
+    let app = TestApp::new().await;
+    let (status, body) = app.get("/characters").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([]));
+
+    let created = app.create_character("aria", "mage", 150).await;
+    assert_eq!(
+        created,
+        json!({ "name": "aria", "class": "mage", "gold": 150, "reserved_gold": 0, "level": 1, "experience": 0 })
+    );
+    app.create_character("borin", "warrior", 0).await;
+
+    let (status, body) = app.get("/characters").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn create_character_rejects_invalid_bodies() {
+    let app = TestApp::new().await;
+
+    let (status, _) = app
+        .post(
+            "/characters",
+            json!({ "name": "", "class": "mage", "gold": 10 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = app
+        .post(
+            "/characters",
+            json!({ "name": "aria", "class": "bard", "gold": 10 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = app
+        .post(
+            "/characters",
+            json!({ "name": "aria", "class": "mage", "gold": -5 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn get_patch_and_delete_character() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+
+    let (status, body) = app.get("/characters/aria").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["gold"], 150);
+
+    let (status, body) = app.patch("/characters/aria", json!({ "gold": 42 })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({ "name": "aria", "class": "mage", "gold": 42, "reserved_gold": 0, "level": 1, "experience": 0 })
+    );
+    assert_eq!(app.character_body("aria").await["gold"], 42);
+
+    let (status, body) = app.delete("/characters/aria").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["name"], "aria");
+
+    let (status, body) = app.get("/characters/aria").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This character does not exist.");
+}
+
+#[tokio::test]
+async fn unknown_character_routes_return_not_found() {
+    let app = TestApp::new().await;
+    let item = app.create_item("Iron Sword").await;
+    let id = item["id"].as_str().unwrap();
+
+    for uri in [
+        "/characters/ghost".to_string(),
+        "/characters/ghost/items".to_string(),
+        "/characters/ghost/auctions".to_string(),
+        format!("/characters/ghost/items/{id}"),
+        format!("/characters/ghost/auctions/{id}"),
+    ] {
+        let (status, _) = app.get(&uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+    }
+    let (status, _) = app.patch("/characters/ghost", json!({ "gold": 1 })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.delete("/characters/ghost").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn loot_list_get_and_remove_items() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+
+    let instance = app.loot_item("aria", &item).await;
+    assert_eq!(instance["item_id"], item["id"]);
+    assert_eq!(instance["item_name"], "Iron Sword");
+    assert_eq!(instance["owner_name"], "aria");
+    let instance_uri = format!(
+        "/characters/aria/items/{}",
+        instance["id"].as_str().unwrap()
+    );
+
+    let (status, body) = app.get("/characters/aria/items").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([instance]));
+
+    let (status, body) = app.get(&instance_uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, instance);
+
+    let (status, body) = app.delete(&instance_uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, instance);
+
+    let (status, body) = app.get(&instance_uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This item instance does not exist.");
+    assert_eq!(app.get("/characters/aria/items").await.1, json!([]));
+}
+
+#[tokio::test]
+async fn looting_an_unknown_item_fails() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+
+    let (status, body) = app
+        .post(
+            "/characters/aria/items/00000000-0000-0000-0000-000000000000",
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This item does not exist.");
+}
+
+#[tokio::test]
+async fn items_of_other_characters_are_not_visible() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    app.create_character("borin", "warrior", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("borin", &item).await;
+
+    let (status, _) = app
+        .get(&format!(
+            "/characters/aria/items/{}",
+            instance["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn create_list_and_cancel_auctions() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+
+    let auction = app.create_auction("aria", &instance).await;
+    assert_eq!(auction["seller_name"], "aria");
+    assert_eq!(auction["auctioned_item_id"], item["id"]);
+    assert_eq!(auction["status"], "active");
+    assert_eq!(auction["price"], 100);
+
+    let (status, body) = app.get("/characters/aria/auctions").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["id"], auction["id"]);
+
+    let auction_uri = format!(
+        "/characters/aria/auctions/{}",
+        auction["id"].as_str().unwrap()
+    );
+    let (status, body) = app.delete(&auction_uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["id"], auction["id"]);
+
+    assert_eq!(app.get("/characters/aria/auctions").await.1, json!([]));
+    let (status, _) = app.delete(&auction_uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn creating_an_auction_requires_owning_the_item() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    app.create_character("borin", "warrior", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("borin", &item).await;
+
+    let (status, body) = app.post("/characters/aria/auctions", instance).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This item instance does not exist.");
+    assert_eq!(app.get("/auctions").await.1, json!([]));
+}
+
+#[tokio::test]
+async fn auctions_of_other_characters_are_not_visible() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    app.create_character("borin", "warrior", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("borin", &item).await;
+    let auction = app.create_auction("borin", &instance).await;
+
+    let uri = format!(
+        "/characters/aria/auctions/{}",
+        auction["id"].as_str().unwrap()
+    );
+    let (status, body) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This auction does not exist.");
+    let (status, _) = app.delete(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(app.get("/auctions").await.1.as_array().unwrap().len(), 1);
+}
+
+// Cancelling through another character's route used to end the seller's auction
+#[tokio::test]
+async fn other_characters_cannot_cancel_an_auction() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    app.create_character("borin", "warrior", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("borin", &item).await;
+    let auction = app.create_auction("borin", &instance).await;
+    let auction_id = auction["id"].as_str().unwrap();
+
+    let (status, _) = app
+        .delete(&format!("/characters/aria/auctions/{auction_id}"))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, body) = app.get(&format!("/auctions/{auction_id}")).await;
+    assert_eq!(body["status"], "active");
+    // The deposit is still held
+    assert_eq!(app.character_body("borin").await["gold"], 145);
+
+    let (status, _) = app
+        .delete(&format!("/characters/borin/auctions/{auction_id}"))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn deleting_a_character_cascades_to_items_and_auctions() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+    app.create_auction("aria", &instance).await;
+
+    let (status, _) = app.delete("/characters/aria").await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert_eq!(app.get("/auctions").await.1, json!([]));
+    let (status, _) = app.get("/characters/aria/items").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    app.create_character("aria", "mage", 0).await;
+    assert_eq!(app.get("/characters/aria/items").await.1, json!([]));
+}
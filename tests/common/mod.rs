@@ -0,0 +1,154 @@
+#![allow(dead_code)]
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{HeaderMap, Method, Request, StatusCode, header::CONTENT_TYPE},
+};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use rpg_server::{AppState, build_app, clock::ManualClock, config::Config, init_db};
+use serde_json::{Value, json};
+use tower::ServiceExt;
+
+/// The whole app running on a fresh in-memory database, with a clock that only moves when told to.
+pub struct TestApp {
+    pub state: AppState,
+    pub clock: Arc<ManualClock>,
+    router: Router,
+}
+
+pub fn start_time() -> DateTime<Utc> {
+    "2025-01-01T12:00:00Z".parse().unwrap()
+}
+
+impl TestApp {
+    pub async fn new() -> TestApp {
+        let mut config = Config::default();
+        config.database.replica_path = ":memory:".to_string();
+        TestApp::with_config(config).await
+    }
+
+    pub async fn with_config(config: Config) -> TestApp {
+        let mut state = init_db(config).await.expect("failed to init the database");
+        let clock = Arc::new(ManualClock::new(start_time()));
+        state.clock = clock.clone();
+        let router = build_app(state.clone());
+        TestApp {
+            state,
+            clock,
+            router,
+        }
+    }
+
+    /// Sends a request and returns its status and body (`Value::String` when it isn't JSON).
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let (status, _, body) = self.request_with_headers(method, uri, body).await;
+        (status, body)
+    }
+
+    pub async fn request_with_headers(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, HeaderMap, Value) {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => request
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()));
+        (status, headers, body)
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        self.request(Method::GET, uri, None).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::POST, uri, Some(body)).await
+    }
+
+    pub async fn patch(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::PATCH, uri, Some(body)).await
+    }
+
+    pub async fn delete(&self, uri: &str) -> (StatusCode, Value) {
+        self.request(Method::DELETE, uri, None).await
+    }
+
+    // =========================Fixtures=========================
+    pub async fn create_character(&self, name: &str, class: &str, gold: u64) -> Value {
+        let (status, body) = self
+            .post(
+                "/characters",
+                json!({ "name": name, "class": class, "gold": gold }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        body
+    }
+
+    pub async fn create_item(&self, name: &str) -> Value {
+        let (status, body) = self.post("/items", json!({ "name": name })).await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        body
+    }
+
+    pub async fn create_stackable_item(&self, name: &str, max_stack: u64) -> Value {
+        let (status, body) = self
+            .post("/items", json!({ "name": name, "max_stack": max_stack }))
+            .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        body
+    }
+
+    pub async fn loot_item(&self, character: &str, item: &Value) -> Value {
+        let (status, body) = self
+            .post(
+                &format!(
+                    "/characters/{}/items/{}",
+                    character,
+                    item["id"].as_str().unwrap()
+                ),
+                json!({}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        body
+    }
+
+    pub async fn create_auction(&self, character: &str, instance: &Value) -> Value {
+        let (status, body) = self
+            .post(
+                &format!("/characters/{}/auctions", character),
+                instance.clone(),
+            )
+            .await;
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        body
+    }
+
+    /// Buyer body of `POST /auctions/{id}/purchase`.
+    pub async fn character_body(&self, name: &str) -> Value {
+        let (status, body) = self.get(&format!("/characters/{}", name)).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+        body
+    }
+}
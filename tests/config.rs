@@ -0,0 +1,36 @@
+use rpg_server::config::Config;
+
+fn with_database(url: &str, local: bool, replica_path: &str) -> Config {
+    let mut config = Config::default();
+    config.database.url = url.to_string();
+    config.database.auth_token = "token".to_string();
+    config.database.local = local;
+    config.database.replica_path = replica_path.to_string();
+    config
+}
+
+#[test]
+fn a_database_url_is_required_unless_the_database_is_local() {
+    assert!(Config::default().validate().is_err());
+    assert!(
+        with_database("libsql://rpg.turso.io", false, "local.db")
+            .validate()
+            .is_ok()
+    );
+    assert!(with_database("", true, "local.db").validate().is_ok());
+    assert!(with_database("", false, ":memory:").validate().is_ok());
+    assert!(
+        with_database("libsql://rpg.turso.io", true, "local.db")
+            .validate()
+            .is_err()
+    );
+}
+
+#[test]
+fn the_auction_price_is_bounded() {
+    let mut config = with_database("", true, "local.db");
+    config.economy.auction_price = 1_000_000_000;
+    assert!(config.validate().is_ok());
+    config.economy.auction_price = u64::MAX;
+    assert!(config.validate().is_err());
+}
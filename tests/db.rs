@@ -0,0 +1,59 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use tokio::time::timeout;
+
+// An open transaction only holds its own connection, the other requests keep being served
+#[tokio::test]
+async fn requests_are_served_while_a_transaction_is_open() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 50).await;
+
+    let transaction = app.state.conn.transaction().await.unwrap();
+    let mut rows = transaction
+        .query("SELECT gold FROM characters WHERE name = 'aria'", ())
+        .await
+        .unwrap();
+    assert_eq!(
+        rows.next().await.unwrap().unwrap().get::<u64>(0).unwrap(),
+        50
+    );
+    drop(rows);
+
+    let (status, character) = timeout(Duration::from_secs(1), app.get("/characters/aria"))
+        .await
+        .expect("the request waited for the transaction");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(character["gold"], 50);
+    transaction.commit().await.unwrap();
+}
+
+// Statements outside a transaction don't end up in it, and don't see what it hasn't committed
+#[tokio::test]
+async fn rolled_back_transactions_leave_other_statements_alone() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 50).await;
+
+    let transaction = app.state.conn.transaction().await.unwrap();
+    transaction
+        .execute("UPDATE characters SET gold = 10 WHERE name = 'aria'", ())
+        .await
+        .unwrap();
+    let read = tokio::spawn({
+        let conn = app.state.conn.clone();
+        async move {
+            let mut rows = conn
+                .query("SELECT gold FROM characters WHERE name = 'aria'", ())
+                .await
+                .unwrap();
+            rows.next().await.unwrap().unwrap().get::<u64>(0).unwrap()
+        }
+    });
+    drop(transaction);
+
+    assert_eq!(read.await.unwrap(), 50);
+    assert_eq!(app.character_body("aria").await["gold"], 50);
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use rpg_server::config::Config;
+use serde_json::{Value, json};
+
+async fn create_weapon(app: &TestApp, body: Value) -> Value {
+    let (status, item) = app.post("/items", body).await;
+    assert_eq!(status, StatusCode::CREATED, "{item}");
+    item
+}
+
+async fn equip(
+    app: &TestApp,
+    character: &str,
+    slot: &str,
+    instance: &Value,
+) -> (StatusCode, Value) {
+    app.post(
+        &format!("/characters/{character}/equipment/{slot}"),
+        json!({ "instance_id": instance["id"] }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn equipped_items_show_up_with_their_stats() {
+    let app = TestApp::new().await;
+    app.create_character("brom", "warrior", 0).await;
+    let axe = create_weapon(
+        &app,
+        json!({ "name": "Axe", "kind": "weapon", "stat_ranges": { "strength": { "min": 4, "max": 4 }, "damage": { "min": 9, "max": 9 } } }),
+    )
+    .await;
+    let instance = app.loot_item("brom", &axe).await;
+
+    let (status, body) = equip(&app, "brom", "main_hand", &instance).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["equipped_slot"], "main_hand");
+
+    let (_, body) = app.get("/characters/brom/equipment").await;
+    assert_eq!(body[0]["id"], instance["id"]);
+
+    let (_, body) = app.get("/characters/brom").await;
+    assert!(body.get("equipment").is_none());
+    let (status, body) = app.get("/characters/brom?include=equipment").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["name"], "brom");
+    assert_eq!(body["equipment"][0]["id"], instance["id"]);
+    assert_eq!(
+        body["total_stats"],
+        json!({ "strength": 14, "intellect": 5, "agility": 5, "damage": 9 })
+    );
+
+    let (status, body) = app.delete("/characters/brom/equipment/main_hand").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["equipped_slot"], json!(null));
+    let (status, body) = app.delete("/characters/brom/equipment/main_hand").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "Nothing is equipped in this slot.");
+    let (status, _) = app.delete("/characters/brom/equipment/tail").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn equipping_checks_the_slot_class_and_level() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 0).await;
+    let sword = create_weapon(&app, json!({ "name": "Sword", "kind": "weapon" })).await;
+    let bow = create_weapon(
+        &app,
+        json!({ "name": "Bow", "kind": "weapon", "class_restriction": "ranger" }),
+    )
+    .await;
+    let staff = create_weapon(
+        &app,
+        json!({ "name": "Staff", "kind": "weapon", "level_requirement": 2 }),
+    )
+    .await;
+    let ore = app.create_item("Ore").await;
+
+    for (item, slot, message) in [
+        (&sword, "head", "This item cannot be equipped in this slot."),
+        (
+            &ore,
+            "off_hand",
+            "This item cannot be equipped in this slot.",
+        ),
+        (
+            &bow,
+            "main_hand",
+            "The character's class cannot use this item.",
+        ),
+        (
+            &staff,
+            "main_hand",
+            "The character's level is too low for this item.",
+        ),
+    ] {
+        let instance = app.loot_item("aria", item).await;
+        let (status, body) = equip(&app, "aria", slot, &instance).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body, message);
+    }
+
+    app.post("/characters/aria/experience", json!({ "amount": 100 }))
+        .await;
+    let (_, items) = app.get("/characters/aria/items").await;
+    let staff_instance = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|instance| instance["item_id"] == staff["id"])
+        .unwrap();
+    let (status, _) = equip(&app, "aria", "main_hand", staff_instance).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = equip(&app, "nobody", "main_hand", staff_instance).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This character does not exist.");
+}
+
+#[tokio::test]
+async fn equipped_items_free_their_slot_and_stay_put() {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config.inventory.warrior_slots = 1;
+    let app = TestApp::with_config(config).await;
+    app.create_character("brom", "warrior", 0).await;
+    let sword = create_weapon(&app, json!({ "name": "Sword", "kind": "weapon" })).await;
+
+    let first = app.loot_item("brom", &sword).await;
+    equip(&app, "brom", "main_hand", &first).await;
+    // The equipped sword left the inventory slot to a second one
+    let second = app.loot_item("brom", &sword).await;
+
+    // Swapping keeps one sword in the bags
+    let (status, _) = equip(&app, "brom", "main_hand", &second).await;
+    assert_eq!(status, StatusCode::OK);
+    // Unequipping needs a free slot
+    let (status, body) = app.delete("/characters/brom/equipment/main_hand").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "The inventory is full.");
+
+    let uri = format!("/characters/brom/items/{}", second["id"].as_str().unwrap());
+    let (status, body) = app.delete(&uri).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "This item is equipped.");
+    let (_, instance) = app.get(&uri).await;
+    let (status, body) = app.post("/characters/brom/auctions", instance).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "This item is equipped.");
+}
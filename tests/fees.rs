@@ -0,0 +1,119 @@
+mod common;
+
+use axum::http::StatusCode;
+use chrono::TimeDelta;
+use common::TestApp;
+use rpg_server::{clock::Clock, config::Config, handlers::auctions::expire_auctions_libsql_query};
+use serde_json::{Value, json};
+
+// Auctions at 200 gold, with a 10% deposit and a 25% sales tax
+async fn setup() -> TestApp {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config.economy.auction_price = 200;
+    config.economy.auction_deposit_percent = 10;
+    config.economy.auction_tax_percent = 25;
+    let app = TestApp::with_config(config).await;
+    app.create_character("aria", "mage", 50).await;
+    app.create_character("borin", "warrior", 500).await;
+    app
+}
+
+async fn list_sword(app: &TestApp) -> Value {
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+    app.create_auction("aria", &instance).await
+}
+
+#[tokio::test]
+async fn sales_refund_the_deposit_and_withhold_the_tax() {
+    let app = setup().await;
+    let auction = list_sword(&app).await;
+    assert_eq!(auction["deposit"], 20);
+    assert_eq!(app.character_body("aria").await["gold"], 30);
+
+    let buyer = app.character_body("borin").await;
+    let (status, _) = app
+        .post(
+            &format!("/auctions/{}/purchase", auction["id"].as_str().unwrap()),
+            buyer,
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(app.character_body("borin").await["gold"], 300);
+    // 30 + 200 - 50 (tax) + 20 (deposit)
+    assert_eq!(app.character_body("aria").await["gold"], 200);
+
+    let (status, fees) = app.get("/fees?character=aria").await;
+    assert_eq!(status, StatusCode::OK);
+    let fees: Vec<(&str, u64)> = fees
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|fee| {
+            (
+                fee["kind"].as_str().unwrap(),
+                fee["amount"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        fees,
+        [
+            ("listing_deposit", 20),
+            ("sales_tax", 50),
+            ("deposit_refund", 20)
+        ]
+    );
+
+    let (_, summary) = app.get("/fees/summary").await;
+    assert_eq!(
+        summary,
+        json!({
+            "deposits_charged": 20,
+            "deposits_refunded": 20,
+            "deposits_held": 0,
+            "deposits_forfeited": 0,
+            "sales_tax": 50,
+            "removed_from_circulation": 50
+        })
+    );
+}
+
+#[tokio::test]
+async fn expired_auctions_forfeit_their_deposit() {
+    let app = setup().await;
+    list_sword(&app).await;
+
+    let (_, summary) = app.get("/fees/summary").await;
+    assert_eq!(summary["deposits_held"], 20);
+    assert_eq!(summary["removed_from_circulation"], 0);
+
+    app.clock.advance(TimeDelta::seconds(61));
+    expire_auctions_libsql_query(&app.state, app.clock.now())
+        .await
+        .unwrap();
+    assert_eq!(app.character_body("aria").await["gold"], 30);
+
+    let (_, summary) = app.get("/fees/summary").await;
+    assert_eq!(summary["deposits_held"], 0);
+    assert_eq!(summary["deposits_forfeited"], 20);
+    assert_eq!(summary["removed_from_circulation"], 20);
+
+    let (status, _) = app.get("/fees?kind=unknown").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn listing_requires_gold_for_the_deposit() {
+    let app = setup().await;
+    app.patch("/characters/aria", json!({ "gold": 19 })).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+
+    let (status, _) = app.post("/characters/aria/auctions", instance).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(app.character_body("aria").await["gold"], 19);
+    assert_eq!(app.get("/auctions").await.1, json!([]));
+    assert_eq!(app.get("/fees").await.1, json!([]));
+}
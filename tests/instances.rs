@@ -0,0 +1,131 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use serde_json::{Value, json};
+
+async fn create_sword(app: &TestApp, binds_on_loot: bool) -> Value {
+    let (status, body) = app
+        .post(
+            "/items",
+            json!({
+                "name": "Runed Sword",
+                "kind": "weapon",
+                "max_durability": 80,
+                "stat_ranges": {
+                    "damage": { "min": 10, "max": 15 },
+                    "speed": { "min": 3, "max": 3 }
+                },
+                "binds_on_loot": binds_on_loot
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    body
+}
+
+fn purchase_uri(auction: &Value) -> String {
+    format!("/auctions/{}/purchase", auction["id"].as_str().unwrap())
+}
+
+#[tokio::test]
+async fn looted_instances_roll_their_properties() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 0).await;
+    let sword = create_sword(&app, false).await;
+
+    let instance = app.loot_item("aria", &sword).await;
+    assert_eq!(instance["durability"], 80);
+    assert_eq!(instance["enchantment"], 0);
+    assert_eq!(instance["soulbound"], false);
+    assert_eq!(instance["stats"]["speed"], 3);
+    let damage = instance["stats"]["damage"].as_i64().unwrap();
+    assert!((10..=15).contains(&damage), "{damage}");
+
+    let (status, body) = app
+        .get(&format!(
+            "/characters/aria/items/{}",
+            instance["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, instance);
+
+    // Items without rolls have plain instances
+    let ore = app.create_stackable_item("Iron Ore", 10).await;
+    let stack = app.loot_item("aria", &ore).await;
+    assert_eq!(stack["durability"], json!(null));
+    assert_eq!(stack["stats"], json!({}));
+}
+
+#[tokio::test]
+async fn auctions_show_and_sell_the_listed_instance() {
+    let app = TestApp::new().await;
+    // Enough for the listing deposit
+    app.create_character("aria", "mage", 5).await;
+    app.create_character("brom", "warrior", 500).await;
+    let sword = create_sword(&app, false).await;
+    let listed = app.loot_item("aria", &sword).await;
+    let kept = app.loot_item("aria", &sword).await;
+
+    let auction = app.create_auction("aria", &listed).await;
+    assert_eq!(auction["auctioned_instance_id"], listed["id"]);
+    assert_eq!(auction["durability"], listed["durability"]);
+    assert_eq!(auction["enchantment"], listed["enchantment"]);
+    assert_eq!(auction["stats"], listed["stats"]);
+    let (_, body) = app
+        .get(&format!("/auctions/{}", auction["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(body, auction);
+
+    let buyer = app.character_body("brom").await;
+    let (status, body) = app.post(&purchase_uri(&auction), buyer).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+
+    let (_, body) = app.get("/characters/brom/items").await;
+    assert_eq!(body[0]["id"], listed["id"]);
+    assert_eq!(body[0]["stats"], listed["stats"]);
+    let (_, body) = app.get("/characters/aria/items").await;
+    assert_eq!(body, json!([kept]));
+}
+
+#[tokio::test]
+async fn soulbound_instances_cannot_be_auctioned() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 0).await;
+    let sword = create_sword(&app, true).await;
+    let instance = app.loot_item("aria", &sword).await;
+    assert_eq!(instance["soulbound"], true);
+
+    let (status, body) = app.post("/characters/aria/auctions", instance).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "This item is soulbound.");
+    assert_eq!(app.get("/characters/aria/auctions").await.1, json!([]));
+}
+
+#[tokio::test]
+async fn invalid_instance_properties_are_rejected() {
+    let app = TestApp::new().await;
+    for (body, message) in [
+        (
+            json!({ "name": "Ore", "max_stack": 10, "max_durability": 5 }),
+            "Stackable items cannot have durability or stat rolls.",
+        ),
+        (
+            json!({ "name": "Ore", "max_stack": 10, "stat_ranges": { "luck": { "min": 1, "max": 2 } } }),
+            "Stackable items cannot have durability or stat rolls.",
+        ),
+        (
+            json!({ "name": "Sword", "stat_ranges": { "damage": { "min": 5, "max": 2 } } }),
+            "A stat range's min cannot be above its max.",
+        ),
+        (
+            json!({ "name": "Sword", "max_durability": 0 }),
+            "The max durability must be at least 1.",
+        ),
+    ] {
+        let (status, response) = app.post("/items", body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+        assert_eq!(response, message);
+    }
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use rpg_server::config::Config;
+use serde_json::{Value, json};
+
+// Mages get 2 slots, warriors 1
+async fn small_inventories() -> TestApp {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config.inventory.mage_slots = 2;
+    config.inventory.warrior_slots = 1;
+    TestApp::with_config(config).await
+}
+
+async fn loot(app: &TestApp, character: &str, item: &Value) -> (StatusCode, Value) {
+    app.post(
+        &format!(
+            "/characters/{}/items/{}",
+            character,
+            item["id"].as_str().unwrap()
+        ),
+        json!({}),
+    )
+    .await
+}
+
+fn purchase_uri(auction: &Value) -> String {
+    format!("/auctions/{}/purchase", auction["id"].as_str().unwrap())
+}
+
+#[tokio::test]
+async fn looting_stops_when_the_inventory_is_full() {
+    let app = small_inventories().await;
+    app.create_character("aria", "mage", 0).await;
+    let sword = app.create_item("Iron Sword").await;
+    assert_eq!(sword["max_stack"], 1);
+
+    app.loot_item("aria", &sword).await;
+    app.loot_item("aria", &sword).await;
+    let (status, body) = loot(&app, "aria", &sword).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "The inventory is full.");
+    assert_eq!(
+        app.get("/characters/aria/items")
+            .await
+            .1
+            .as_array()
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn stackable_items_share_a_slot_up_to_their_max_stack() {
+    let app = small_inventories().await;
+    app.create_character("borin", "warrior", 0).await;
+    let potion = app.create_stackable_item("Health Potion", 3).await;
+
+    let first = app.loot_item("borin", &potion).await;
+    assert_eq!(first["quantity"], 1);
+    app.loot_item("borin", &potion).await;
+    let third = app.loot_item("borin", &potion).await;
+    assert_eq!(third["id"], first["id"]);
+    assert_eq!(third["quantity"], 3);
+
+    // The stack is full and it was the only slot
+    let (status, _) = loot(&app, "borin", &potion).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn max_stack_must_be_positive() {
+    let app = TestApp::new().await;
+
+    let (status, body) = app
+        .post("/items", json!({ "name": "Arrow", "max_stack": 0 }))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body, "The max stack must be at least 1.");
+}
+
+#[tokio::test]
+async fn purchase_is_rejected_when_the_buyer_inventory_is_full() {
+    let app = small_inventories().await;
+    // Enough for the listing deposit
+    app.create_character("aria", "mage", 5).await;
+    app.create_character("borin", "warrior", 500).await;
+    let sword = app.create_item("Iron Sword").await;
+    let shield = app.create_item("Oak Shield").await;
+    let instance = app.loot_item("aria", &sword).await;
+    app.loot_item("borin", &shield).await;
+    let auction = app.create_auction("aria", &instance).await;
+
+    let buyer = app.character_body("borin").await;
+    let (status, body) = app.post(&purchase_uri(&auction), buyer).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "The inventory is full.");
+
+    // Nothing happened, the deposit is still held
+    assert_eq!(app.character_body("borin").await["gold"], 500);
+    assert_eq!(app.character_body("aria").await["gold"], 0);
+    assert_eq!(app.get("/characters/aria/items").await.1, json!([instance]));
+    let (_, active) = app.get("/auctions?status=active").await;
+    assert_eq!(active.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn purchasing_a_stackable_item_moves_a_single_unit() {
+    let app = small_inventories().await;
+    // Enough for the listing deposit
+    app.create_character("aria", "mage", 5).await;
+    app.create_character("borin", "warrior", 500).await;
+    let potion = app.create_stackable_item("Health Potion", 5).await;
+    app.loot_item("aria", &potion).await;
+    let stack = app.loot_item("aria", &potion).await;
+    let owned = app.loot_item("borin", &potion).await;
+    let auction = app.create_auction("aria", &stack).await;
+
+    let buyer = app.character_body("borin").await;
+    let (status, _) = app.post(&purchase_uri(&auction), buyer).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (_, seller_items) = app.get("/characters/aria/items").await;
+    assert_eq!(seller_items[0]["id"], stack["id"]);
+    assert_eq!(seller_items[0]["quantity"], 1);
+    // Joined the buyer's stack, even with no free slot
+    let (_, buyer_items) = app.get("/characters/borin/items").await;
+    assert_eq!(buyer_items.as_array().unwrap().len(), 1);
+    assert_eq!(buyer_items[0]["id"], owned["id"]);
+    assert_eq!(buyer_items[0]["quantity"], 2);
+}
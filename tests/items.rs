@@ -0,0 +1,302 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use serde_json::json;
+
+#[tokio::test]
+async fn create_list_and_get_items() {
+    let app = TestApp::new().await;
+    assert_eq!(app.get("/items").await.1, json!([]));
+
+    let item = app.create_item("Iron Sword").await;
+    assert_eq!(item["name"], "Iron Sword");
+    assert_eq!(item["id"].as_str().unwrap().len(), 36);
+
+    let (status, body) = app.get("/items").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!([item]));
+
+    let (status, body) = app
+        .get(&format!("/items/{}", item["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, item);
+}
+
+#[tokio::test]
+async fn create_item_rejects_empty_names() {
+    let app = TestApp::new().await;
+    let (status, body) = app.post("/items", json!({ "name": "" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body, "The name provided is empty.");
+    assert_eq!(app.get("/items").await.1, json!([]));
+}
+
+#[tokio::test]
+async fn unknown_item_routes_return_not_found() {
+    let app = TestApp::new().await;
+    let id = "00000000-0000-0000-0000-000000000000";
+
+    for uri in [
+        format!("/items/{id}"),
+        format!("/items/{id}/auctions"),
+        format!("/items/{id}/auctions/{id}"),
+    ] {
+        let (status, _) = app.get(&uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+    }
+    let (status, _) = app
+        .patch(&format!("/items/{id}"), json!({ "name": "Axe" }))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.delete(&format!("/items/{id}")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn renaming_an_item_renames_its_instances() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    app.loot_item("aria", &item).await;
+
+    let (status, body) = app
+        .patch(
+            &format!("/items/{}", item["id"].as_str().unwrap()),
+            json!({ "name": "Steel Sword" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["name"], "Steel Sword");
+
+    let (_, instances) = app.get("/characters/aria/items").await;
+    assert_eq!(instances[0]["item_name"], "Steel Sword");
+}
+
+#[tokio::test]
+async fn deleting_an_item_cascades_to_instances_and_auctions() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let other = app.create_item("Wooden Shield").await;
+    let instance = app.loot_item("aria", &item).await;
+    let kept = app.loot_item("aria", &other).await;
+    app.create_auction("aria", &instance).await;
+
+    let (status, body) = app
+        .delete(&format!("/items/{}", item["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, item);
+
+    assert_eq!(app.get("/characters/aria/items").await.1, json!([kept]));
+    assert_eq!(app.get("/auctions").await.1, json!([]));
+    assert_eq!(app.get("/items").await.1, json!([other]));
+}
+
+#[tokio::test]
+async fn list_and_get_item_auctions() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let other = app.create_item("Wooden Shield").await;
+    let auction = app
+        .create_auction("aria", &app.loot_item("aria", &item).await)
+        .await;
+    let other_auction = app
+        .create_auction("aria", &app.loot_item("aria", &other).await)
+        .await;
+    let item_id = item["id"].as_str().unwrap();
+
+    let (status, body) = app.get(&format!("/items/{item_id}/auctions")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["id"], auction["id"]);
+
+    let (status, body) = app
+        .get(&format!(
+            "/items/{item_id}/auctions/{}",
+            auction["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["id"], auction["id"]);
+
+    // An auction for another item isn't reachable through this one
+    let (status, _) = app
+        .get(&format!(
+            "/items/{item_id}/auctions/{}",
+            other_auction["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// `/items/{id}/auctions/{auction_id}` takes the item's id, not the id of one of its instances
+#[tokio::test]
+async fn item_auction_route_looks_up_the_item_definition() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+    let auction = app.create_auction("aria", &instance).await;
+    let auction_id = auction["id"].as_str().unwrap();
+
+    let (status, body) = app
+        .get(&format!(
+            "/items/{}/auctions/{auction_id}",
+            item["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["auctioned_item_id"], item["id"]);
+
+    let (status, body) = app
+        .get(&format!(
+            "/items/{}/auctions/{auction_id}",
+            instance["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This item does not exist.");
+}
+
+#[tokio::test]
+async fn items_are_described_with_defaults() {
+    let app = TestApp::new().await;
+    let item = app.create_item("Iron Ore").await;
+    assert_eq!(item["kind"], "material");
+    assert_eq!(item["rarity"], "common");
+    assert_eq!(item["level_requirement"], 1);
+    assert_eq!(item["class_restriction"], json!(null));
+    assert_eq!(item["base_value"], 0);
+    assert_eq!(item["description"], "");
+    assert_eq!(item["attributes"], json!({}));
+
+    let (status, staff) = app
+        .post(
+            "/items",
+            json!({
+                "name": "Staff of Embers",
+                "kind": "weapon",
+                "rarity": "epic",
+                "level_requirement": 30,
+                "class_restriction": "mage",
+                "base_value": 2500,
+                "description": "Still warm.",
+                "attributes": { "damage": 42, "effects": ["burn"] }
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (_, body) = app
+        .get(&format!("/items/{}", staff["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(body, staff);
+    assert_eq!(body["attributes"]["effects"], json!(["burn"]));
+}
+
+#[tokio::test]
+async fn list_items_with_filters() {
+    let app = TestApp::new().await;
+    let ore = app.create_item("Iron Ore").await;
+    let (_, staff) = app
+        .post(
+            "/items",
+            json!({ "name": "Staff", "kind": "weapon", "rarity": "rare", "level_requirement": 30, "class_restriction": "mage" }),
+        )
+        .await;
+    let (_, bow) = app
+        .post(
+            "/items",
+            json!({ "name": "Bow", "kind": "weapon", "rarity": "rare", "level_requirement": 10, "class_restriction": "ranger" }),
+        )
+        .await;
+
+    assert_eq!(app.get("/items?kind=weapon").await.1, json!([staff, bow]));
+    assert_eq!(app.get("/items?rarity=common").await.1, json!([ore]));
+    assert_eq!(app.get("/items?class=mage").await.1, json!([ore, staff]));
+    assert_eq!(
+        app.get("/items?min_level=5&max_level=20").await.1,
+        json!([bow])
+    );
+    assert_eq!(
+        app.get("/items?kind=weapon&class=ranger").await.1,
+        json!([bow])
+    );
+
+    let (status, _) = app.get("/items?rarity=mythic").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn invalid_item_definitions_are_rejected() {
+    let app = TestApp::new().await;
+    for (body, message) in [
+        (
+            json!({ "name": "Sword", "kind": "weapon", "max_stack": 5 }),
+            "Weapons and armor cannot be stacked.",
+        ),
+        (
+            json!({ "name": "Sword", "level_requirement": 0 }),
+            "The level requirement must be between 1 and 100.",
+        ),
+        (
+            json!({ "name": "Sword", "level_requirement": 101 }),
+            "The level requirement must be between 1 and 100.",
+        ),
+        (
+            json!({ "name": "Sword", "description": "a".repeat(1001) }),
+            "The description cannot be longer than 1000 characters.",
+        ),
+        (
+            json!({ "name": "Sword", "attributes": [1, 2] }),
+            "The attributes must be a JSON object.",
+        ),
+    ] {
+        let (status, response) = app.post("/items", body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+        assert_eq!(response, message);
+    }
+    let (status, _) = app
+        .post("/items", json!({ "name": "Sword", "kind": "trinket" }))
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(app.get("/items").await.1, json!([]));
+}
+
+#[tokio::test]
+async fn patching_an_item_only_changes_the_given_fields() {
+    let app = TestApp::new().await;
+    let (_, item) = app
+        .post(
+            "/items",
+            json!({ "name": "Bow", "kind": "weapon", "class_restriction": "ranger", "base_value": 40 }),
+        )
+        .await;
+    let uri = format!("/items/{}", item["id"].as_str().unwrap());
+
+    let (status, body) = app
+        .patch(
+            &uri,
+            json!({ "rarity": "uncommon", "attributes": { "range": 12 } }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["name"], "Bow");
+    assert_eq!(body["rarity"], "uncommon");
+    assert_eq!(body["class_restriction"], "ranger");
+    assert_eq!(body["base_value"], 40);
+    assert_eq!(body["attributes"], json!({ "range": 12 }));
+
+    let (_, body) = app.patch(&uri, json!({ "class_restriction": null })).await;
+    assert_eq!(body["class_restriction"], json!(null));
+    assert_eq!(app.get(&uri).await.1, body);
+
+    let (status, _) = app.patch(&uri, json!({ "name": "" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app.patch(&uri, json!({ "level_requirement": 0 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(app.get(&uri).await.1, body);
+}
@@ -0,0 +1,206 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use serde_json::{Value, json};
+
+async fn create_table(app: &TestApp, body: Value) -> Value {
+    let (status, table) = app.post("/loot-tables", body).await;
+    assert_eq!(status, StatusCode::CREATED, "{table}");
+    table
+}
+
+fn loot_uri(character: &str, table: &Value) -> String {
+    format!(
+        "/characters/{character}/loot/{}",
+        table["id"].as_str().unwrap()
+    )
+}
+
+#[tokio::test]
+async fn loot_tables_crud() {
+    let app = TestApp::new().await;
+    let sword = app.create_item("Sword").await;
+
+    let table = create_table(
+        &app,
+        json!({ "name": "Goblin", "entries": [{ "item_id": sword["id"], "weight": 3 }] }),
+    )
+    .await;
+    assert_eq!(table["rolls"], 1);
+    assert_eq!(table["entries"][0]["max_quantity"], 1);
+    assert_eq!(table["entries"][0]["guaranteed"], false);
+    let uri = format!("/loot-tables/{}", table["id"].as_str().unwrap());
+
+    let (status, body) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, table);
+
+    let (status, body) = app.patch(&uri, json!({ "rolls": 3, "max_gold": 10 })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["rolls"], 3);
+    assert_eq!(body["max_gold"], 10);
+    assert_eq!(body["name"], "Goblin");
+
+    let (status, body) = app.get("/loot-tables").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+
+    let (status, _) = app.delete(&uri).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn invalid_loot_tables_are_rejected() {
+    let app = TestApp::new().await;
+    let sword = app.create_item("Sword").await;
+
+    for body in [
+        json!({ "name": "" }),
+        json!({ "name": "Goblin", "min_gold": 5, "max_gold": 1 }),
+        json!({ "name": "Goblin", "rolls": 101 }),
+        json!({ "name": "Goblin", "nothing_weight": 1_000_001 }),
+        json!({ "name": "Goblin", "entries": [{ "item_id": sword["id"], "weight": u64::MAX }, { "rarity": "rare", "weight": u64::MAX }] }),
+        json!({ "name": "Goblin", "rolls": 100, "entries": [{ "item_id": sword["id"], "max_quantity": 11 }] }),
+        json!({ "name": "Goblin", "entries": [{ "item_id": sword["id"], "min_quantity": 1000, "max_quantity": 1000, "guaranteed": true }, { "rarity": "rare" }] }),
+        json!({ "name": "Goblin", "max_gold": 1_000_000_001u64 }),
+        json!({ "name": "Goblin", "min_gold": u64::MAX, "max_gold": u64::MAX }),
+        json!({ "name": "Goblin", "entries": [{}] }),
+        json!({ "name": "Goblin", "entries": [{ "item_id": sword["id"], "rarity": "rare" }] }),
+        json!({ "name": "Goblin", "entries": [{ "item_id": sword["id"], "min_quantity": 0 }] }),
+        json!({ "name": "Goblin", "entries": [{ "rarity": "rare", "min_quantity": 3, "max_quantity": 2 }] }),
+    ] {
+        let (status, _) = app.post("/loot-tables", body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    }
+    // Right at the limits
+    let (status, _) = app
+        .post(
+            "/loot-tables",
+            json!({ "name": "Goblin", "rolls": 100, "nothing_weight": 1_000_000, "entries": [{ "item_id": sword["id"], "weight": 1_000_000, "max_quantity": 10 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = app
+        .post(
+            "/loot-tables",
+            json!({ "name": "Goblin", "entries": [{ "item_id": "00000000-0000-0000-0000-000000000000" }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn loot_rolls_are_reproducible_from_their_seed() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 0).await;
+    app.create_character("brom", "warrior", 0).await;
+    let coin = app.create_stackable_item("Coin", 100).await;
+    let gem = app.create_stackable_item("Gem", 100).await;
+    let (status, rare) = app
+        .post(
+            "/items",
+            json!({ "name": "Ruby", "rarity": "rare", "max_stack": 10 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let table = create_table(
+        &app,
+        json!({
+            "name": "Chest",
+            "rolls": 5,
+            "nothing_weight": 2,
+            "min_gold": 1,
+            "max_gold": 50,
+            "entries": [
+                { "item_id": coin["id"], "weight": 5, "min_quantity": 1, "max_quantity": 4 },
+                { "item_id": gem["id"], "weight": 1 },
+                { "rarity": "rare", "guaranteed": true }
+            ]
+        }),
+    )
+    .await;
+
+    let (status, roll) = app
+        .post(&loot_uri("aria", &table), json!({ "seed": 42 }))
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{roll}");
+    assert_eq!(roll["seed"], 42);
+    let drops = roll["drops"].as_array().unwrap();
+    // Guaranteed drops come first
+    assert_eq!(drops[0]["item_id"], rare["id"]);
+    assert_eq!(drops[0]["guaranteed"], true);
+    let gold = roll["gold"].as_u64().unwrap();
+    assert!((1..=50).contains(&gold));
+    assert_eq!(app.character_body("aria").await["gold"], gold);
+
+    // Every dropped unit ended up in the inventory
+    let dropped: u64 = drops
+        .iter()
+        .map(|drop| drop["quantity"].as_u64().unwrap())
+        .sum();
+    let (_, items) = app.get("/characters/aria/items").await;
+    let owned: u64 = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|instance| instance["quantity"].as_u64().unwrap())
+        .sum();
+    assert_eq!(owned, dropped);
+
+    let (status, replay) = app
+        .post(&loot_uri("brom", &table), json!({ "seed": 42 }))
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(replay["drops"], roll["drops"]);
+    assert_eq!(replay["gold"], roll["gold"]);
+
+    // Without a seed, one is picked and returned
+    let (status, roll) = app.post(&loot_uri("aria", &table), json!({})).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(roll["seed"].is_u64());
+}
+
+#[tokio::test]
+async fn loot_is_all_or_nothing_when_the_inventory_is_full() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 0).await;
+    let sword = app.create_item("Sword").await;
+    for _ in 0..15 {
+        app.loot_item("aria", &sword).await;
+    }
+    let table = create_table(
+        &app,
+        json!({
+            "name": "Armory",
+            "min_gold": 10,
+            "max_gold": 10,
+            "entries": [{ "item_id": sword["id"], "min_quantity": 2, "max_quantity": 2, "guaranteed": true }]
+        }),
+    )
+    .await;
+
+    let (status, _) = app.post(&loot_uri("aria", &table), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        app.get("/characters/aria/items")
+            .await
+            .1
+            .as_array()
+            .unwrap()
+            .len(),
+        15
+    );
+    assert_eq!(app.character_body("aria").await["gold"], 0);
+
+    let (status, _) = app
+        .post(
+            "/characters/aria/loot/00000000-0000-0000-0000-000000000000",
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use rpg_server::{
+    db::{self, Connection},
+    handlers::auctions::Auction,
+    into_rows,
+    metrics::Metrics,
+    replica::Replica,
+};
+
+// Auctions as they were stored before their dates became Unix milliseconds
+#[tokio::test]
+async fn text_auction_dates_are_migrated_to_millis() {
+    let metrics = Arc::new(Metrics::default());
+    let database = db::open_local(":memory:").await.unwrap();
+    let replica = Arc::new(Replica::local(database, metrics.clone()));
+    let conn = Connection::new(replica, metrics).await.unwrap();
+    conn.execute(
+        "CREATE TABLE schema_migrations (version INTEGER PRIMARY KEY, applied_at TEXT)",
+        (),
+    )
+    .await
+    .unwrap();
+    for version in 1..=4 {
+        conn.execute(
+            "INSERT INTO schema_migrations (version, applied_at) VALUES (?1, datetime('now'))",
+            [version],
+        )
+        .await
+        .unwrap();
+    }
+    conn.execute_batch(
+        "CREATE TABLE characters (name TEXT PRIMARY KEY);
+        INSERT INTO characters VALUES ('aria');
+        CREATE TABLE items (id TEXT PRIMARY KEY);
+        INSERT INTO items VALUES ('00000000-0000-0000-0000-000000000000');
+        CREATE TABLE items_instances (id TEXT PRIMARY KEY, owner_name TEXT);",
+    )
+    .await
+    .unwrap();
+    conn.execute(
+        "CREATE TABLE auctions (
+        id TEXT PRIMARY KEY,
+        auctioned_item_id TEXT NOT NULL,
+        seller_name TEXT NOT NULL,
+        creation_date TEXT NOT NULL,
+        end_date TEXT NOT NULL,
+        price INTEGER NOT NULL,
+        status TEXT NOT NULL
+        )",
+        (),
+    )
+    .await
+    .unwrap();
+    conn.execute(
+        "INSERT INTO auctions VALUES
+        ('11111111-1111-1111-1111-111111111111', '00000000-0000-0000-0000-000000000000', 'aria', '2025-01-01 12:00:00', '2025-01-01 12:01:00', 100, 'active'),
+        ('22222222-2222-2222-2222-222222222222', '00000000-0000-0000-0000-000000000000', 'aria', '2025-01-01T12:00:00Z', '2025-01-01T12:01:00.250Z', 100, 'sold')",
+        (),
+    )
+    .await
+    .unwrap();
+
+    db::run_migrations(&conn).await.unwrap();
+    assert_eq!(db::pending_migrations(&conn).await.unwrap(), 0);
+
+    let rows = conn
+        .query("SELECT * FROM auctions ORDER BY id", ())
+        .await
+        .unwrap();
+    let auctions = into_rows::<Auction>(rows).await.unwrap();
+    assert_eq!(auctions.len(), 2);
+    assert_eq!(
+        auctions[0].creation_date.to_rfc3339(),
+        "2025-01-01T12:00:00+00:00"
+    );
+    assert_eq!(
+        auctions[0].end_date.to_rfc3339(),
+        "2025-01-01T12:01:00+00:00"
+    );
+    assert_eq!(
+        auctions[1].end_date.to_rfc3339(),
+        "2025-01-01T12:01:00.250+00:00"
+    );
+
+    let mut rows = conn
+        .query(
+            "SELECT count(*) FROM auctions WHERE typeof(creation_date) = 'integer' AND typeof(end_date) = 'integer'",
+            (),
+        )
+        .await
+        .unwrap();
+    let row = rows.next().await.unwrap().unwrap();
+    assert_eq!(row.get::<u64>(0).unwrap(), 2);
+}
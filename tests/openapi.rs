@@ -0,0 +1,155 @@
+mod common;
+
+use std::collections::BTreeSet;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use rpg_server::{build_app, openapi::ApiDoc};
+use serde_json::{Value, json};
+use utoipa::OpenApi;
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+// Routes served by the router but not described by the spec
+const UNDOCUMENTED: [&str; 2] = ["/openapi.json", "/docs"];
+
+// An app where every path parameter points to something that exists, so that the
+// middlewares let the requests reach the method routing
+async fn populated_app() -> (TestApp, Value, Value, Value) {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+    let auction = app.create_auction("aria", &instance).await;
+    (app, item, instance, auction)
+}
+
+// Documented path -> a concrete URI matching it
+fn concrete_uri(path: &str, item: &Value, instance: &Value, auction: &Value) -> String {
+    let auction_id = auction["id"].as_str().unwrap();
+    let unversioned = path.trim_start_matches("/v1").trim_start_matches("/v2");
+    let id = if unversioned.starts_with("/items") {
+        item["id"].as_str().unwrap()
+    } else {
+        auction_id
+    };
+    path.replace("{name}", "aria")
+        .replace("{id}", id)
+        .replace("{item_id}", instance["id"].as_str().unwrap())
+        .replace("{auction_id}", auction_id)
+        .replace("{slot}", "main_hand")
+        .replace("{table_id}", auction_id)
+        .replace("{recipe_id}", auction_id)
+}
+
+#[tokio::test]
+async fn openapi_document_is_served() {
+    let app = TestApp::new().await;
+
+    let (status, body) = app.get("/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["openapi"].as_str().unwrap().starts_with("3.1"));
+    for schema in [
+        "Character",
+        "Item",
+        "ItemInstance",
+        "Auction",
+        "AuctionStatus",
+    ] {
+        assert!(
+            body["components"]["schemas"][schema].is_object(),
+            "{schema}"
+        );
+    }
+    let parameters = &body["paths"]["/v2/auctions"]["get"]["parameters"];
+    assert_eq!(parameters[0]["name"], "status");
+    assert_eq!(parameters[0]["in"], "query");
+
+    let (status, body) = app.get("/docs").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.as_str().unwrap().contains("/openapi.json"));
+}
+
+#[tokio::test]
+async fn every_documented_operation_is_routed() {
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+    for (path, operations) in spec["paths"].as_object().unwrap() {
+        let (app, item, instance, auction) = populated_app().await;
+        let uri = concrete_uri(path, &item, &instance, &auction);
+        for method in METHODS {
+            let documented = operations.get(method).is_some();
+            let body = matches!(method, "post" | "put" | "patch").then(|| json!({}));
+            let (status, response) = app
+                .request(
+                    Method::from_bytes(method.to_uppercase().as_bytes()).unwrap(),
+                    &uri,
+                    body,
+                )
+                .await;
+
+            if documented {
+                // Unmatched routes answer 404 with an empty body, the handlers always explain theirs
+                assert_ne!(status, StatusCode::METHOD_NOT_ALLOWED, "{method} {path}");
+                assert!(
+                    status != StatusCode::NOT_FOUND || response != Value::String(String::new()),
+                    "{method} {path} is documented but not routed"
+                );
+            } else {
+                // The existence middlewares can answer before the method routing does
+                assert!(
+                    matches!(
+                        status,
+                        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_FOUND
+                    ),
+                    "{method} {path} is routed but not documented"
+                );
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn every_routed_path_is_documented() {
+    let app = TestApp::new().await;
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    let paths = spec["paths"].as_object().unwrap();
+    // The root serves the same routes as /v1
+    let aliases = paths
+        .keys()
+        .filter_map(|path| path.strip_prefix("/v1"))
+        .map(str::to_string);
+    let documented: BTreeSet<String> = paths
+        .keys()
+        .cloned()
+        .chain(aliases)
+        .chain(UNDOCUMENTED.iter().map(|path| path.to_string()))
+        .collect();
+
+    // axum can't list its routes, but its Debug output holds every registered path
+    let router = format!("{:?}", build_app(app.state.clone()));
+    let routed: BTreeSet<String> = router
+        .split('"')
+        .filter(|part| part.starts_with('/') && *part != "/" && !part.contains("__private__"))
+        .map(str::to_string)
+        .collect();
+    assert!(!routed.is_empty(), "no path found in the router");
+
+    assert_eq!(routed, documented);
+}
+
+#[tokio::test]
+async fn v1_operations_are_deprecated_in_the_spec() {
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+    let v1 = &spec["paths"]["/v1/characters/{name}/auctions/{id}"]["get"];
+    assert_eq!(v1["deprecated"], true);
+    assert_eq!(v1["operationId"], "v1_get_character_auction");
+    let v2 = &spec["paths"]["/v2/characters/{name}/auctions/{id}"]["get"];
+    assert!(v2.get("deprecated").is_none());
+    assert_eq!(v2["operationId"], "v2_get_character_auction_v2");
+    assert_eq!(
+        v2["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/Auction"
+    );
+}
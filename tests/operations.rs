@@ -0,0 +1,82 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use serde_json::json;
+
+#[tokio::test]
+async fn health_and_readiness() {
+    let app = TestApp::new().await;
+
+    let (status, _) = app.get("/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app.get("/readyz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({ "database": true, "migrations": true, "replica": true })
+    );
+}
+
+#[tokio::test]
+async fn replica_status_and_sync_on_a_local_database() {
+    let app = TestApp::new().await;
+
+    let (status, body) = app.get("/admin/replica").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["last_sync"], json!(null));
+    assert_eq!(body["seconds_since_last_sync"], json!(null));
+    assert!(body.get("lag_seconds").is_none());
+
+    let (status, _) = app.post("/admin/replica/sync", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn metrics_report_traffic_and_economy() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    app.create_character("borin", "warrior", 50).await;
+    app.get("/characters/aria").await;
+
+    let (status, body) = app.get("/metrics").await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.as_str().unwrap();
+    assert!(body.contains("rpg_gold_in_circulation 200"));
+    assert!(body.contains("rpg_auctions{status=\"active\"} 0"));
+    assert!(body.contains(
+        "rpg_http_requests_total{method=\"POST\",route=\"/characters\",status=\"201\"} 2"
+    ));
+    assert!(body.contains(
+        "rpg_http_requests_total{method=\"GET\",route=\"/characters/{name}\",status=\"200\"} 1"
+    ));
+    assert!(body.contains(
+        "rpg_db_query_duration_seconds_count{operation=\"insert\",table=\"characters\"} 2"
+    ));
+}
+
+// Gold held for a buy order is still in circulation
+#[tokio::test]
+async fn gold_in_circulation_includes_reserved_gold() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let (status, order) = app
+        .post(
+            "/buy-orders",
+            json!({ "character_name": "aria", "item_id": item["id"], "max_price": 40, "quantity": 2 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{order}");
+    assert_eq!(app.character_body("aria").await["reserved_gold"], 80);
+
+    let (_, body) = app.get("/metrics").await;
+    assert!(
+        body.as_str()
+            .unwrap()
+            .contains("rpg_gold_in_circulation 150")
+    );
+    let (_, supply) = app.get("/admin/economy/gold").await;
+    assert_eq!(supply["total_gold"], 150);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use rpg_server::config::Config;
+use serde_json::json;
+
+#[tokio::test]
+async fn new_characters_start_at_level_one_with_class_stats() {
+    let app = TestApp::new().await;
+    let (status, body) = app
+        .post(
+            "/characters",
+            json!({ "name": "aria", "class": "mage", "gold": 0, "level": 50, "experience": 9 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(body["level"], 1);
+    assert_eq!(body["experience"], 0);
+
+    let (status, body) = app.get("/characters/aria/stats").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({
+            "level": 1,
+            "experience": 0,
+            "experience_to_next_level": 100,
+            "stats": { "strength": 5, "intellect": 10, "agility": 5 }
+        })
+    );
+
+    app.create_character("brom", "warrior", 0).await;
+    app.create_character("cyra", "ranger", 0).await;
+    let (_, body) = app.get("/characters/brom/stats").await;
+    assert_eq!(
+        body["stats"],
+        json!({ "strength": 10, "intellect": 5, "agility": 5 })
+    );
+    let (_, body) = app.get("/characters/cyra/stats").await;
+    assert_eq!(
+        body["stats"],
+        json!({ "strength": 5, "intellect": 5, "agility": 10 })
+    );
+}
+
+#[tokio::test]
+async fn experience_levels_characters_up_along_the_curve() {
+    let app = TestApp::new().await;
+    app.create_character("brom", "warrior", 0).await;
+
+    let (status, body) = app
+        .post("/characters/brom/experience", json!({ "amount": 60 }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["level"], 1);
+    assert_eq!(body["experience"], 60);
+
+    // 100 to reach level 2, then 150 to reach level 3
+    let (_, body) = app
+        .post("/characters/brom/experience", json!({ "amount": 200 }))
+        .await;
+    assert_eq!(body["level"], 3);
+    assert_eq!(body["experience"], 10);
+    assert_eq!(body["experience_to_next_level"], 225);
+    assert_eq!(
+        body["stats"],
+        json!({ "strength": 16, "intellect": 7, "agility": 7 })
+    );
+
+    let character = app.character_body("brom").await;
+    assert_eq!(character["level"], 3);
+    assert_eq!(character["experience"], 10);
+
+    let (status, _) = app
+        .post("/characters/nobody/experience", json!({ "amount": 1 }))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn levels_stop_at_the_max_level() {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config.progression.base_experience = 1;
+    config.progression.experience_growth_percent = 0;
+    let app = TestApp::with_config(config).await;
+    app.create_character("aria", "mage", 0).await;
+
+    let (_, body) = app
+        .post("/characters/aria/experience", json!({ "amount": 1000 }))
+        .await;
+    assert_eq!(body["level"], 100);
+    assert_eq!(body["experience"], 0);
+    assert_eq!(body["experience_to_next_level"], json!(null));
+}
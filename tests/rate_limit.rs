@@ -0,0 +1,109 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use chrono::TimeDelta;
+use common::{TestApp, start_time};
+use rpg_server::{
+    config::{Config, RateLimitConfig},
+    rate_limit::{RateLimiter, RouteGroup},
+};
+use serde_json::json;
+
+fn config(rate_limit: RateLimitConfig) -> Config {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config.rate_limit = rate_limit;
+    config
+}
+
+#[tokio::test]
+async fn requests_over_the_burst_are_rejected_until_refilled() {
+    let app = TestApp::with_config(config(RateLimitConfig {
+        requests_per_minute: 60,
+        burst: 3,
+        ..RateLimitConfig::default()
+    }))
+    .await;
+
+    for _ in 0..3 {
+        assert_eq!(app.get("/characters").await.0, StatusCode::OK);
+    }
+    let (status, headers, body) = app
+        .request_with_headers(Method::GET, "/v2/items", None)
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(headers["retry-after"], "1");
+    assert_eq!(body, "Too many requests, slow down.");
+
+    // The operations routes aren't limited
+    assert_eq!(app.get("/healthz").await.0, StatusCode::OK);
+    assert_eq!(app.get("/metrics").await.0, StatusCode::OK);
+
+    app.clock.advance(TimeDelta::seconds(1));
+    assert_eq!(app.get("/characters").await.0, StatusCode::OK);
+    assert_eq!(
+        app.get("/characters").await.0,
+        StatusCode::TOO_MANY_REQUESTS
+    );
+}
+
+#[tokio::test]
+async fn marketplace_writes_have_their_own_tighter_limit() {
+    let app = TestApp::with_config(config(RateLimitConfig {
+        marketplace_requests_per_minute: 6,
+        marketplace_burst: 2,
+        ..RateLimitConfig::default()
+    }))
+    .await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+
+    app.create_auction("aria", &instance).await;
+    app.create_auction("aria", &instance).await;
+    let (status, headers, _) = app
+        .request_with_headers(
+            Method::POST,
+            "/characters/aria/auctions",
+            Some(instance.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(headers["retry-after"], "10");
+
+    // Buying counts against the same limit, reading doesn't
+    let (status, _) = app
+        .post(
+            "/auctions/00000000-0000-0000-0000-000000000000/purchase",
+            json!({ "name": "aria", "class": "mage", "gold": 150 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(app.get("/characters/aria/auctions").await.0, StatusCode::OK);
+
+    app.clock.advance(TimeDelta::seconds(10));
+    app.create_auction("aria", &instance).await;
+}
+
+#[test]
+fn clients_and_groups_have_separate_buckets() {
+    let limiter = RateLimiter::new(&RateLimitConfig {
+        requests_per_minute: 60,
+        burst: 1,
+        marketplace_requests_per_minute: 1,
+        marketplace_burst: 1,
+    });
+    let now = start_time();
+
+    assert_eq!(limiter.check(now, RouteGroup::Default, "10.0.0.1"), Ok(()));
+    assert_eq!(limiter.check(now, RouteGroup::Default, "10.0.0.1"), Err(1));
+    assert_eq!(limiter.check(now, RouteGroup::Default, "10.0.0.2"), Ok(()));
+    assert_eq!(
+        limiter.check(now, RouteGroup::Marketplace, "10.0.0.1"),
+        Ok(())
+    );
+    assert_eq!(
+        limiter.check(now, RouteGroup::Marketplace, "10.0.0.1"),
+        Err(60)
+    );
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use serde_json::{Value, json};
+
+// aria (warrior, 100 gold) and a recipe turning 3 ore and a plank into a sword for 40 gold
+async fn setup() -> (TestApp, Value, Value, Value) {
+    let app = TestApp::new().await;
+    app.create_character("aria", "warrior", 100).await;
+    let ore = app.create_stackable_item("Iron Ore", 2).await;
+    let plank = app.create_stackable_item("Plank", 10).await;
+    let sword = app.create_item("Sword").await;
+    let (status, recipe) = app
+        .post(
+            "/recipes",
+            json!({
+                "name": "Forge a sword",
+                "inputs": [
+                    { "item_id": ore["id"], "quantity": 3 },
+                    { "item_id": plank["id"], "quantity": 1 }
+                ],
+                "gold_cost": 40,
+                "output_item_id": sword["id"]
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{recipe}");
+    (app, ore, plank, recipe)
+}
+
+fn craft_uri(recipe: &Value) -> String {
+    format!("/characters/aria/craft/{}", recipe["id"].as_str().unwrap())
+}
+
+#[tokio::test]
+async fn crafting_consumes_inputs_and_gold() {
+    let (app, ore, plank, recipe) = setup().await;
+    assert_eq!(recipe["output_quantity"], 1);
+    let (status, body) = app
+        .get(&format!("/recipes/{}", recipe["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, recipe);
+
+    // The ore is split over two stacks
+    for _ in 0..4 {
+        app.loot_item("aria", &ore).await;
+    }
+    app.loot_item("aria", &plank).await;
+
+    let (status, crafted) = app.post(&craft_uri(&recipe), json!({})).await;
+    assert_eq!(status, StatusCode::CREATED, "{crafted}");
+    assert_eq!(crafted[0]["item_id"], recipe["output_item_id"]);
+    assert_eq!(app.character_body("aria").await["gold"], 60);
+
+    let (_, items) = app.get("/characters/aria/items").await;
+    let items = items.as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    let ore_left: Vec<&Value> = items
+        .iter()
+        .filter(|instance| instance["item_id"] == ore["id"])
+        .collect();
+    assert_eq!(ore_left.len(), 1);
+    assert_eq!(ore_left[0]["quantity"], 1);
+}
+
+#[tokio::test]
+async fn crafting_lists_missing_ingredients() {
+    let (app, ore, _, recipe) = setup().await;
+    app.loot_item("aria", &ore).await;
+
+    let (status, body) = app.post(&craft_uri(&recipe), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "Missing ingredients : 2 x Iron Ore, 1 x Plank.");
+    assert_eq!(app.character_body("aria").await["gold"], 100);
+    assert_eq!(app.get("/characters/aria/items").await.1[0]["quantity"], 1);
+}
+
+#[tokio::test]
+async fn crafting_is_rolled_back_without_enough_gold() {
+    let (app, ore, plank, recipe) = setup().await;
+    for _ in 0..3 {
+        app.loot_item("aria", &ore).await;
+    }
+    app.loot_item("aria", &plank).await;
+    let (status, _) = app.patch("/characters/aria", json!({ "gold": 10 })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app.post(&craft_uri(&recipe), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        app.get("/characters/aria/items")
+            .await
+            .1
+            .as_array()
+            .unwrap()
+            .len(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn invalid_recipes_are_rejected() {
+    let (app, ore, _, recipe) = setup().await;
+    let output = &recipe["output_item_id"];
+
+    for body in [
+        json!({ "name": "", "inputs": [{ "item_id": ore["id"], "quantity": 1 }], "output_item_id": output }),
+        json!({ "name": "Nothing", "inputs": [], "output_item_id": output }),
+        json!({ "name": "Zero", "inputs": [{ "item_id": ore["id"], "quantity": 0 }], "output_item_id": output }),
+        json!({
+            "name": "Twice",
+            "inputs": [{ "item_id": ore["id"], "quantity": 1 }, { "item_id": ore["id"], "quantity": 1 }],
+            "output_item_id": output
+        }),
+    ] {
+        let (status, _) = app.post("/recipes", body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    }
+
+    let (status, _) = app
+        .post(
+            "/recipes",
+            json!({
+                "name": "Ghost",
+                "inputs": [{ "item_id": ore["id"], "quantity": 1 }],
+                "output_item_id": "00000000-0000-0000-0000-000000000000"
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Deleting the output item deletes its recipes
+    let (status, _) = app
+        .delete(&format!("/items/{}", output.as_str().unwrap()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, recipes) = app.get("/recipes").await;
+    assert_eq!(recipes, json!([]));
+}
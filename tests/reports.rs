@@ -0,0 +1,133 @@
+mod common;
+
+use axum::http::StatusCode;
+use chrono::TimeDelta;
+use common::TestApp;
+use rpg_server::config::Config;
+use serde_json::{Value, json};
+
+// aria sells a sword (base value 40) to borin for 200 gold, with a 10% deposit and a 25% sales tax
+async fn setup() -> (TestApp, Value) {
+    let mut config = Config::default();
+    config.database.replica_path = ":memory:".to_string();
+    config.economy.auction_price = 200;
+    config.economy.auction_deposit_percent = 10;
+    config.economy.auction_tax_percent = 25;
+    let app = TestApp::with_config(config).await;
+    app.create_character("aria", "mage", 50).await;
+    app.create_character("borin", "warrior", 500).await;
+    app.create_character("cato", "ranger", 0).await;
+
+    let (status, sword) = app
+        .post("/items", json!({ "name": "Sword", "base_value": 40 }))
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{sword}");
+    let instance = app.loot_item("aria", &sword).await;
+    let auction = app.create_auction("aria", &instance).await;
+    let buyer = app.character_body("borin").await;
+    let (status, _) = app
+        .post(
+            &format!("/auctions/{}/purchase", auction["id"].as_str().unwrap()),
+            buyer,
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    (app, sword)
+}
+
+#[tokio::test]
+async fn gold_supply_and_richest_characters() {
+    let (app, _) = setup().await;
+
+    let (status, supply) = app.get("/admin/economy/gold").await;
+    assert_eq!(status, StatusCode::OK);
+    // aria 200, borin 300, cato 0
+    assert_eq!(supply["characters"], 3);
+    assert_eq!(supply["total_gold"], 500);
+    assert_eq!(supply["held_in_deposits"], 0);
+    assert_eq!(supply["reserved_gold"], 0);
+    assert_eq!(
+        supply["percentiles"],
+        json!({ "min": 0, "p10": 0, "p25": 0, "p50": 200, "p75": 300, "p90": 300, "p99": 300, "max": 300 })
+    );
+
+    let (status, richest) = app.get("/admin/economy/richest?limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        richest,
+        json!([
+            { "name": "borin", "level": 1, "gold": 300, "item_value": 40, "net_worth": 340 },
+            { "name": "aria", "level": 1, "gold": 200, "item_value": 0, "net_worth": 200 }
+        ])
+    );
+
+    let (status, _) = app.get("/admin/economy/richest?limit=many").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn auction_volume_and_price_trends() {
+    let (app, sword) = setup().await;
+
+    let (status, volume) = app.get("/admin/economy/auctions").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        volume,
+        json!([{ "day": "2025-01-01", "listed": 1, "sold": 1, "gold_volume": 200 }])
+    );
+
+    let (status, trends) = app
+        .get(&format!(
+            "/admin/economy/prices?item_id={}",
+            sword["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        trends,
+        json!([{
+            "item_id": sword["id"],
+            "item_name": "Sword",
+            "day": "2025-01-01",
+            "sales": 1,
+            "average_price": 200.0,
+            "min_price": 200,
+            "max_price": 200
+        }])
+    );
+
+    // The sale falls out of a one day window
+    app.clock.advance(TimeDelta::days(2));
+    assert_eq!(app.get("/admin/economy/auctions?days=1").await.1, json!([]));
+    assert_eq!(app.get("/admin/economy/prices?days=1").await.1, json!([]));
+
+    let (status, _) = app.get("/admin/economy/auctions?days=-1").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn gold_flows_account_for_the_gold_supply() {
+    let (app, _) = setup().await;
+    let (status, _) = app.patch("/characters/cato", json!({ "gold": 10 })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.delete("/characters/cato").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, flows) = app.get("/admin/economy/flows").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        flows,
+        json!({
+            "created": 560,
+            "destroyed": 60,
+            "net": 500,
+            "sources": [
+                { "source": "character_creation", "created": 550, "destroyed": 0 },
+                { "source": "admin_adjustment", "created": 10, "destroyed": 0 },
+                { "source": "character_deletion", "created": 0, "destroyed": 10 },
+                { "source": "auction_tax", "created": 0, "destroyed": 50 }
+            ]
+        })
+    );
+    assert_eq!(app.get("/admin/economy/gold").await.1["total_gold"], 500);
+}
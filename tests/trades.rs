@@ -0,0 +1,240 @@
+mod common;
+
+use axum::http::StatusCode;
+use chrono::TimeDelta;
+use common::TestApp;
+use rpg_server::{clock::Clock, handlers::trades::expire_trades_libsql_query};
+use serde_json::{Value, json};
+
+// aria (mage, 100 gold) holds a sword, brom (warrior, 50 gold) holds a shield
+async fn setup() -> (TestApp, Value, Value) {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 100).await;
+    app.create_character("brom", "warrior", 50).await;
+    let sword = app.create_item("Sword").await;
+    let shield = app.create_item("Shield").await;
+    let sword = app.loot_item("aria", &sword).await;
+    let shield = app.loot_item("brom", &shield).await;
+    (app, sword, shield)
+}
+
+async fn propose(app: &TestApp, body: Value) -> Value {
+    let (status, trade) = app.post("/trades", body).await;
+    assert_eq!(status, StatusCode::CREATED, "{trade}");
+    trade
+}
+
+fn trade_uri(trade: &Value, action: &str) -> String {
+    format!("/trades/{}/{action}", trade["id"].as_str().unwrap())
+}
+
+#[tokio::test]
+async fn accepted_trades_swap_items_and_gold() {
+    let (app, sword, shield) = setup().await;
+    let trade = propose(
+        &app,
+        json!({
+            "proposer_name": "aria",
+            "recipient_name": "brom",
+            "proposer_items": [sword["id"]],
+            "recipient_items": [shield["id"]],
+            "recipient_gold": 30
+        }),
+    )
+    .await;
+    assert_eq!(trade["status"], "pending");
+    assert_eq!(trade["proposer_accepted"], false);
+
+    let (status, body) = app
+        .post(&trade_uri(&trade, "accept"), json!({ "name": "aria" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["proposer_accepted"], true);
+    assert_eq!(body["status"], "pending");
+    // Nothing moves until both accepted
+    assert_eq!(
+        app.get("/characters/aria/items").await.1[0]["id"],
+        sword["id"]
+    );
+
+    let (status, body) = app
+        .post(&trade_uri(&trade, "accept"), json!({ "name": "brom" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "completed");
+
+    assert_eq!(
+        app.get("/characters/aria/items").await.1[0]["id"],
+        shield["id"]
+    );
+    assert_eq!(
+        app.get("/characters/brom/items").await.1[0]["id"],
+        sword["id"]
+    );
+    assert_eq!(app.character_body("aria").await["gold"], 130);
+    assert_eq!(app.character_body("brom").await["gold"], 20);
+
+    let (status, body) = app
+        .post(&trade_uri(&trade, "accept"), json!({ "name": "brom" }))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body, "This trade is not pending.");
+    let (_, body) = app.get("/trades?status=completed&character=brom").await;
+    assert_eq!(body.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn failed_swaps_leave_everything_in_place() {
+    let (app, sword, _) = setup().await;
+    let trade = propose(
+        &app,
+        json!({ "proposer_name": "aria", "recipient_name": "brom", "proposer_items": [sword["id"]], "recipient_gold": 50 }),
+    )
+    .await;
+    app.patch("/characters/brom", json!({ "gold": 10 })).await;
+
+    app.post(&trade_uri(&trade, "accept"), json!({ "name": "aria" }))
+        .await;
+    let (status, body) = app
+        .post(&trade_uri(&trade, "accept"), json!({ "name": "brom" }))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body, "The buyer does not have enough gold.");
+
+    assert_eq!(
+        app.get("/characters/aria/items").await.1[0]["id"],
+        sword["id"]
+    );
+    assert_eq!(app.character_body("aria").await["gold"], 100);
+    let (_, body) = app
+        .get(&format!("/trades/{}", trade["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(body["status"], "pending");
+    assert_eq!(body["recipient_accepted"], false);
+}
+
+#[tokio::test]
+async fn invalid_proposals_are_rejected() {
+    let (app, sword, shield) = setup().await;
+    for (body, status, message) in [
+        (
+            json!({ "proposer_name": "aria", "recipient_name": "aria", "proposer_gold": 1 }),
+            StatusCode::BAD_REQUEST,
+            "A character cannot trade with itself.",
+        ),
+        (
+            json!({ "proposer_name": "aria", "recipient_name": "brom" }),
+            StatusCode::BAD_REQUEST,
+            "The trade is empty.",
+        ),
+        (
+            json!({ "proposer_name": "aria", "recipient_name": "brom", "proposer_items": [sword["id"], sword["id"]] }),
+            StatusCode::BAD_REQUEST,
+            "An item instance is offered twice.",
+        ),
+        (
+            json!({ "proposer_name": "aria", "recipient_name": "brom", "proposer_items": [shield["id"]] }),
+            StatusCode::NOT_FOUND,
+            "This item instance does not exist.",
+        ),
+        (
+            json!({ "proposer_name": "aria", "recipient_name": "nobody", "proposer_gold": 1 }),
+            StatusCode::NOT_FOUND,
+            "This character does not exist.",
+        ),
+        (
+            json!({ "proposer_name": "aria", "recipient_name": "brom", "proposer_gold": 101 }),
+            StatusCode::FORBIDDEN,
+            "The buyer does not have enough gold.",
+        ),
+    ] {
+        let (actual_status, response) = app.post("/trades", body.clone()).await;
+        assert_eq!(actual_status, status, "{body}");
+        assert_eq!(response, message);
+    }
+    assert_eq!(app.get("/trades").await.1, json!([]));
+}
+
+#[tokio::test]
+async fn trades_can_be_cancelled_by_their_parties_and_expire() {
+    let (app, sword, _) = setup().await;
+    let body = json!({ "proposer_name": "aria", "recipient_name": "brom", "proposer_items": [sword["id"]] });
+    let trade = propose(&app, body.clone()).await;
+
+    let (status, response) = app
+        .post(&trade_uri(&trade, "cancel"), json!({ "name": "cyra" }))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(response, "This character is not part of the trade.");
+    let (status, response) = app
+        .post(&trade_uri(&trade, "cancel"), json!({ "name": "brom" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["status"], "cancelled");
+
+    let trade = propose(&app, body).await;
+    app.clock.advance(TimeDelta::seconds(301));
+    let (status, _) = app
+        .post(&trade_uri(&trade, "accept"), json!({ "name": "brom" }))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let expired = expire_trades_libsql_query(&app.state, app.clock.now())
+        .await
+        .unwrap();
+    assert_eq!(expired, 1);
+    let (_, body) = app.get("/trades?status=expired").await;
+    assert_eq!(body[0]["id"], trade["id"]);
+
+    let (status, _) = app
+        .get("/trades/00000000-0000-0000-0000-000000000000")
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn accepting_reserves_the_offered_gold_until_the_trade_ends() {
+    let (app, sword, _) = setup().await;
+    let body = json!({ "proposer_name": "aria", "recipient_name": "brom", "proposer_items": [sword["id"]], "recipient_gold": 30 });
+    let trade = propose(&app, body.clone()).await;
+
+    for _ in 0..2 {
+        let (status, _) = app
+            .post(&trade_uri(&trade, "accept"), json!({ "name": "brom" }))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let brom = app.character_body("brom").await;
+    assert_eq!(brom["gold"], 20);
+    assert_eq!(brom["reserved_gold"], 30);
+
+    // The reserved gold cannot be offered again
+    let (status, _) = app
+        .post(
+            "/buy-orders",
+            json!({ "character_name": "brom", "item_id": sword["item_id"], "max_price": 25 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app
+        .post(&trade_uri(&trade, "cancel"), json!({ "name": "aria" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let brom = app.character_body("brom").await;
+    assert_eq!(brom["gold"], 50);
+    assert_eq!(brom["reserved_gold"], 0);
+
+    let trade = propose(&app, body).await;
+    let (status, _) = app
+        .post(&trade_uri(&trade, "accept"), json!({ "name": "brom" }))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.character_body("brom").await["reserved_gold"], 30);
+    app.clock.advance(TimeDelta::seconds(301));
+    expire_trades_libsql_query(&app.state, app.clock.now())
+        .await
+        .unwrap();
+    let brom = app.character_body("brom").await;
+    assert_eq!(brom["gold"], 50);
+    assert_eq!(brom["reserved_gold"], 0);
+}
@@ -0,0 +1,281 @@
+mod common;
+
+use axum::http::StatusCode;
+use chrono::TimeDelta;
+use common::TestApp;
+use rpg_server::{clock::Clock, handlers::vendors::restock_vendors_libsql_query};
+use serde_json::{Value, json};
+
+// aria (warrior, 100 gold) next to a vendor selling potions (base value 10) and a limited stock
+// of swords (base value 40, 2 in stock, restocked every minute)
+async fn setup() -> (TestApp, Value, Value, Value) {
+    let app = TestApp::new().await;
+    app.create_character("aria", "warrior", 100).await;
+    let (status, potion) = app
+        .post(
+            "/items",
+            json!({ "name": "Potion", "kind": "consumable", "max_stack": 20, "base_value": 10 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{potion}");
+    let (status, sword) = app
+        .post(
+            "/items",
+            json!({ "name": "Sword", "kind": "weapon", "base_value": 40 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{sword}");
+    let (status, vendor) = app
+        .post(
+            "/vendors",
+            json!({
+                "name": "Smith",
+                "offers": [
+                    { "item_id": potion["id"], "price": 15 },
+                    { "item_id": sword["id"], "stock": 2, "restock_interval_secs": 60 }
+                ]
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{vendor}");
+    (app, potion, sword, vendor)
+}
+
+fn vendor_uri(vendor: &Value, action: &str) -> String {
+    format!("/vendors/{}/{action}", vendor["id"].as_str().unwrap())
+}
+
+#[tokio::test]
+async fn vendors_list_their_offers() {
+    let (app, _, _, vendor) = setup().await;
+
+    let (status, vendors) = app.get("/vendors").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(vendors, json!([vendor]));
+
+    let offers = &vendor["offers"];
+    assert_eq!(offers[0]["item_name"], "Potion");
+    assert_eq!(offers[0]["price"], 15);
+    assert_eq!(offers[0]["sell_back_price"], 2);
+    assert_eq!(offers[0]["stock"], Value::Null);
+    // The price defaults to the base value
+    assert_eq!(offers[1]["price"], 40);
+    assert_eq!(offers[1]["sell_back_price"], 10);
+    assert_eq!(offers[1]["stock"], 2);
+    assert_eq!(offers[1]["max_stock"], 2);
+}
+
+#[tokio::test]
+async fn buying_and_selling_moves_gold_and_items() {
+    let (app, potion, _, vendor) = setup().await;
+
+    let (status, receipt) = app
+        .post(
+            &vendor_uri(&vendor, "buy"),
+            json!({ "character_name": "aria", "item_id": potion["id"], "quantity": 4 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{receipt}");
+    assert_eq!(receipt["gold"], 60);
+    assert_eq!(receipt["instances"][0]["quantity"], 4);
+    assert_eq!(app.character_body("aria").await["gold"], 40);
+
+    let (status, receipt) = app
+        .post(
+            &vendor_uri(&vendor, "sell"),
+            json!({ "character_name": "aria", "instance_id": receipt["instances"][0]["id"], "quantity": 3 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{receipt}");
+    assert_eq!(receipt["gold"], 6);
+    assert_eq!(receipt["instances"][0]["quantity"], 1);
+    assert_eq!(app.character_body("aria").await["gold"], 46);
+
+    let (status, _) = app
+        .post(
+            &vendor_uri(&vendor, "buy"),
+            json!({ "character_name": "aria", "item_id": potion["id"], "quantity": 4 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(app.character_body("aria").await["gold"], 46);
+}
+
+#[tokio::test]
+async fn limited_stock_runs_out_and_restocks() {
+    let (app, _, sword, vendor) = setup().await;
+    let (status, _) = app.patch("/characters/aria", json!({ "gold": 1000 })).await;
+    assert_eq!(status, StatusCode::OK);
+    let buy = json!({ "character_name": "aria", "item_id": sword["id"], "quantity": 2 });
+
+    let (status, receipt) = app.post(&vendor_uri(&vendor, "buy"), buy.clone()).await;
+    assert_eq!(status, StatusCode::CREATED, "{receipt}");
+    assert_eq!(receipt["instances"].as_array().unwrap().len(), 2);
+
+    let (status, _) = app.post(&vendor_uri(&vendor, "buy"), buy.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(app.character_body("aria").await["gold"], 920);
+
+    app.clock.advance(TimeDelta::seconds(61));
+    let restocked = restock_vendors_libsql_query(&app.state, app.clock.now())
+        .await
+        .unwrap();
+    assert_eq!(restocked, 1);
+    let (_, body) = app
+        .get(&format!("/vendors/{}", vendor["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(body["offers"][1]["stock"], 2);
+
+    let (status, _) = app.post(&vendor_uri(&vendor, "buy"), buy).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn invalid_vendor_operations_are_rejected() {
+    let (app, potion, sword, vendor) = setup().await;
+
+    for body in [
+        json!({ "name": "" }),
+        json!({ "name": "Twice", "offers": [{ "item_id": potion["id"] }, { "item_id": potion["id"] }] }),
+        json!({ "name": "Empty", "offers": [{ "item_id": potion["id"], "stock": 0 }] }),
+        json!({ "name": "Hoard", "offers": [{ "item_id": potion["id"], "stock": u64::MAX }] }),
+        json!({ "name": "Endless", "offers": [{ "item_id": potion["id"], "restock_interval_secs": 60 }] }),
+        json!({ "name": "Sleepy", "offers": [{ "item_id": potion["id"], "stock": 1, "restock_interval_secs": u64::MAX }] }),
+        // Sold back for 10
+        json!({ "name": "Cheap", "offers": [{ "item_id": sword["id"], "price": 9 }] }),
+    ] {
+        let (status, _) = app.post("/vendors", body.clone()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    }
+    let (status, _) = app
+        .post(
+            "/vendors",
+            json!({ "name": "Fair", "offers": [{ "item_id": sword["id"], "price": 10 }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = app
+        .post(
+            &vendor_uri(&vendor, "buy"),
+            json!({ "character_name": "aria", "item_id": potion["id"], "quantity": 0 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Equipped items cannot be sold
+    let instance = app.loot_item("aria", &sword).await;
+    let (status, _) = app
+        .post(
+            "/characters/aria/equipment/main_hand",
+            json!({ "instance_id": instance["id"] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .post(
+            &vendor_uri(&vendor, "sell"),
+            json!({ "character_name": "aria", "instance_id": instance["id"] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app
+        .delete(&format!("/vendors/{}", vendor["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .post(
+            &vendor_uri(&vendor, "buy"),
+            json!({ "character_name": "aria", "item_id": potion["id"] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn overflowing_gold_amounts_are_rejected() {
+    let (app, _, _, _) = setup().await;
+    let (status, crown) = app
+        .post(
+            "/items",
+            json!({ "name": "Crown", "max_stack": 20, "base_value": 4_611_686_018_427_387_904u64 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{crown}");
+    let (status, vendor) = app
+        .post(
+            "/vendors",
+            json!({ "name": "Jeweller", "offers": [{ "item_id": crown["id"] }] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{vendor}");
+
+    let (status, body) = app
+        .post(
+            &vendor_uri(&vendor, "buy"),
+            json!({ "character_name": "aria", "item_id": crown["id"], "quantity": 2 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body, "The purchase is too large.");
+
+    assert_eq!(
+        vendor["offers"][0]["sell_back_price"],
+        1_152_921_504_606_846_976u64
+    );
+
+    // 8 crowns sell back for 2^63 gold
+    let mut instance = Value::Null;
+    for _ in 0..8 {
+        instance = app.loot_item("aria", &crown).await;
+    }
+    assert_eq!(instance["quantity"], 8);
+    let (status, body) = app
+        .post(
+            &vendor_uri(&vendor, "sell"),
+            json!({ "character_name": "aria", "instance_id": instance["id"], "quantity": 8 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body, "The sale is too large.");
+    assert_eq!(app.character_body("aria").await["gold"], 100);
+    assert_eq!(
+        app.get("/characters/aria/items").await.1[0]["id"],
+        instance["id"]
+    );
+}
+
+// Raising the base value after the potion was offered for 15 doesn't make selling back profitable
+#[tokio::test]
+async fn selling_back_never_pays_more_than_the_cheapest_offer() {
+    let (app, potion, _, vendor) = setup().await;
+    let (status, _) = app
+        .patch(
+            &format!("/items/{}", potion["id"].as_str().unwrap()),
+            json!({ "base_value": 1000 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = app
+        .get(&format!("/vendors/{}", vendor["id"].as_str().unwrap()))
+        .await;
+    assert_eq!(body["offers"][0]["sell_back_price"], 15);
+
+    let (status, receipt) = app
+        .post(
+            &vendor_uri(&vendor, "buy"),
+            json!({ "character_name": "aria", "item_id": potion["id"] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{receipt}");
+    let (status, receipt) = app
+        .post(
+            &vendor_uri(&vendor, "sell"),
+            json!({ "character_name": "aria", "instance_id": receipt["instances"][0]["id"] }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{receipt}");
+    assert_eq!(receipt["gold"], 15);
+    assert_eq!(app.character_body("aria").await["gold"], 100);
+}
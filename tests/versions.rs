@@ -0,0 +1,90 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use serde_json::json;
+
+#[tokio::test]
+async fn v1_and_the_root_alias_are_deprecated() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+
+    for uri in ["/v1/characters/aria", "/characters/aria"] {
+        let (status, headers, body) = app.request_with_headers(Method::GET, uri, None).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert_eq!(body["name"], "aria");
+        assert_eq!(headers["deprecation"], "@1792281600");
+        assert_eq!(headers["sunset"], "Sun, 18 Apr 2027 00:00:00 GMT");
+        assert_eq!(headers["link"], "</v2>; rel=\"successor-version\"");
+    }
+
+    // Errors are deprecated too
+    let (status, headers, _) = app
+        .request_with_headers(Method::GET, "/v1/characters/ghost", None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(headers.contains_key("deprecation"));
+}
+
+#[tokio::test]
+async fn v2_and_operations_are_not_deprecated() {
+    let app = TestApp::new().await;
+
+    for uri in ["/v2/characters", "/healthz", "/openapi.json"] {
+        let (status, headers, _) = app.request_with_headers(Method::GET, uri, None).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert!(!headers.contains_key("deprecation"), "{uri}");
+        assert!(!headers.contains_key("sunset"), "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn versions_share_the_same_data() {
+    let app = TestApp::new().await;
+    let (status, _) = app
+        .post(
+            "/v2/characters",
+            json!({ "name": "aria", "class": "mage", "gold": 150 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    assert_eq!(app.get("/v1/characters/aria").await.1["gold"], 150);
+    assert_eq!(app.get("/characters/aria").await.1["gold"], 150);
+}
+
+#[tokio::test]
+async fn v2_returns_the_auction_of_a_character() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+    let auction = app.create_auction("aria", &instance).await;
+
+    let (status, body) = app
+        .get(&format!(
+            "/v2/characters/aria/auctions/{}",
+            auction["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, auction);
+}
+
+#[tokio::test]
+async fn v1_returns_the_item_of_a_character_auction() {
+    let app = TestApp::new().await;
+    app.create_character("aria", "mage", 150).await;
+    let item = app.create_item("Iron Sword").await;
+    let instance = app.loot_item("aria", &item).await;
+    let auction = app.create_auction("aria", &instance).await;
+
+    let (status, body) = app
+        .get(&format!(
+            "/v1/characters/aria/auctions/{}",
+            auction["id"].as_str().unwrap()
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, item);
+}